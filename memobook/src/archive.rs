@@ -0,0 +1,303 @@
+//  archive.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+/********************************************
+   Archive members: gzip, zip and tar (plain
+    or gzipped) files are opened so their
+    members can be searched and bookmarked.
+    A member is addressed as
+    archive.zip!/path/inside.txt
+*********************************************/
+
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use flate2::read::GzDecoder;
+use crate::mberror::MBError;
+
+
+pub const MEMBER_SEPARATOR: &str = "!/";
+
+/// Members larger than this are skipped
+pub const MAX_MEMBER_SIZE: u64 = 16 * 1024 * 1024;
+
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ArchiveKind {
+    Gzip,
+    Zip,
+    Tar,
+    TarGzip
+}
+
+
+impl ArchiveKind {
+
+    pub fn of(path: &Path) -> Option<ArchiveKind> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGzip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".gz") {
+            Some(ArchiveKind::Gzip)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+
+}
+
+
+/// Split "archive!/member" into its archive and member parts
+pub fn split_member(path: &str) -> Option<(&str, &str)> {
+    let (archive, member) = path.split_once(MEMBER_SEPARATOR)?;
+    if archive.is_empty() || member.is_empty() {
+        None
+    } else {
+        Some((archive, member))
+    }
+}
+
+
+pub fn member_path(archive: &str, member: &str) -> String {
+    format!("{archive}{MEMBER_SEPARATOR}{}", member.trim_start_matches('/'))
+}
+
+
+fn archive_error<E: std::fmt::Display>(archive: &Path, e: E) -> MBError {
+    MBError::FileSys(format!("cannot read archive {}: {e}", archive.display()))
+}
+
+
+fn read_limited<R: Read>(reader: R) -> Option<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::new();
+    reader.take(MAX_MEMBER_SIZE + 1).read_to_end(&mut data).ok()?;
+    if data.len() as u64 > MAX_MEMBER_SIZE {
+        None
+    } else {
+        Some(data)
+    }
+}
+
+
+// Each visit gets a member's name and content; one member is held in memory at a time
+type Visit<'a> = dyn FnMut(String, Vec<u8>) -> Result<(), MBError> + 'a;
+
+
+fn tar_members<R: Read>(reader: R, archive: &Path, wanted: Option<&str>, visit: &mut Visit) -> Result<(), MBError> {
+    let mut tarchive = tar::Archive::new(reader);
+    for entry in tarchive.entries().map_err(|e| archive_error(archive, e))? {
+        let entry = entry.map_err(|e| archive_error(archive, e))?;
+        if !entry.header().entry_type().is_file() || entry.size() > MAX_MEMBER_SIZE {
+            continue;
+        }
+        let name: String = match entry.path() {
+            Ok(p) => p.to_string_lossy().trim_start_matches("./").to_string(),
+            Err(_) => continue
+        };
+        if wanted.is_some_and(|w| w != name) {
+            continue;
+        }
+        if let Some(data) = read_limited(entry) {
+            visit(name, data)?;
+            if wanted.is_some() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+
+fn zip_members(file: File, archive: &Path, wanted: Option<&str>, visit: &mut Visit) -> Result<(), MBError> {
+    let mut zarchive = zip::ZipArchive::new(file).map_err(|e| archive_error(archive, e))?;
+    for i in 0..zarchive.len() {
+        let entry = match zarchive.by_index(i) {
+            Ok(e) => e,
+            Err(_) => continue
+        };
+        if !entry.is_file() || entry.size() > MAX_MEMBER_SIZE {
+            continue;
+        }
+        let name: String = entry.name().to_string();
+        if wanted.is_some_and(|w| w != name) {
+            continue;
+        }
+        if let Some(data) = read_limited(entry) {
+            visit(name, data)?;
+            if wanted.is_some() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+
+fn visit_members(archive: &Path, wanted: Option<&str>, visit: &mut Visit) -> Result<(), MBError> {
+    let Some(kind) = ArchiveKind::of(archive) else {
+        return Err(MBError::FileSys(format!("{} is not a supported archive", archive.display())));
+    };
+    let file = File::open(archive).map_err(|e| archive_error(archive, e))?;
+    match kind {
+        ArchiveKind::Gzip => {
+            // a lone gzip holds one member, named for the file without its .gz
+            let name: String = archive.file_stem().and_then(|s| s.to_str()).unwrap_or("").to_string();
+            if wanted.is_some_and(|w| w != name) {
+                return Ok(());
+            }
+            match read_limited(GzDecoder::new(file)) {
+                Some(data) => visit(name, data),
+                None => Ok(())
+            }
+        },
+        ArchiveKind::Tar => tar_members(file, archive, wanted, visit),
+        ArchiveKind::TarGzip => tar_members(GzDecoder::new(file), archive, wanted, visit),
+        ArchiveKind::Zip => zip_members(file, archive, wanted, visit)
+    }
+}
+
+
+/// Calls f with each regular member of an archive and its content, one member at a time,
+/// stopping at the first error f returns. Nested archives are not opened.
+pub fn for_each_member<F>(archive: &Path, mut f: F) -> Result<(), MBError>
+where F: FnMut(String, Vec<u8>) -> Result<(), MBError>
+{
+    visit_members(archive, None, &mut f)
+}
+
+
+/// Content of a member given as "archive!/member"
+pub fn read_member(path: &str) -> Result<Vec<u8>, MBError> {
+    let Some((archive, member)) = split_member(path) else {
+        return Err(MBError::FileSys(format!("{path} is not an archive member path")));
+    };
+    let mut found: Option<Vec<u8>> = None;
+    visit_members(Path::new(archive), Some(member), &mut |_, data| {
+        found = Some(data);
+        Ok(())
+    })?;
+    found.ok_or_else(|| MBError::FileSys(format!("no member {member} in {archive}")))
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("memobook_test_archive_{}_{name}", std::process::id()))
+    }
+
+    fn members(archive: &Path) -> Result<Vec<(String, Vec<u8>)>, MBError> {
+        let mut found: Vec<(String, Vec<u8>)> = vec![];
+        for_each_member(archive, |name, data| {
+            found.push((name, data));
+            Ok(())
+        })?;
+        Ok(found)
+    }
+
+    #[test]
+    fn test_split_member() {
+        assert_eq!(split_member("/a/b.zip!/notes/c.txt"), Some(("/a/b.zip", "notes/c.txt")));
+        assert_eq!(split_member("/a/b.zip"), None);
+        assert_eq!(split_member("!/c.txt"), None);
+        assert_eq!(member_path("/a/b.zip", "/c.txt"), "/a/b.zip!/c.txt");
+        assert_eq!(ArchiveKind::of(Path::new("x.TAR.GZ")), Some(ArchiveKind::TarGzip));
+        assert_eq!(ArchiveKind::of(Path::new("x.log.gz")), Some(ArchiveKind::Gzip));
+        assert_eq!(ArchiveKind::of(Path::new("x.txt")), None);
+    }
+
+    #[test]
+    fn test_tar_gz_members() {
+        let path = temp_path("notes.tgz");
+        {
+            let gz = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+            let mut builder = tar::Builder::new(gz);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(9);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, "dir/a.txt", &b"raycaster"[..]).unwrap();
+            builder.into_inner().unwrap().finish().unwrap();
+        }
+        let found = members(&path).unwrap();
+        assert_eq!(found, vec![("dir/a.txt".to_string(), b"raycaster".to_vec())]);
+        let member = member_path(path.to_str().unwrap(), "dir/a.txt");
+        assert_eq!(read_member(&member).unwrap(), b"raycaster".to_vec());
+        assert!(read_member(&member_path(path.to_str().unwrap(), "dir/b.txt")).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_gzip_member() {
+        let path = temp_path("server.log.gz");
+        {
+            let mut gz = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+            gz.write_all(b"segfault at 0x0").unwrap();
+            gz.finish().unwrap();
+        }
+        let name = format!("memobook_test_archive_{}_server.log", std::process::id());
+        assert_eq!(members(&path).unwrap(), vec![(name.to_string(), b"segfault at 0x0".to_vec())]);
+        assert!(read_member(&member_path(path.to_str().unwrap(), &name)).is_ok());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_member_visit_stops() {
+        let path = temp_path("stops.tar");
+        {
+            let mut builder = tar::Builder::new(File::create(&path).unwrap());
+            for name in ["a.txt", "b.txt", "c.txt"] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(1);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append_data(&mut header, name, &b"x"[..]).unwrap();
+            }
+            builder.finish().unwrap();
+        }
+        let mut seen: Vec<String> = vec![];
+        let visited = for_each_member(&path, |name, _| {
+            seen.push(name);
+            if seen.len() == 2 { Err(MBError::Cancelled("enough".to_string())) } else { Ok(()) }
+        });
+        assert!(matches!(visited, Err(MBError::Cancelled(_))));
+        assert_eq!(seen, vec!["a.txt".to_string(), "b.txt".to_string()]);
+        assert_eq!(read_member(&member_path(path.to_str().unwrap(), "c.txt")).unwrap(), b"x".to_vec());
+        let _ = std::fs::remove_file(&path);
+    }
+
+}
@@ -48,6 +48,11 @@ use std::fs;
 use json::object;
 use std::collections::HashMap as HashMap;
 use crate::mimer::Mimer as Mimer;
+use crate::savedsearch::SavedSearches;
+use crate::query::Query;
+use crate::mbfilter::MBFilter;
+use crate::magic::{DetectMode, MagicTable, Sniffer};
+use crate::sharedmime::{SharedMime, DEFAULT_SHARED_MIME};
 
 
 
@@ -67,7 +72,10 @@ pub struct MBInfo
     pub src: String,      //path of db file
     pub table: String,    //name of table in db
     pub scan: Repository, //container for search directories
-    pub alt: bool        //flag for: needs backed up
+    pub alt: bool,        //flag for: needs backed up
+    pub deftype: String,  //type for added files whose type cannot be inferred, may be empty
+    pub sniff: Sniffer,   //suffix and/or magic-number type detection
+    pub archives: bool    //flag for: grep descends into gzip, zip and tar files
 }
 
 
@@ -79,11 +87,22 @@ where M: Backer + BackerParserJSON + std::marker::Send
     mb: MBInfo,
     back: Option<M>,           //the backups object
     mime: HashMap<String,Mimer>,
+    shared: Option<SharedMime>,  //types generated from shared-mime-info, if enabled
+    saved: SavedSearches,
     holdover: Vec<(String,json::JsonValue)>,
     changed: bool
 }
 
 
+// The parts of a configuration read from its file besides the database section
+struct Loaded {
+    mime: HashMap<String,Mimer>,
+    shared: Option<SharedMime>,
+    saved: SavedSearches,
+    holdover: Vec<(String,json::JsonValue)>
+}
+
+
 /*enum TrunkType {
     TRUNKTRIM,
     TRUNKREADY,
@@ -99,11 +118,11 @@ where
     fn new(path: String,
             mb: MBInfo,
             back: Option<M>, 
-            mime: HashMap<String,Mimer>, 
-            holdover: Vec<(String, json::JsonValue)>, 
+            loaded: Loaded,
             changed: bool) -> Configuration<M> {
+        let Loaded { mime, shared, saved, holdover } = loaded;
         Configuration {
-            path, mb, back, mime, holdover, changed
+            path, mb, back, mime, shared, saved, holdover, changed
         }
     }
     
@@ -181,6 +200,17 @@ where
             backup.read(&rawjson)?;
         }
 
+        // Prepare the type detection
+        let detect: DetectMode = match &rawjson["database"]["detect"] {
+            json::JsonValue::String(x) => DetectMode::from_name(x),
+            json::JsonValue::Short(x) => DetectMode::from_name(x),
+            json::JsonValue::Null => Some(DetectMode::Suffix),
+            _ => None
+        }.ok_or("Parse error on type detection mode".to_string())?;
+        let mut magic: MagicTable = MagicTable::new();
+        magic.read(&rawjson["magic"])?;
+        processed.insert("magic", true);
+
         // Prepare the "memobook" info
         let membook = MBInfo { 
             src: match &rawjson["database"]["src"] {
@@ -197,12 +227,41 @@ where
             alt: match &rawjson["database"]["alt"] {
                 json::JsonValue::Boolean(x) => *x,
                 _ => true
+            },
+            deftype: match &rawjson["database"]["deftype"] {
+                json::JsonValue::String(x) => x.to_owned(),
+                json::JsonValue::Short(x) => x.to_string(),
+                _ => "".to_string()
+            },
+            sniff: Sniffer::new(detect, magic),
+            archives: match &rawjson["database"]["archives"] {
+                json::JsonValue::Boolean(x) => *x,
+                _ => false
             }
         };
         processed.insert("database", true);
 
+        // Generate mime types from shared-mime-info, if asked; conf.json types override these
+        let mut shared: Option<SharedMime> = match &rawjson["database"]["sharedmime"] {
+            json::JsonValue::Boolean(true) => Some(SharedMime::load(DEFAULT_SHARED_MIME)?),
+            json::JsonValue::String(x) => Some(SharedMime::load(x)?),
+            json::JsonValue::Short(x) => Some(SharedMime::load(x)?),
+            json::JsonValue::Boolean(false) | json::JsonValue::Null => None,
+            _ => { return Err("Parse error on shared mime setting".to_string()); }
+        };
+
+        // Generated types the user removed or renamed stay gone
+        if let (Some(sm), json::JsonValue::Array(names)) = (shared.as_mut(), &rawjson["database"]["sharedmime_removed"]) {
+            for name in names.iter().filter_map(|n| n.as_str()) {
+                sm.remove(name);
+            }
+        }
+
         // Read in the mime types
-        mimemap = HashMap::new();
+        mimemap = match &shared {
+            Some(sm) => sm.types(),
+            None => HashMap::new()
+        };
         if !rawjson["mime"].is_null() && rawjson["mime"].is_array() && !rawjson["mime"].is_empty() {
             if let json::JsonValue::Array(types) = &rawjson["mime"] {
                 for vecitem in types {
@@ -223,13 +282,19 @@ where
         }
         processed.insert("mime", true);
 
+        // Read in the saved searches
+        let mut saved: SavedSearches = SavedSearches::new();
+        saved.read(&rawjson)?;
+        processed.insert("saved", true);
+
         // Collect and store unused json objects for later output
        let holds: Vec<(String, json::JsonValue)> = rawjson.entries()
             .filter_map(|(nm, jv)| if !processed.contains_key(nm) { Some((nm.to_string(), jv.to_owned())) } else { None })
             .collect();
 
         // Return the configuration object
-        Ok(Configuration::new(path.to_string(), membook, back, mimemap, holds, false))
+        let loaded = Loaded { mime: mimemap, shared, saved, holdover: holds };
+        Ok(Configuration::new(path.to_string(), membook, back, loaded, false))
 
     }
 
@@ -268,6 +333,17 @@ where
     }
 
 
+    pub fn assemble_mime_info(&self) -> String {
+        let mut jmime = json::JsonValue::new_object();
+        let mut names: Vec<&String> = self.mime.keys().collect();
+        names.sort();
+        for name in names {
+            let _ = jmime.insert(name, self.mime.get(name).unwrap().iter().collect::<Vec<&str>>());
+        }
+        jmime.dump()
+    }
+
+
     pub fn assemble_backup_info(&self) -> String {
         let jback = match &self.back {
             Some(bu) => match bu.write() {
@@ -407,21 +483,24 @@ where
     }
 
 
-    pub fn check_backup(&mut self, auto: bool) {
+    /// Make a backup if one is due; true if a backup was made
+    pub fn check_backup(&mut self, auto: bool) -> bool {
         let Some(bu) = self.back.as_mut() else {
-            return;
+            return false;
         };
         if bu.check() && (auto || self.mb.alt || self.changed) {
             match bu.make(&self.mb.src, &[self.path.as_str()]) {
                 Ok(_) => { 
                     self.changed = true;
                     self.mb.alt = false;
+                    return true;
                 },
                 Err(e) => { 
                     println!("Backup error: {e}"); 
                 }
             }
         }
+        false
     }
 
     
@@ -470,6 +549,112 @@ where
     }
 
 
+    pub fn saved(&self) -> &SavedSearches {
+        &self.saved
+    }
+
+
+    /// Store a named search. With replace false, an existing search of the same name is an error;
+    /// with replace true, a missing search of that name is an error.
+    pub fn save_search(&mut self, name: &str, query: Query<MBFilter>, replace: bool) -> Result<(), String> {
+        if name.is_empty() {
+            return Err("saved search name cannot be empty".to_string());
+        }
+        match (self.saved.contains(name), replace) {
+            (true, false) => { return Err(format!("saved search [{name}] already exists")); },
+            (false, true) => { return Err(format!("saved search [{name}] does not exist")); },
+            _ => {}
+        }
+        self.saved.insert(name, query);
+        self.changed = true;
+        Ok(())
+    }
+
+
+    pub fn remove_search(&mut self, name: &str) -> Result<(), String> {
+        match self.saved.remove(name) {
+            Some(_) => {
+                self.changed = true;
+                Ok(())
+            },
+            None => Err(format!("saved search [{name}] does not exist"))
+        }
+    }
+
+
+    pub fn add_type(&mut self, name: &str, suffixes: &[String]) -> Result<(), String> {
+        if name.is_empty() {
+            return Err("type name cannot be empty".to_string());
+        }
+        if self.mime.contains_key(name) {
+            return Err(format!("type [{name}] already exists"));
+        }
+        let mut mimer = Mimer::new_by_vec(vec![]);
+        for suffix in suffixes {
+            mimer.add(suffix);
+        }
+        self.mime.insert(name.to_string(), mimer);
+        if let Some(sm) = self.shared.as_mut() {
+            sm.restore(name);
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+
+    pub fn remove_type(&mut self, name: &str) -> Result<(), String> {
+        match self.mime.remove(name) {
+            Some(_) => {
+                if let Some(sm) = self.shared.as_mut() {
+                    sm.remove(name);
+                }
+                self.changed = true;
+                Ok(())
+            },
+            None => Err(format!("type [{name}] does not exist"))
+        }
+    }
+
+
+    pub fn add_suffixes(&mut self, name: &str, suffixes: &[String]) -> Result<(), String> {
+        let mimer = self.mime.get_mut(name).ok_or(format!("type [{name}] does not exist"))?;
+        for suffix in suffixes {
+            mimer.add(suffix);
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+
+    /// Remove suffixes from a type; the type itself remains even if left with no suffixes
+    pub fn remove_suffixes(&mut self, name: &str, suffixes: &[String]) -> Result<(), String> {
+        let mimer = self.mime.get_mut(name).ok_or(format!("type [{name}] does not exist"))?;
+        for suffix in suffixes {
+            mimer.rem(suffix);
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+
+    pub fn rename_type(&mut self, old: &str, new: &str) -> Result<(), String> {
+        if new.is_empty() {
+            return Err("type name cannot be empty".to_string());
+        }
+        if self.mime.contains_key(new) {
+            return Err(format!("type [{new}] already exists"));
+        }
+        let mimer = self.mime.remove(old).ok_or(format!("type [{old}] does not exist"))?;
+        self.mime.insert(new.to_string(), mimer);
+        if let Some(sm) = self.shared.as_mut() {
+            sm.remove(old);
+            sm.restore(new);
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+
     pub fn set_source(&mut self, target: &str) {
         self.mb.src = target.to_string();
         self.changed = true;
@@ -620,16 +805,34 @@ where
                 },
                 None => json::JsonValue::Null
             };
-            let jdatabase = object!{
+            let mut jdatabase = object!{
                 src: self.mb.src.as_str(),
                 table: self.mb.table.as_str(),
                 scan: jrepo,
                 alt: self.mb.alt,
                 back: jback
             };
-            // Prep the mime object
+            if !self.mb.deftype.is_empty() {
+                let _ = jdatabase.insert("deftype", self.mb.deftype.as_str());
+            }
+            if self.mb.sniff.mode != DetectMode::Suffix {
+                let _ = jdatabase.insert("detect", self.mb.sniff.mode.name());
+            }
+            if self.mb.archives {
+                let _ = jdatabase.insert("archives", true);
+            }
+            if let Some(sm) = &self.shared {
+                let _ = jdatabase.insert("sharedmime", sm.path.as_str());
+                if !sm.removed().is_empty() {
+                    let _ = jdatabase.insert("sharedmime_removed", sm.removed().iter().map(|n| n.as_str()).collect::<Vec<&str>>());
+                }
+            }
+            // Prep the mime object, leaving out unaltered shared-mime-info types
             let mut jmime = json::JsonValue::new_array();
             for m in self.mime.keys() {
+                if self.shared.as_ref().is_some_and(|sm| sm.is_generated(m, self.mime.get(m).unwrap())) {
+                    continue;
+                }
                 let mut mimevec: Vec<&str> = vec![m];
                 mimevec.append(&mut self.mime.get(m).unwrap().iter().collect::<Vec<&str>>().to_vec());
                 let _ = jmime.push(mimevec); 
@@ -639,6 +842,14 @@ where
                 database: jdatabase,
                 mime: jmime
             };
+            if !self.mb.sniff.magic.user_signatures().is_empty() {
+                let _ = jroot.insert("magic", self.mb.sniff.magic.write());
+            }
+            if !self.saved.is_empty() {
+                if let Ok(jsaved) = self.saved.write() {
+                    let _ = jroot.insert("saved", jsaved);
+                }
+            }
             // Insert any holdovers into the root
             for (key,val) in self.holdover.iter() {
                 let _ = jroot.insert(key.as_str(), val.clone());
@@ -653,3 +864,50 @@ where
 
 
 } //end impl Configuration
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::utckeeper::UtcKeeper;
+
+    const SHARED: &str = r#"<mime-info>
+  <mime-type type="image/png"><glob pattern="*.png"/></mime-type>
+  <mime-type type="text/plain"><glob pattern="*.txt"/></mime-type>
+  <mime-type type="video/mp4"><glob pattern="*.mp4"/></mime-type>
+</mime-info>"#;
+
+    #[test]
+    fn test_removed_shared_mime_stays_gone() {
+        let dir = env::temp_dir().join(format!("memobook_conf_shared_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let shared = dir.join("shared.xml");
+        fs::write(&shared, SHARED).unwrap();
+        let confpath = dir.join("conf.json");
+        let conf = object!{ database: { src: "memo.db", table: "memo", sharedmime: shared.to_str().unwrap() } };
+        fs::write(&confpath, conf.dump()).unwrap();
+        let read = || Configuration::<UtcKeeper>::read(confpath.to_str().unwrap(), None).unwrap();
+        let names = |cfg: &Configuration<UtcKeeper>| {
+            let mut v: Vec<String> = cfg.mime().keys().cloned().collect();
+            v.sort();
+            v
+        };
+        let mut cfg = read();
+        assert_eq!(names(&cfg), vec!["Image", "Text", "Video"]);
+        cfg.remove_type("Image").unwrap();
+        cfg.rename_type("Video", "Movies").unwrap();
+        cfg.finish();
+        let mut cfg = read();
+        assert_eq!(names(&cfg), vec!["Movies", "Text"]);
+        assert_eq!(cfg.mime().get("Movies").unwrap().display(), "mp4");
+        // adding a removed type back lets it be generated again
+        cfg.add_type("Image", &["png".to_string()]).unwrap();
+        cfg.finish();
+        let cfg = read();
+        assert_eq!(names(&cfg), vec!["Image", "Movies", "Text"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+}
@@ -25,6 +25,10 @@
 use crate::mberror::MBError;
 use crate::repository::Repository;
 use std::path::PathBuf;
+use crate::magic::Sniffer;
+use crate::extractor::ExtractorRegistry;
+use crate::jobprogress::JobProgress;
+use std::sync::Arc;
 
 
 pub enum CrawlOption {
@@ -32,7 +36,11 @@ pub enum CrawlOption {
     FollowLinks(bool),
     Repository(Repository),
     Transport(String),
-    Log(String)
+    Log(String),
+    Sniffer(Sniffer),
+    Extractors(ExtractorRegistry),
+    Archives(bool),
+    Progress(Arc<JobProgress>)
 }
 
 
@@ -31,12 +31,15 @@ use crate::mberror::MBError;
 use crate::liteexportquery::LiteExportQuery;
 use crate::rem_dupes;
 use sha256::digest;
+use crate::archive;
+use crate::jobprogress::JobProgress;
 
 
 pub struct ExportLogger {
     log: String,
     table: String,
-    toc: Vec<String>
+    toc: Vec<String>,
+    records: Vec<(String, Vec<String>, Vec<String>)> // (file, marks, types), gathered by collect
 }
 
 
@@ -47,7 +50,8 @@ impl ExportLogger {
         ExportLogger {
             log: lognm.to_string(),
             table: tablenm.to_string(),
-            toc: Vec::new()
+            toc: Vec::new(),
+            records: Vec::new()
         }
     }
 
@@ -115,6 +119,9 @@ impl ExportLogger {
 
 
     fn checksum(&self, filenm: &str) -> Option<String> {
+        if archive::split_member(filenm).is_some() {
+            return archive::read_member(filenm).ok().map(digest);
+        }
         let filecanon = match PathBuf::from(filenm).as_path().canonicalize() {
             Ok(f) => f,
             Err(_) => { return None; }
@@ -127,8 +134,9 @@ impl ExportLogger {
     }
 
 
-    pub fn dump(&self, conn: &Connection) -> Result<(), MBError> {
-        let mut recordhash: HashMap<String, json::JsonValue> = HashMap::new();
+    /// Read the marks and types of every file in the toc. Only this step needs the connection.
+    pub fn collect(&mut self, conn: &Connection) -> Result<&mut ExportLogger, MBError> {
+        let mut records: Vec<(String, Vec<String>, Vec<String>)> = Vec::new();
         for item in self.toc.iter() {
             let commandformer = LiteExportQuery::new(&self.table);
             let marks: Vec<String> = match self.gather(conn, commandformer.form_mark_query(item).as_str()) {
@@ -139,25 +147,50 @@ impl ExportLogger {
                 Some(m) => rem_dupes!(m.iter().filter(|x| !x.is_empty()).collect::<Vec<&String>>()),
                 None => { continue; }
             };
+            records.push((item.to_string(), marks, types));
+        }
+        self.records = records;
+        Ok(self)
+    }
+
+
+    /// Checksum the collected files and write the log; returns the number of records written
+    pub fn write(&self, progress: Option<&JobProgress>) -> Result<usize, MBError> {
+        let mut recordhash: HashMap<String, json::JsonValue> = HashMap::new();
+        for (item, marks, types) in self.records.iter() {
+            if let Some(p) = progress {
+                p.check()?;
+                p.scanned(1);
+            }
             let sum: String = match self.checksum(item) {
                 Some(s) => s,
                 None => { continue; }
             };
+            if let Some(p) = progress {
+                p.hashed(1);
+                p.matched(1);
+            }
             let record = object!{
                 source: item.to_string(),
-                mark: marks,
-                type: types
+                mark: marks.clone(),
+                type: types.clone()
             };
             recordhash.insert(sum, record);
         }
         if recordhash.is_empty() {
-            return Ok(());
+            return Ok(0);
         }
+        let written: usize = recordhash.len();
         let recordjson: json::JsonValue = json::JsonValue::from(recordhash);
         match fs::write(&self.log, json::stringify_pretty(recordjson,4)) {
-            Ok(_) => Ok(()),
+            Ok(_) => Ok(written),
             Err(x) => Err(MBError::FileSys(format!("cannot write export log: {x}")))
         }
     }
 
+
+    pub fn dump(&mut self, conn: &Connection) -> Result<(), MBError> {
+        self.collect(conn)?.write(None).map(|_| ())
+    }
+
 }
@@ -0,0 +1,533 @@
+//  extractor.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+/********************************************
+   Text extraction for file types whose text
+    grep cannot see, e.g. compressed PDF
+    streams or zipped office documents.
+    Extractors are registered per mime type.
+*********************************************/
+
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use flate2::read::ZlibDecoder;
+use crate::archive;
+use crate::mberror::MBError;
+use crate::mimer::Mimer;
+use crate::sharedmime::unescape;
+
+
+pub const OFFICE_SUFFIXES: [&str; 9] = ["docx", "xlsx", "pptx", "odt", "ods", "odp", "odg", "ott", "epub"];
+
+
+
+pub trait TextExtractor: Send + Sync {
+
+    /// Whether this extractor understands files of the given (lowercase) suffix
+    fn accepts(&self, suffix: &str) -> bool;
+
+    fn extract(&self, path: &Path) -> Result<String, MBError>;
+
+}
+
+
+/*---------------------------------------------------------------------*/
+
+
+/// Text shown by the content streams of a PDF. Only uncompressed and FlateDecode
+/// streams are read, and strings are taken byte-for-byte, so fonts with custom
+/// encodings come out garbled.
+pub struct PdfExtractor;
+
+
+impl TextExtractor for PdfExtractor {
+
+    fn accepts(&self, suffix: &str) -> bool {
+        suffix == "pdf"
+    }
+
+    fn extract(&self, path: &Path) -> Result<String, MBError> {
+        let data: Vec<u8> = fs::read(path)
+            .map_err(|e| MBError::Extract(format!("cannot read {}: {e}", path.display())))?;
+        if !data.starts_with(b"%PDF") {
+            return Err(MBError::Extract(format!("{} is not a PDF", path.display())));
+        }
+        Ok(pdf_text(&data))
+    }
+
+}
+
+
+fn find_bytes(hay: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from >= hay.len() {
+        return None;
+    }
+    hay[from..].windows(needle.len()).position(|w| w == needle).map(|p| p + from)
+}
+
+
+fn rfind_bytes(hay: &[u8], needle: &[u8]) -> Option<usize> {
+    hay.windows(needle.len()).rposition(|w| w == needle)
+}
+
+
+fn pdf_text(data: &[u8]) -> String {
+    let mut text: String = String::new();
+    let mut cursor: usize = 0;
+    while let Some(kw) = find_bytes(data, b"stream", cursor) {
+        // "endstream" also contains the keyword
+        if kw >= 3 && &data[kw - 3..kw] == b"end" {
+            cursor = kw + 6;
+            continue;
+        }
+        let mut start = kw + 6;
+        if data.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if data.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+        let Some(end) = find_bytes(data, b"endstream", start) else {
+            break;
+        };
+        cursor = end + 9;
+        let dictstart = rfind_bytes(&data[..kw], b"obj").unwrap_or(0);
+        let dict = String::from_utf8_lossy(&data[dictstart..kw]);
+        // content streams carry no type; fonts, images and object streams are skipped
+        if dict.contains("/Type") || dict.contains("/Subtype") || dict.contains("/Length1") {
+            continue;
+        }
+        let content: Vec<u8> = if dict.contains("/FlateDecode") {
+            let mut inflated: Vec<u8> = Vec::new();
+            let mut decoder = ZlibDecoder::new(&data[start..end]).take(archive::MAX_MEMBER_SIZE + 1);
+            if decoder.read_to_end(&mut inflated).is_err() && inflated.is_empty() {
+                continue;
+            }
+            // a stream inflating past the member limit is skipped rather than held in memory
+            if inflated.len() as u64 > archive::MAX_MEMBER_SIZE {
+                continue;
+            }
+            inflated
+        } else if dict.contains("/Filter") {
+            continue;
+        } else {
+            data[start..end].to_vec()
+        };
+        let shown = pdf_shown_text(&content);
+        if !shown.is_empty() {
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&shown);
+        }
+    }
+    text
+}
+
+
+fn pdf_literal(content: &[u8], mut i: usize, out: &mut Vec<u8>) -> usize {
+    // content[i] is the opening parenthesis; returns index past the closing one
+    let mut depth: usize = 1;
+    i += 1;
+    while i < content.len() {
+        let c = content[i];
+        match c {
+            b'\\' => {
+                i += 1;
+                let Some(&e) = content.get(i) else { break; };
+                match e {
+                    b'n' => out.push(b'\n'),
+                    b'r' => out.push(b'\r'),
+                    b't' => out.push(b'\t'),
+                    b'b' | b'f' => {},
+                    b'0'..=b'7' => {
+                        let mut val: u32 = 0;
+                        let mut digits = 0;
+                        while digits < 3 && i < content.len() && (b'0'..=b'7').contains(&content[i]) {
+                            val = val * 8 + (content[i] - b'0') as u32;
+                            i += 1;
+                            digits += 1;
+                        }
+                        out.push(val as u8);
+                        continue;
+                    },
+                    b'\r' | b'\n' => {},
+                    _ => out.push(e)
+                }
+            },
+            b'(' => { depth += 1; out.push(c); },
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+                out.push(c);
+            },
+            _ => out.push(c)
+        }
+        i += 1;
+    }
+    i
+}
+
+
+fn pdf_hex(content: &[u8], mut i: usize, out: &mut Vec<u8>) -> usize {
+    // content[i] is the opening angle bracket
+    let mut digits: Vec<u8> = Vec::new();
+    i += 1;
+    while i < content.len() && content[i] != b'>' {
+        if content[i].is_ascii_hexdigit() {
+            digits.push(content[i]);
+        }
+        i += 1;
+    }
+    if !digits.len().is_multiple_of(2) {
+        digits.push(b'0');
+    }
+    for pair in digits.chunks(2) {
+        if let Ok(b) = u8::from_str_radix(std::str::from_utf8(pair).unwrap_or("00"), 16) {
+            out.push(b);
+        }
+    }
+    i + 1
+}
+
+
+/// Strings shown between BT and ET, with a space wherever the text position moves
+fn pdf_shown_text(content: &[u8]) -> String {
+    let mut out: Vec<u8> = Vec::new();
+    let mut pending: Vec<u8> = Vec::new();
+    let mut intext: bool = false;
+    let mut i: usize = 0;
+    while i < content.len() {
+        let c = content[i];
+        if c == b'%' {
+            while i < content.len() && content[i] != b'\n' && content[i] != b'\r' {
+                i += 1;
+            }
+            continue;
+        }
+        if intext && c == b'(' {
+            i = pdf_literal(content, i, &mut pending);
+            continue;
+        }
+        if intext && c == b'<' && content.get(i + 1) != Some(&b'<') {
+            i = pdf_hex(content, i, &mut pending);
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == b'\'' || c == b'"' || c == b'*' {
+            let start = i;
+            while i < content.len() && (content[i].is_ascii_alphabetic() || content[i] == b'*' || content[i] == b'\'' || content[i] == b'"') {
+                i += 1;
+            }
+            match &content[start..i] {
+                b"BT" => { intext = true; },
+                b"ET" => {
+                    intext = false;
+                    pending.clear();
+                    if !out.is_empty() && out.last() != Some(&b' ') {
+                        out.push(b' ');
+                    }
+                },
+                b"Tj" | b"TJ" => { out.append(&mut pending); },
+                b"'" | b"\"" => {
+                    if !out.is_empty() {
+                        out.push(b' ');
+                    }
+                    out.append(&mut pending);
+                },
+                b"Td" | b"TD" | b"Tm" | b"T*" if !out.is_empty() && out.last() != Some(&b' ') => {
+                    out.push(b' ');
+                },
+                _ => {}
+            }
+            continue;
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).trim().to_string()
+}
+
+
+/*---------------------------------------------------------------------*/
+
+
+/// Text of OOXML (docx, xlsx, pptx) and ODF (odt, ods, odp, ...) documents, read
+/// from the xml parts of the zip container
+pub struct OfficeExtractor;
+
+
+impl TextExtractor for OfficeExtractor {
+
+    fn accepts(&self, suffix: &str) -> bool {
+        OFFICE_SUFFIXES.contains(&suffix)
+    }
+
+    fn extract(&self, path: &Path) -> Result<String, MBError> {
+        let file = fs::File::open(path)
+            .map_err(|e| MBError::Extract(format!("cannot read {}: {e}", path.display())))?;
+        let mut archive = zip::ZipArchive::new(file)
+            .map_err(|e| MBError::Extract(format!("{} is not a zip container: {e}", path.display())))?;
+        let mut parts: Vec<String> = archive.file_names()
+            .filter(|n| is_text_part(n))
+            .map(|n| n.to_string())
+            .collect();
+        parts.sort_by_key(|n| part_order(n));
+        let mut text: String = String::new();
+        for part in parts {
+            let mut xml: String = String::new();
+            match archive.by_name(&part) {
+                Ok(entry) => {
+                    if entry.take(archive::MAX_MEMBER_SIZE + 1).read_to_string(&mut xml).is_err()
+                        || xml.len() as u64 > archive::MAX_MEMBER_SIZE {
+                        continue;
+                    }
+                },
+                Err(_) => continue
+            }
+            let parttext = xml_text(&xml);
+            if !parttext.is_empty() {
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(&parttext);
+            }
+        }
+        Ok(text)
+    }
+
+}
+
+
+fn is_text_part(name: &str) -> bool {
+    name == "content.xml"
+        || name == "word/document.xml"
+        || name == "xl/sharedStrings.xml"
+        || (name.starts_with("word/") && (name.contains("footnotes") || name.contains("header") || name.contains("footer")) && name.ends_with(".xml"))
+        || (name.starts_with("ppt/slides/slide") && name.ends_with(".xml"))
+        || (name.ends_with(".xhtml") || name.ends_with(".html"))
+}
+
+
+/// Sort key keeping slides in numeric order and the main document first
+fn part_order(name: &str) -> (usize, usize, String) {
+    let number: usize = name.trim_end_matches(".xml")
+        .rsplit(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0);
+    let rank = if name == "content.xml" || name == "word/document.xml" { 0 } else { 1 };
+    (rank, number, name.to_string())
+}
+
+
+/// Character data of an xml document; paragraph-like elements are separated by spaces
+fn xml_text(xml: &str) -> String {
+    let mut out: String = String::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&unescape(&rest[..start]));
+        let end = match rest[start..].find('>') {
+            Some(e) => start + e,
+            None => break
+        };
+        let tag = rest[start + 1..end].trim_start_matches('/').trim_end_matches('/');
+        let name = tag.split(char::is_whitespace).next().unwrap_or("");
+        let local = name.rsplit(':').next().unwrap_or(name);
+        if matches!(local, "p" | "h" | "br" | "tab" | "s" | "si" | "tc" | "li" | "div") {
+            out.push(' ');
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(&unescape(rest));
+    out.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+
+/*---------------------------------------------------------------------*/
+
+
+/// Extractors keyed by mime type name. A file is handled by the extractor of one
+/// of its types, provided that extractor accepts the file's suffix.
+#[derive(Clone, Default)]
+pub struct ExtractorRegistry {
+    extractors: HashMap<String, Arc<dyn TextExtractor>>,
+    mime: HashMap<String, Mimer>
+}
+
+
+impl ExtractorRegistry {
+
+    pub fn new() -> ExtractorRegistry {
+        ExtractorRegistry::default()
+    }
+
+    /// Registry with the built-in extractors bound to every type holding a suffix they accept
+    pub fn with_builtins(mime: &HashMap<String, Mimer>) -> ExtractorRegistry {
+        let mut registry = ExtractorRegistry::new();
+        registry.retarget(mime);
+        registry
+    }
+
+    pub fn register(&mut self, ftype: &str, extractor: Arc<dyn TextExtractor>) -> &mut ExtractorRegistry {
+        self.extractors.insert(ftype.to_string(), extractor);
+        self
+    }
+
+    /// Follow a change of the mime table; types gaining a built-in suffix get the built-in extractor,
+    /// explicit registrations are left alone
+    pub fn retarget(&mut self, mime: &HashMap<String, Mimer>) {
+        self.mime = mime.clone();
+        let builtins: [Arc<dyn TextExtractor>; 2] = [Arc::new(PdfExtractor), Arc::new(OfficeExtractor)];
+        for (ftype, mimer) in mime.iter() {
+            if self.extractors.contains_key(ftype) {
+                continue;
+            }
+            if let Some(x) = builtins.iter().find(|x| mimer.iter().any(|s| x.accepts(&s.to_lowercase()))) {
+                self.extractors.insert(ftype.to_string(), x.clone());
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.extractors.is_empty()
+    }
+
+    pub fn for_suffix(&self, suffix: &str) -> Option<Arc<dyn TextExtractor>> {
+        let suffix = suffix.to_lowercase();
+        self.mime.iter()
+            .filter(|(_, m)| m.contains(&suffix))
+            .filter_map(|(t, _)| self.extractors.get(t))
+            .find(|x| x.accepts(&suffix))
+            .cloned()
+    }
+
+    pub fn for_path(&self, path: &Path) -> Option<Arc<dyn TextExtractor>> {
+        path.extension().and_then(|s| s.to_str()).and_then(|s| self.for_suffix(s))
+    }
+
+    /// The text of a file or archive member, through its extractor if it has one
+    pub fn read_text(&self, file: &str) -> Result<String, MBError> {
+        if archive::split_member(file).is_some() {
+            return archive::read_member(file).map(|d| String::from_utf8_lossy(&d).to_string());
+        }
+        match self.for_path(Path::new(file)) {
+            Some(x) => x.extract(Path::new(file)),
+            None => match fs::read(file) {
+                Ok(d) => Ok(String::from_utf8_lossy(&d).to_string()),
+                Err(e) => Err(MBError::FileSys(format!("cannot read {file}: {e}")))
+            }
+        }
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Write;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("memobook_test_extract_{}_{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_pdf_text() {
+        let content = b"BT /F1 12 Tf 72 712 Td (Hello) Tj 0 -14 Td [(Wor) -20 (ld\\051)] TJ ET";
+        let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(content).unwrap();
+        let mut pdf: Vec<u8> = b"%PDF-1.4\n1 0 obj\n<< /Type /Font /Subtype /Type1 >>\nendobj\n4 0 obj\n<< /Length 9 /Filter /FlateDecode >>\nstream\n".to_vec();
+        pdf.extend(enc.finish().unwrap());
+        pdf.extend(b"\nendstream\nendobj\n5 0 obj\n<< /Length 20 >>\nstream\nBT <416263> Tj ET\nendstream\nendobj\n%%EOF\n");
+        let path = temp_path("a.pdf");
+        fs::write(&path, &pdf).unwrap();
+        let text = PdfExtractor.extract(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(text, "Hello World)\nAbc");
+    }
+
+    #[test]
+    fn test_office_text() {
+        let path = temp_path("b.docx");
+        {
+            let mut zw = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            let opts = zip::write::SimpleFileOptions::default();
+            zw.start_file("word/document.xml", opts).unwrap();
+            zw.write_all(br#"<w:document><w:body><w:p><w:r><w:t>Fish &amp; chips</w:t></w:r></w:p><w:p><w:r><w:t>and peas</w:t></w:r></w:p></w:body></w:document>"#).unwrap();
+            zw.start_file("word/styles.xml", opts).unwrap();
+            zw.write_all(b"<w:styles><w:name>Heading</w:name></w:styles>").unwrap();
+            zw.finish().unwrap();
+        }
+        let text = OfficeExtractor.extract(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(text, "Fish & chips and peas");
+    }
+
+    #[test]
+    fn test_oversized_parts_skipped() {
+        let oversized = vec![b' '; archive::MAX_MEMBER_SIZE as usize + 1];
+        let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(&oversized).unwrap();
+        let mut pdf: Vec<u8> = b"%PDF-1.4\n4 0 obj\n<< /Filter /FlateDecode >>\nstream\n".to_vec();
+        pdf.extend(enc.finish().unwrap());
+        pdf.extend(b"\nendstream\nendobj\n5 0 obj\n<< /Length 20 >>\nstream\nBT (kept) Tj ET\nendstream\nendobj\n%%EOF\n");
+        assert_eq!(pdf_text(&pdf), "kept");
+        let path = temp_path("c.docx");
+        {
+            let mut zw = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            let opts = zip::write::SimpleFileOptions::default();
+            zw.start_file("word/document.xml", opts).unwrap();
+            zw.write_all(&oversized).unwrap();
+            zw.start_file("word/footer1.xml", opts).unwrap();
+            zw.write_all(b"<w:ftr><w:t>kept</w:t></w:ftr>").unwrap();
+            zw.finish().unwrap();
+        }
+        let text = OfficeExtractor.extract(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(text, "kept");
+    }
+
+    #[test]
+    fn test_registry_by_type() {
+        let mut mime: HashMap<String, Mimer> = HashMap::new();
+        mime.insert("PDF".to_string(), Mimer::new_by_vec(vec!["pdf".to_string()]));
+        mime.insert("Docs".to_string(), Mimer::new_by_vec(vec!["txt".to_string(), "odt".to_string()]));
+        let registry = ExtractorRegistry::with_builtins(&mime);
+        assert!(registry.for_suffix("PDF").is_some());
+        assert!(registry.for_suffix("odt").is_some());
+        assert!(registry.for_suffix("txt").is_none());
+        assert!(registry.for_path(Path::new("/tmp/x.docx")).is_none());
+    }
+
+}
@@ -28,12 +28,19 @@ use crate::logic::Logic;
 use crate::mberror::MBError;
 use crate::repository::Repository;
 use std::collections::HashMap;
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::io::Write;
 use std::path::PathBuf;
 use crate::maskingset::MaskingSet;
 use crate::suffixhash::SuffixHash;
 use crate::crawler::{Crawler, CrawlOption};
 use crate::filecrawler::FileCrawler;
+use crate::magic::{DetectMode, Sniffer};
+use crate::extractor::ExtractorRegistry;
+use crate::archive::{self, ArchiveKind};
+use crate::jobprogress::JobProgress;
+use std::sync::Arc;
+use std::path::Path;
 
 
 pub struct LogicalHash {
@@ -59,7 +66,7 @@ impl LogicalHash {
                 }
             },
             Logic::OR => {
-                self.sethash.entry(item.to_string()).or_insert(1);
+                self.sethash.entry(item.to_string()).and_modify(|counter| *counter += 1).or_insert(1);
             }
         }
         self
@@ -78,7 +85,7 @@ impl LogicalHash {
             },
             Logic::OR => {
                 for found in items {
-                    self.sethash.entry(found.to_string()).or_insert(1);
+                    self.sethash.entry(found.to_string()).and_modify(|counter| *counter += 1).or_insert(1);
                 }
             }
         }
@@ -99,6 +106,21 @@ impl LogicalHash {
         }
     }
 
+    /// Like express, but pairs each item with the number of terms it was found for
+    pub fn express_counted(&self) -> Option<Vec<(String, usize)>> {
+        let mut retvec: Vec<(String, usize)> = vec![];
+        for (k,v) in &self.sethash {
+            if *v >= self.criterion {
+                retvec.push((k.to_string(), *v));
+            }
+        }
+        if retvec.is_empty() {
+            None
+        } else {
+            Some(retvec)
+        }
+    }
+
 }
 
 
@@ -115,7 +137,12 @@ pub struct GrepCrawler {
     logic: Logic,
     casesens: bool,
     followlink: bool,
-    results: Option<Vec<String>>
+    sniffer: Sniffer,
+    extractors: ExtractorRegistry,
+    archives: bool,
+    progress: Option<Arc<JobProgress>>,
+    results: Option<Vec<String>>,
+    counted: Option<Vec<(String, usize)>>
 
 }
 
@@ -131,7 +158,12 @@ impl GrepCrawler {
             logic: Logic::OR,
             casesens: false,
             followlink: false,
-            results: None
+            sniffer: Sniffer::default(),
+            extractors: ExtractorRegistry::new(),
+            archives: false,
+            progress: None,
+            results: None,
+            counted: None
         }
     }
 
@@ -140,6 +172,10 @@ impl GrepCrawler {
             CrawlOption::CaseSensitive(ref b) => { self.casesens = *b; },
             CrawlOption::FollowLinks(ref b) => { self.followlink = *b; },
             CrawlOption::Repository(ref r) => { self.repos = Some(r.clone()); },
+            CrawlOption::Sniffer(ref s) => { self.sniffer = s.clone(); },
+            CrawlOption::Extractors(ref x) => { self.extractors = x.clone(); },
+            CrawlOption::Archives(ref b) => { self.archives = *b; },
+            CrawlOption::Progress(ref p) => { self.progress = Some(p.clone()); },
             _ => {}
         }
         _ = self.crawler.options(optsenum);
@@ -170,9 +206,21 @@ impl GrepCrawler {
         let mut filefilter: HashMap<PathBuf, bool> = HashMap::new();
         let mut suffixfilter: SuffixHash = SuffixHash::new();
         let _ = suffixfilter.addv(filesuffixes);
+        let sniffer: &Sniffer = &self.sniffer;
+        let archives: bool = self.archives;
+        let progress: Option<Arc<JobProgress>> = self.progress.clone();
+        let check = || progress.as_ref().map_or(Ok(()), |p| p.check());
         let filesvec: Vec<PathBuf> = match self.crawler.crawl(
             &mut |filecanon: PathBuf| {
-                if suffixfilter.test(&filecanon.to_str().unwrap().to_string()) && !filefilter.contains_key(&filecanon) {
+                if let Some(p) = progress.as_ref() {
+                    p.scanned(1);
+                }
+                // archives are let through whatever their type; their members are type-tested below
+                let typematch: bool = (archives && ArchiveKind::of(&filecanon).is_some()) || match sniffer.mode {
+                    DetectMode::Suffix => suffixfilter.test(&filecanon.to_str().unwrap().to_string()),
+                    _ => sniffer.suffixes(&filecanon).iter().any(|s| suffixfilter.test_suffix(s))
+                };
+                if typematch && !filefilter.contains_key(&filecanon) {
                     filefilter.insert(filecanon.to_path_buf(), true);
                     Ok(filecanon.to_path_buf())
                 } else {
@@ -185,38 +233,93 @@ impl GrepCrawler {
                     Some(v) => v,
                     None => {
                         self.results = None;
+                        self.counted = None;
                         return Ok(self);
                     }
                 }
             },
             Err(_) => return Err(MBError::Grep("file crawl error".to_string()))
         };
+        // Text from extractors and archive members is piped through grep, term by term, as it is read
+        let mut extracted: Vec<Vec<String>> = vec![vec![]; searchterms.len()];
+        let mut grepfiles: Vec<&PathBuf> = vec![];
+        let casesens: bool = self.casesens;
+        let mut grep_extracted = |path: String, text: &str| -> Result<(), MBError> {
+            for (hits, term) in extracted.iter_mut().zip(searchterms.iter()) {
+                if grep_text(term, text, casesens)? {
+                    hits.push(path.clone());
+                }
+            }
+            Ok(())
+        };
+        check()?;
+        for file in filesvec.iter() {
+            check()?;
+            if self.archives && ArchiveKind::of(file).is_some() {
+                let visited = archive::for_each_member(file, |name, data| {
+                    check()?;
+                    let typematch: bool = match self.sniffer.mode {
+                        DetectMode::Suffix => suffixfilter.test(&name),
+                        _ => self.sniffer.suffixes_of_bytes(Path::new(&name), &data).iter().any(|s| suffixfilter.test_suffix(s))
+                    };
+                    if typematch {
+                        grep_extracted(archive::member_path(file.to_str().unwrap(), &name), &String::from_utf8_lossy(&data))?;
+                    }
+                    Ok(())
+                });
+                // an unreadable archive is skipped, but cancellation and grep failures end the search
+                match visited {
+                    Err(MBError::FileSys(_)) | Ok(()) => continue,
+                    Err(e) => return Err(e)
+                }
+            }
+            let extractor = match self.sniffer.mode {
+                DetectMode::Suffix => self.extractors.for_path(file),
+                _ => self.sniffer.suffixes(file).iter().find_map(|s| self.extractors.for_suffix(s))
+            };
+            match extractor {
+                Some(x) => {
+                    if let Ok(text) = x.extract(file) {
+                        grep_extracted(file.to_str().unwrap().to_string(), &text)?;
+                    }
+                },
+                None => grepfiles.push(file)
+            }
+        }
         let mut setofresults: LogicalHash = LogicalHash::new(self.logic);
-        for term in searchterms {
+        for (term, mut resultsformark) in searchterms.iter().zip(extracted) {
+            check()?;
+            if grepfiles.is_empty() {
+                setofresults.addv(&resultsformark);
+                continue;
+            }
 			let mut grepcmd = Command::new("grep");
             if !self.casesens {
     			grepcmd.arg("-i");
             }
             grepcmd.arg("-l").arg(term);
-			for file in filesvec.iter() {
+			for file in grepfiles.iter() {
 				grepcmd.arg(file.as_path().to_str().unwrap());
 			}
 			let grepresult = match grepcmd.output() {
                 Ok(x) => x,
                 Err(x) => return Err(MBError::Grep(format!("Grep error: {x}")))
             };
-            let resultsformark = match String::from_utf8(grepresult.stdout) {
+            match String::from_utf8(grepresult.stdout) {
 				Ok(strresult) => {
-                    strresult.split('\n')
-					.filter(|s| !s.is_empty())
-					.map(|y| y.to_string())
-					.collect()
+                    resultsformark.extend(strresult.split('\n')
+					    .filter(|s| !s.is_empty())
+					    .map(|y| y.to_string()));
                 },
 				Err(z) => { return Err(MBError::Grep(format!("Grep FromUTF8Error: {z}"))); }
 			};
             setofresults.addv(&resultsformark);
 		}
         self.results = setofresults.express();
+        self.counted = setofresults.express_counted();
+        if let (Some(p), Some(found)) = (progress.as_ref(), self.results.as_ref()) {
+            p.matched(found.len());
+        }
         Ok(self)
     }
     
@@ -225,15 +328,71 @@ impl GrepCrawler {
         self.results.clone()
     }
 
+
+    /// Hits paired with the number of search terms found in each
+    pub fn retrieve_counted(&self) -> Option<Vec<(String, usize)>> {
+        self.counted.clone()
+    }
+
+}
+
+
+/// Whether grep finds the term in text, so extracted text matches the way files on disk do
+pub(crate) fn grep_text(term: &str, text: &str, casesens: bool) -> Result<bool, MBError> {
+    let mut grepcmd = Command::new("grep");
+    if !casesens {
+        grepcmd.arg("-i");
+    }
+    grepcmd.arg("-q").arg(term)
+        .stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null());
+    let mut child = grepcmd.spawn().map_err(|x| MBError::Grep(format!("Grep error: {x}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // grep -q stops reading at the first match, so a broken pipe is not an error
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let status = child.wait().map_err(|x| MBError::Grep(format!("Grep error: {x}")))?;
+    Ok(status.success())
 }
 
 } // pub mod grep_crawler
 
 
 
-/*
 #[cfg(test)]
 mod tests {
 
-}*/
- 
+    use super::grep_crawler::{grep_text, LogicalHash};
+    use crate::logic::Logic;
+
+    #[test]
+    fn test_logical_hash_or_counts() {
+        let mut lh: LogicalHash = LogicalHash::new(Logic::OR);
+        lh.addv(&vec!["a.txt".to_string(), "b.txt".to_string()]);
+        lh.addv(&vec!["a.txt".to_string()]);
+        let mut counted = lh.express_counted().unwrap();
+        counted.sort();
+        assert_eq!(counted, vec![("a.txt".to_string(), 2), ("b.txt".to_string(), 1)]);
+        assert_eq!(lh.express().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_logical_hash_and_counts() {
+        let mut lh: LogicalHash = LogicalHash::new(Logic::AND);
+        lh.addv(&vec!["a.txt".to_string(), "b.txt".to_string()]);
+        lh.addv(&vec!["a.txt".to_string()]);
+        assert_eq!(lh.express_counted().unwrap(), vec![("a.txt".to_string(), 2)]);
+    }
+
+
+    #[test]
+    fn test_grep_text() {
+        assert!(grep_text("alpha", "beta\nalpha gamma", true).unwrap());
+        assert!(!grep_text("ALPHA", "alpha", true).unwrap());
+        assert!(grep_text("ALPHA", "alpha", false).unwrap());
+        // terms are patterns, as they are for files grepped on disk
+        assert!(grep_text("al.ha", "alpha", true).unwrap());
+        assert!(grep_text("^gamma", "alpha\ngamma", true).unwrap());
+        assert!(!grep_text("^alpha$", "alpha beta", true).unwrap());
+    }
+
+}
@@ -40,6 +40,9 @@ use std::fs;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::thread::available_parallelism;
+use std::sync::Arc;
+use crate::jobprogress::JobProgress;
+use crate::archive::{self, ArchiveKind};
 //use std::time::Instant;
 
 
@@ -61,10 +64,12 @@ pub struct ImportCrawler {
 
     repo: Option<Repository>,
     followlink: bool,
+    archives: bool,
     importfile: Option<PathBuf>,
     logfile: Option<PathBuf>,
     results: Vec<ModifyAddRecord>,
     results_empty: bool,
+    progress: Option<Arc<JobProgress>>,
     crawler: FileCrawler<ImportPair>
 
 }
@@ -77,19 +82,51 @@ impl Default for ImportCrawler {
 }
 
 
-async fn process(pathlist: &[ImportPair], recordsdict: &json::JsonValue) -> Result<Vec<ImportPair>, MBError> {
+async fn process(pathlist: &[ImportPair], recordsdict: &json::JsonValue, archives: bool, progress: Option<&JobProgress>) -> Result<Vec<ImportPair>, MBError> {
     let mut retvec: Vec<ImportPair> = vec![];
     for item in pathlist {
+        if let Some(p) = progress {
+            p.check()?;
+        }
         let inputdata = match fs::read(item.target.as_path()) {
             Ok(f) => f,
             Err(e) => { return Err(MBError::Import(format!("import error: {e}"))); }
         };
         let sum: String = digest(inputdata);
+        if let Some(p) = progress {
+            p.hashed(1);
+        }
         if recordsdict.has_key(&sum) {
+            if let Some(p) = progress {
+                p.matched(1);
+            }
             retvec.push(ImportPair {sum, target: item.target.to_path_buf()});
         } else {
             //return Err(MBError::Nil);
         }
+        // exports of member records carry the member's checksum; an unreadable archive is passed over
+        if archives && ArchiveKind::of(&item.target).is_some() {
+            let archivenm = item.target.to_string_lossy().to_string();
+            let _ = archive::for_each_member(&item.target, |member, data| {
+                if let Some(p) = progress {
+                    p.check()?;
+                }
+                let sum: String = digest(data);
+                if let Some(p) = progress {
+                    p.hashed(1);
+                }
+                if recordsdict.has_key(&sum) {
+                    if let Some(p) = progress {
+                        p.matched(1);
+                    }
+                    retvec.push(ImportPair {sum, target: PathBuf::from(archive::member_path(&archivenm, &member))});
+                }
+                Ok(())
+            });
+            if let Some(p) = progress {
+                p.check()?;
+            }
+        }
     }
     Ok(retvec)
 }
@@ -101,10 +138,12 @@ impl ImportCrawler {
         ImportCrawler {
             repo: None,
             followlink: false,
+            archives: false,
             importfile: None,
             logfile: None,
             results: Vec::new(),
             results_empty: true,
+            progress: None,
             crawler: FileCrawler::<ImportPair>::new()
         }
     }
@@ -112,6 +151,7 @@ impl ImportCrawler {
     pub fn set_options(&mut self, optsenum: CrawlOption) -> &mut ImportCrawler {
         match optsenum {
             CrawlOption::FollowLinks(ref b) => { self.followlink = *b; },
+            CrawlOption::Archives(ref b) => { self.archives = *b; },
             CrawlOption::Repository(ref r) => { self.repo = Some(r.clone()); },
             CrawlOption::Transport(ref tp) => {
                 self.importfile = PathBuf::from(tp)
@@ -119,6 +159,7 @@ impl ImportCrawler {
                             .canonicalize() 
                             .ok();
             },
+            CrawlOption::Progress(ref p) => { self.progress = Some(p.clone()); },
             CrawlOption::Log(ref l) => {
                 self.logfile = Some(PathBuf::from(l));
                 //println!("log file is {l}");
@@ -162,8 +203,12 @@ impl ImportCrawler {
         // prepare the processor function and other vars. Processor fctn just makes an ImportPair, nothing more.
         // in the sync version, the processor function also computes the checksum.
         ////let start1 = Instant::now();
+        let progress: Option<&JobProgress> = self.progress.as_deref();
         let preresultsvec: Vec<ImportPair> = match self.crawler.crawl(
             &mut |filecanon: PathBuf| {
+                if let Some(p) = progress {
+                    p.scanned(1);
+                }
                 Ok(ImportPair { sum: "".to_string(), target: filecanon})
             }
         ) {
@@ -193,9 +238,9 @@ impl ImportCrawler {
         ////let start2 = Instant::now();
         for i in 0..num_tasks {
             if i < num_tasks - 1 {
-                collectionvec.push(process(&preresultsvec[i*chunksize..(i+1)*chunksize], &recordsdict).await?);
+                collectionvec.push(process(&preresultsvec[i*chunksize..(i+1)*chunksize], &recordsdict, self.archives, progress).await?);
             } else { 
-                collectionvec.push(process(&preresultsvec[i*chunksize..], &recordsdict).await?);
+                collectionvec.push(process(&preresultsvec[i*chunksize..], &recordsdict, self.archives, progress).await?);
             }
         }
         let resultsvec = collectionvec.concat();
@@ -230,7 +275,8 @@ impl ImportCrawler {
                 json::JsonValue::Object(jobj) =>{ 
                     processedvec.push(
                         ModifyAddRecord {
-                            files: process_json(&jobj["file"]), 
+                            // exports record the old path as "source"; the file is imported where its checksum turned up
+                            files: vec![pair.target.to_string_lossy().to_string()],
                             marks: process_json(&jobj["mark"]),
                             ftypes: process_json(&jobj["type"])
                         }
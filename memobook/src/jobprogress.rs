@@ -0,0 +1,105 @@
+//  jobprogress.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+use crate::mberror::MBError;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+
+/// Counters a long operation (import, export, grep) bumps as it goes, shared with whoever
+/// watches it, together with a flag asking it to stop.
+///     scanned: files walked, or records read for export
+///     hashed: files checksummed
+///     matched: files found, i.e. checksum hits, exported records or grep hits
+#[derive(Default, Debug)]
+pub struct JobProgress {
+    scanned: AtomicUsize,
+    hashed: AtomicUsize,
+    matched: AtomicUsize,
+    cancelled: AtomicBool
+}
+
+
+impl JobProgress {
+
+    pub fn new() -> JobProgress {
+        JobProgress::default()
+    }
+
+    pub fn scanned(&self, n: usize) {
+        self.scanned.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn hashed(&self, n: usize) {
+        self.hashed.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn matched(&self, n: usize) {
+        self.matched.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// (scanned, hashed, matched)
+    pub fn snapshot(&self) -> (usize, usize, usize) {
+        (self.scanned.load(Ordering::Relaxed), self.hashed.load(Ordering::Relaxed), self.matched.load(Ordering::Relaxed))
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Err once cancelled, for use with ? at the points where an operation may stop
+    pub fn check(&self) -> Result<(), MBError> {
+        if self.is_cancelled() {
+            Err(MBError::Cancelled("operation cancelled".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_progress_counts_and_cancel() {
+        let progress = JobProgress::new();
+        progress.scanned(3);
+        progress.hashed(2);
+        progress.matched(1);
+        progress.scanned(1);
+        assert_eq!(progress.snapshot(), (4, 2, 1));
+        assert!(progress.check().is_ok());
+        progress.cancel();
+        assert!(progress.is_cancelled());
+        assert!(matches!(progress.check(), Err(MBError::Cancelled(_))));
+    }
+
+}
@@ -61,9 +61,20 @@ pub mod backer;
 pub mod utcbackup;
 pub mod utckeeper;
 pub mod backerparserjson;
+pub mod savedsearch;
+pub mod litescorequery;
+pub mod liteanalyticsquery;
+pub mod mbstats;
+pub mod suggester;
+pub mod magic;
+pub mod sharedmime;
+pub mod extractor;
+pub mod archive;
+pub mod jobprogress;
+pub mod readpool;
 
 
-use rusqlite::{Connection, Error};
+use rusqlite::{Connection, Error, Transaction};
 //use std::time::Instant;
 use std::collections::HashMap as HashMap;
 use mimer::Mimer as Mimer;
@@ -78,7 +89,7 @@ use crate::modifierassembler::ModifierAssembler;
 use crate::mberror::MBError;
 use crate::dbopenerassembler::DBOpenerAssembler;
 use crate::liteopen::LiteOpen;
-use crate::modifiers::Modifier;
+use crate::modifiers::{Modifier, ModifyAddRecord};
 use crate::liteaddrecord::LiteAddRecord;
 use crate::litefieldreplace::LiteFieldReplace;
 use crate::litemarkupdate::LiteMarkUpdate;
@@ -89,10 +100,42 @@ use crate::crawler::CrawlOption;
 use crate::transportstruct::TransPortStruct;
 use crate::importcrawlerp::import_crawler::ImportCrawler; // <--Change here to use synchronous importcrawler
 use crate::exportlogger::ExportLogger;
+use crate::jobprogress::JobProgress;
+use crate::readpool::{ReadPool, DEFAULT_READERS};
+use std::sync::{Arc, Mutex};
+use crate::litescorequery::LiteScoreQuery;
+use crate::liteanalyticsquery::LiteAnalyticsQuery;
+use crate::mbstats::MBStats;
+use crate::suggester::{Suggestion, SuggestWork};
+use crate::extractor::{ExtractorRegistry, TextExtractor};
+
+
+/// Extra score given to a file found by both the database and the grep crawl
+const CONCURRENCE_BONUS: usize = 2;
 
 
 
 #[inline]
+fn assembler_for(cmd: &Modifier) -> Box<dyn ModifierAssembler> {
+    match cmd {
+        Modifier::AddRecord(_) => Box::new(LiteAddRecord),
+        Modifier::FieldReplace(_) => Box::new(LiteFieldReplace),
+        Modifier::MarkUpdate(_) => Box::new(LiteMarkUpdate),
+        Modifier::TypeUpdate(_) => Box::new(LiteTypeUpdate),
+        Modifier::TargetRemove(_) => Box::new(LiteTargetRemove)
+    }
+}
+
+
+/// Distinct values of a field among a file's records, none when the file has none
+fn lookup_in(conn: &Connection, table: &str, field: &str, file: &str) -> Result<Option<Vec<String>>, MBError> {
+    let mut stmt = conn.prepare(&format!("select distinct {field} from {table} where file = ?1 order by {field};")).map_err(MBError::Sqlite)?;
+    let values: Vec<String> = stmt.query_map([file], |row| row.get::<_, String>(0)).map_err(MBError::Sqlite)?
+        .collect::<Result<Vec<String>, Error>>().map_err(MBError::Sqlite)?;
+    Ok(if values.is_empty() { None } else { Some(values) })
+}
+
+
 fn gather_types(query: &impl (for <'a> Queryer<'a>)) -> Option<Vec<String>> {
     let mut retvec: Vec<String> = vec![];
     'filts: for filter in query.iter_filters() {
@@ -110,6 +153,17 @@ fn gather_types(query: &impl (for <'a> Queryer<'a>)) -> Option<Vec<String>> {
 }
 
 
+#[inline]
+fn gather_files(query: &impl (for <'a> Queryer<'a>)) -> Option<Vec<String>> {
+    for filter in query.iter_filters() {
+        if filter.filtertype() == "file" {
+            return Some(filter.iter().map(|term| term.to_string()).collect());
+        }
+    }
+    None
+}
+
+
 #[inline]
 fn gather_marks(query: &impl (for <'a> Queryer<'a>)) -> Result<(Logic,Vec<String>), MBError> {
     let mut rettup = (Logic::OR, vec![]);
@@ -136,6 +190,10 @@ pub trait Queryable {
     fn initialize(&mut self) -> Result<(), MBError>;
     fn connect(&mut self, source: Option<String>) -> Result<(), MBError>;
     fn search(&self, req: impl (for <'a> Queryer<'a>)) -> Result<Vec<String>, MBError>;
+    fn search_scored(&self, req: impl (for <'a> Queryer<'a>)) -> Result<Vec<(String, usize)>, MBError>;
+    fn related(&self, marks: &[String], limit: usize) -> Result<Vec<(String, usize)>, MBError>;
+    fn stats(&self, topn: usize) -> Result<MBStats, MBError>;
+    fn suggest(&self, file: &str, limit: usize) -> Result<Vec<Suggestion>, MBError>;
     fn modify(&mut self, cmd: &Modifier) -> Result<(), MBError>;
     fn target(&mut self, scan: &Repository, mime: &HashMap<String,Mimer>) -> Result<(), MBError>;
     fn import(&mut self, portinfo: TransPortStruct) -> Result<String, MBError>;
@@ -145,9 +203,11 @@ pub trait Queryable {
 
 
 pub struct MemoBook {
-    connection: Option<Connection>,
+    connection: Option<Mutex<Connection>>, // the writer's; locked only when there is no read pool
+    readers: Option<ReadPool>,
     info: MBInfo,
-    mime: HashMap<String,Mimer>
+    mime: HashMap<String,Mimer>,
+    extractors: ExtractorRegistry
     //assemblers: DBBundler;
 }
 
@@ -159,8 +219,10 @@ impl MemoBook {
     pub fn new(info: &MBInfo, mime: &HashMap<String,Mimer>  /*, dbtype: DBType*/) -> MemoBook {
         MemoBook {
             connection: None, 
+            readers: None,
             info: info.clone(),//config.mb().clone(),
-            mime: mime.clone()//config.mime().clone()
+            mime: mime.clone(),//config.mime().clone()
+            extractors: ExtractorRegistry::with_builtins(mime)
             //translate: DBBundler::new(dbtype)
         }
     }
@@ -190,18 +252,37 @@ impl MemoBook {
             },
             Err(q) => { return Err(q); }
         }
-        self.connection = Some(conn);
+        self.connection = Some(Mutex::new(conn));
+        // without a pool, e.g. for an in-memory database, reads fall back on the writer's connection
+        self.readers = ReadPool::open(&self.info.src, DEFAULT_READERS).ok();
         Ok(())
     }
 
 
+    /// Run a read on a pooled connection, or on the writer's when there is no pool; None when
+    /// not connected
+    fn read<T>(&self, read: impl FnOnce(&Connection) -> Result<T, MBError>) -> Option<Result<T, MBError>> {
+        if let Some(pool) = self.readers.as_ref() {
+            return Some(pool.with(read));
+        }
+        let conn = self.connection.as_ref()?.lock().unwrap_or_else(|e| e.into_inner());
+        Some(read(&conn))
+    }
+
+
+    fn writer(&mut self) -> Option<&mut Connection> {
+        self.connection.as_mut().map(|c| c.get_mut().unwrap_or_else(|e| e.into_inner()))
+    }
+
+
     fn resolve_type_suffix(&self, typeopt: Option<Vec<String>>) -> Result<Vec<String>, MBError> {
         let mut resultv: Vec<String> = Vec::new();
         if let Some(typev) = typeopt {
             for item in typev {
-                let mut buffv: Vec<String> = self.mime.get(item.to_string().as_str())
-                                                .unwrap()
-                                                .iter()
+                let Some(mimer) = self.mime.get(item.as_str()) else {
+                    return Err(MBError::TypeGather(format!("unknown type {item}")));
+                };
+                let mut buffv: Vec<String> = mimer.iter()
                                                 .map(|s| s.to_string())
                                                 .collect();
                 resultv.append(&mut buffv);
@@ -216,6 +297,235 @@ impl MemoBook {
     }
    
 
+    /// Bind a text extractor to a type, replacing any built-in one
+    pub fn register_extractor(&mut self, ftype: &str, extractor: std::sync::Arc<dyn TextExtractor>) {
+        self.extractors.register(ftype, extractor);
+    }
+
+
+    /// Text content of a file: through its type's extractor if there is one, else the raw bytes.
+    /// Archive members are always read raw.
+    pub fn read_text(&self, file: &str) -> Result<String, MBError> {
+        self.extractors.read_text(file)
+    }
+
+
+    /// The database half of a suggestion, for a file already in the archive. The returned work
+    /// reads the files, so run it without holding the book.
+    pub fn suggest_for(&self, file: &str) -> Result<SuggestWork, MBError> {
+        let analytics = LiteAnalyticsQuery::new(&self.info.table);
+        let Some(gathered) = self.read(|conn| Ok((self.search_helper(conn, analytics.form_marks())?, self.search_helper(conn, analytics.form_files())?))) else {
+            return Err(MBError::Suggest("no database connection".to_string()));
+        };
+        let (mut marks, mut corpus): (Vec<String>, Vec<String>) = gathered?;
+        if !corpus.iter().any(|f| f == file) {
+            return Err(MBError::Suggest(format!("{file} is not in the archive")));
+        }
+        marks.retain(|m| !m.is_empty());
+        corpus.retain(|f| f != file);
+        Ok(SuggestWork {
+            file: file.to_string(),
+            marks,
+            corpus,
+            mime: self.mime.clone(),
+            extractors: self.extractors.clone()
+        })
+    }
+
+
+    /// Types of a file by inverse suffix lookup in the mime table, all matching types included.
+    /// The suffix comes from the file name and/or its content, per the detection mode.
+    /// Falls back to the default type, if configured.
+    pub fn resolve_file_types(&self, file: &str) -> Vec<String> {
+        let mut typev: Vec<String> = Vec::new();
+        // the first candidate suffix, in order of trust, that the mime table knows decides the types
+        for suffix in self.info.sniff.suffixes(std::path::Path::new(file)) {
+            typev = self.mime.iter()
+                .filter(|(_, m)| m.contains(&suffix))
+                .map(|(t, _)| t.to_string())
+                .collect();
+            if !typev.is_empty() {
+                break;
+            }
+        }
+        typev.sort();
+        if typev.is_empty() && !self.info.deftype.is_empty() {
+            typev.push(self.info.deftype.to_string());
+        }
+        typev
+    }
+
+
+    /// Split an add-record without types into one add-record per file, typed by resolve_file_types
+    fn type_records(&self, ar: &ModifyAddRecord) -> Result<Vec<ModifyAddRecord>, MBError> {
+        let mut retvec: Vec<ModifyAddRecord> = Vec::new();
+        for file in ar.files.iter() {
+            let typev: Vec<String> = self.resolve_file_types(file);
+            if typev.is_empty() {
+                return Err(MBError::TypeGather(format!("no type found for {file} and no default type set")));
+            }
+            retvec.push(ModifyAddRecord::new(&[file.to_string()], &ar.marks, &typev));
+        }
+        Ok(retvec)
+    }
+
+
+    /// A grep crawler set up for the query. It carries its own copies of the repository, sniffer
+    /// and extractors, so it may be run once the book has been released.
+    pub fn grep_for(&self, req: &impl (for <'a> Queryer<'a>)) -> Result<GrepCrawler, MBError> {
+        let resolvedtypelist: Vec<String> = self.resolve_type_suffix(gather_types(req))?;
+        let marktuple = gather_marks(req)?;
+        let mut fs_searcher: GrepCrawler = GrepCrawler::new();
+        fs_searcher.set_options(CrawlOption::CaseSensitive(req.grepcase())) 
+            .set_options(CrawlOption::FollowLinks(req.greplink()))
+            .set_options(CrawlOption::Repository(self.info.scan.clone()))
+            .set_options(CrawlOption::Sniffer(self.info.sniff.clone()))
+            .set_options(CrawlOption::Extractors(self.extractors.clone()))
+            .set_options(CrawlOption::Archives(self.info.archives))
+            .set_search_terms(marktuple, resolvedtypelist);
+        Ok(fs_searcher)
+    }
+
+
+    /// The database half of a search, merged with the hits of a grep crawl run separately
+    pub fn search_merged(&self, req: impl (for <'a> Queryer<'a>), hits: Vec<String>) -> Result<Vec<String>, MBError> {
+        let mut v = hits;
+        let queryassembler = LiteQueryAssembler::new(&self.info.table, req);
+        /* here, check for complexity(), need an in-code algorithm if complexity is too high
+           (not implemented at this time) */
+        let querystring = queryassembler.form()?;
+        match self.read(|conn| self.search_helper(conn, querystring)) {
+            Some(Ok(mut res)) => { 
+                res.retain(|r| !r.is_empty());
+                v.append(&mut res); 
+            },
+            Some(Err(e)) => { return Err(e); },
+            None => {}
+        }
+        v = rem_dupes!(&v); // don't remove. grep might give same hits as db does.
+        v.sort();
+        Ok(v)
+    }
+
+
+    /// Crawl the repository and checksum its files, and with archives on their members, against
+    /// an export. The database is not touched; the records found are inserted afterward with
+    /// insert_import.
+    pub async fn gather_import(scan: Repository, archives: bool, portinfo: TransPortStruct, progress: Option<Arc<JobProgress>>)
+        -> Result<Vec<ModifyAddRecord>, MBError>
+    {
+        let mut fs_importer: ImportCrawler = ImportCrawler::new();
+        fs_importer.set_options(CrawlOption::FollowLinks(portinfo.links))
+            .set_options(CrawlOption::Transport(portinfo.target))
+            .set_options(CrawlOption::Log(portinfo.log))
+            .set_options(CrawlOption::Repository(scan))
+            .set_options(CrawlOption::Archives(archives));
+        if let Some(p) = progress {
+            fs_importer.set_options(CrawlOption::Progress(p));
+        }
+        fs_importer.crawl().await?;
+        Ok(fs_importer.iter().cloned().collect())
+    }
+
+
+    /// Insert records gathered by gather_import in one transaction; returns a report
+    pub fn insert_import(&mut self, found: Vec<ModifyAddRecord>) -> Result<String, MBError> {
+        if self.connection.is_none() {
+            return Err(MBError::Import("no database connected".to_string()));
+        }
+        // Records exported without types are typed like any other addition; untypeable ones are skipped
+        let mut records: Vec<ModifyAddRecord> = Vec::new();
+        for result in found.iter() {
+            if result.ftypes.is_empty() {
+                records.append(&mut self.type_records(result).unwrap_or_default());
+            } else {
+                records.push(result.clone());
+            }
+        }
+        let count: usize = records.len();
+        let table: String = self.info.table.to_string();
+        // Start a transaction for the database calls, assert the calls, then commit
+        let conn = self.writer().unwrap();
+        let transact = match conn.transaction() {
+            Ok(t) => t,
+            Err(e) => return Err(MBError::Sqlite(e))
+        };
+        for record in records {
+            let cmdobj = Box::new(LiteAddRecord);
+            let cmd: Modifier = Modifier::AddRecord(record);
+            match transact.execute_batch(cmdobj.form(&table, &cmd)?.join(" ").as_str()) {
+                Ok(_) => {},
+                Err(e) => return Err(MBError::BadModify(format!("DB import error: {e}")))
+            }
+        }
+        match transact.commit() {
+            Ok(_) => {},
+            Err(e) => return Err(MBError::Sqlite(e))
+        }
+        Ok(format!("imported {count} records"))
+    }
+
+
+    /// Apply modifications in one transaction, all or nothing: there is a result for each, and
+    /// the batch is committed only if every one succeeded. Each runs under its own savepoint, so
+    /// that the rest are still tried after a failure. Mark and type updates look up the file's
+    /// records within the transaction, and so see the batch's earlier changes.
+    pub fn modify_batch(&mut self, cmds: Vec<Modifier>) -> Result<Vec<Result<(), MBError>>, MBError> {
+        if self.connection.is_none() {
+            return Err(MBError::BadModify("no database connected".to_string()));
+        }
+        // additions without types are typed through the mime table, as by modify
+        let expanded: Vec<Result<Vec<Modifier>, MBError>> = cmds.into_iter()
+            .map(|cmd| match cmd {
+                Modifier::AddRecord(ar) if ar.ftypes.is_empty() => {
+                    self.type_records(&ar).map(|typed| typed.into_iter().map(Modifier::AddRecord).collect())
+                },
+                cmd => Ok(vec![cmd])
+            })
+            .collect();
+        let table: String = self.info.table.to_string();
+        let conn = self.writer().unwrap();
+        let mut transact = conn.transaction().map_err(MBError::Sqlite)?;
+        let results: Vec<Result<(), MBError>> = expanded.into_iter()
+            .map(|item| item.and_then(|cmds| Self::apply_in(&mut transact, &table, cmds)))
+            .collect();
+        if results.iter().all(|r| r.is_ok()) {
+            transact.commit().map_err(MBError::Sqlite)?;
+        }
+        // otherwise the transaction is dropped, and with it every change
+        Ok(results)
+    }
+
+
+    fn apply_in(transact: &mut Transaction, table: &str, cmds: Vec<Modifier>) -> Result<(), MBError> {
+        let savepoint = transact.savepoint().map_err(MBError::Sqlite)?;
+        for mut cmd in cmds {
+            match &mut cmd {
+                Modifier::MarkUpdate(mu) => { mu.aux = lookup_in(&savepoint, table, "type", &mu.file)?; },
+                Modifier::TypeUpdate(tu) => { tu.aux = lookup_in(&savepoint, table, "mark", &tu.file)?; },
+                _ => {}
+            }
+            let statements: Vec<String> = assembler_for(&cmd).form(table, &cmd)?;
+            if let Err(e) = savepoint.execute_batch(statements.join(" ").as_str()) {
+                return Err(MBError::BadModify(format!("DB modification error: {e}")));
+            }
+        }
+        savepoint.commit().map_err(MBError::Sqlite)?;
+        Ok(())
+    }
+
+
+    /// Read what an export will contain. Checksumming the files and writing the log are left
+    /// to the returned logger's write, which does not need the book.
+    pub fn prepare_export(&self, portinfo: &TransPortStruct) -> Result<ExportLogger, MBError> {
+        let mut logger: ExportLogger = ExportLogger::new(&portinfo.log, &self.info.table);
+        match self.read(|conn| logger.prepare(conn)?.collect(conn).map(|_| ())) {
+            Some(r) => r.map(|_| logger),
+            None => Err(MBError::FileSys("no database connected".to_string()))
+        }
+    }
+
+
     fn search_helper(&self, cnx: &Connection, query: String) -> Result<Vec<String>, MBError> {
         let mut v: Vec<String> = Vec::new();
 	    let mut state = match cnx.prepare(query.as_str()) {
@@ -242,6 +552,26 @@ impl MemoBook {
         } 
         Ok(v)
     }
+
+
+    fn count_helper(&self, cnx: &Connection, query: String) -> Result<Vec<(String, usize)>, MBError> {
+        let mut state = match cnx.prepare(query.as_str()) {
+            Ok(x) => x,
+            Err(x) => { return Err(MBError::Sqlite(x)); }
+        };
+        let rows = match state.query_map([], |row| Ok((row.get::<usize, String>(0)?, row.get::<usize, usize>(1)?))) {
+            Ok(x) => x,
+            Err(x) => { return Err(MBError::Sqlite(x)); }
+        };
+        let mut v: Vec<(String, usize)> = Vec::new();
+        for row in rows {
+            match row {
+                Ok(r) => v.push(r),
+                Err(x) => { return Err(MBError::Sqlite(x)); }
+            }
+        }
+        Ok(v)
+    }
  
 }
 
@@ -258,13 +588,14 @@ impl Queryable for MemoBook {
     fn initialize(&mut self) -> Result<(), MBError> {
         if self.connection.is_none() {
             self.connection = match Connection::open(&self.info.src) { 
-                Ok(x) => Some(x),
+                Ok(x) => Some(Mutex::new(x)),
                 Err(x) => { return Err(MBError::Sqlite(x)); } 
             }
         }
         let opener = LiteOpen;
-        let conn = self.connection.as_ref().unwrap();
-        match conn.execute(opener.form_create_table(&self.info.table).as_str(), ())
+        let table: String = self.info.table.to_string();
+        let conn = self.writer().unwrap();
+        match conn.execute(opener.form_create_table(&table).as_str(), ())
         {   Ok(_) => Ok(()),
             Err(x) => Err(MBError::Sqlite(x)) 
         }    
@@ -273,7 +604,7 @@ impl Queryable for MemoBook {
 
     ///Connect to database: open table or create it if it doesn't exist
     fn connect(&mut self, source: Option<String>) -> Result<(), MBError> {
-        self.connection = None;
+        self.disconnect();
         if let Some(newsrc) = source { 
             self.info.src = newsrc.to_string(); 
         }
@@ -286,42 +617,120 @@ impl Queryable for MemoBook {
     
     ///Database searches, implemented with the Queryer trait
     fn search(&self, req: impl (for <'a> Queryer<'a>)) -> Result<Vec<String>, MBError> {
-		let mut v = Vec::new();
+        let mut hits: Vec<String> = Vec::new();
         if req.grep() {
             /********* windows, no grep functionality?, need a macro or something for windows detection *******/
             // maybe require uutils or coreutils for grep...?
-            let resolvedtypelist: Vec<String> = self.resolve_type_suffix(gather_types(&req))?;
-            let marktuple = gather_marks(&req)?;
-            let mut fs_searcher: GrepCrawler = GrepCrawler::new();
-            fs_searcher.set_options(CrawlOption::CaseSensitive(req.grepcase())) 
-                .set_options(CrawlOption::FollowLinks(req.greplink()))
-                .set_options(CrawlOption::Repository(self.info.scan.clone()))
-                .set_search_terms(marktuple, resolvedtypelist);
-            //let now = Instant::now();
-            if let Some(mut resultvec) = fs_searcher.crawl()?.retrieve() {
-                v.append(&mut resultvec);
+            if let Some(mut resultvec) = self.grep_for(&req)?.crawl()?.retrieve() {
+                hits.append(&mut resultvec);
             }
-            //println!("Time elapsed for grep operation: {}", now.elapsed().as_millis());
-        }
-        if let Some(conn) = self.connection.as_ref() {
-            let queryassembler = LiteQueryAssembler::new(&self.info.table, req);
-            /* here, check for complexity(), need an in-code algorithm if complexity is too high
-               (not implemented at this time) */
-            let querystring = queryassembler.form()?;
-            match self.search_helper(conn, querystring) {
-                Ok(mut res) => { 
-                    res.retain(|r| !r.is_empty());
-                    v.append(&mut res); 
-                },
-                Err(e) => { return Err(e); }
+        }
+        self.search_merged(req, hits)
+    }
+
+
+    ///Scored searches: files ranked by the number of marks they match. Hits found by both the
+    ///database and the grep crawl receive a bonus. Results are sorted by descending score.
+    fn search_scored(&self, req: impl (for <'a> Queryer<'a>)) -> Result<Vec<(String, usize)>, MBError> {
+        if req.equality() != "file" {
+            return Err(MBError::BadQuery("scored searches must return files".to_string()));
+        }
+        let mut marktuple = gather_marks(&req)?;
+        // the AND path counts distinct marks, so a repeated mark would never be matched
+        marktuple.1.sort();
+        marktuple.1.dedup();
+        let mut scores: HashMap<String, (usize, usize)> = HashMap::new();
+        if req.grep() {
+            if let Some(counted) = self.grep_for(&req)?.crawl()?.retrieve_counted() {
+                for (file, count) in counted {
+                    scores.entry(file).or_default().1 = count;
+                }
             }
         }
-        v = rem_dupes!(&v); // don't remove. grep might give same hits as db does.
-        v.sort();
+        let scorequery = LiteScoreQuery::new(&self.info.table);
+        let typeopt = gather_types(&req);
+        let fileopt = gather_files(&req);
+        let querystring = scorequery.form(&marktuple, typeopt.as_deref(), fileopt.as_deref());
+        if let Some(counted) = self.read(|conn| self.count_helper(conn, querystring)) {
+            for (file, count) in counted? {
+                if !file.is_empty() {
+                    scores.entry(file).or_default().0 = count;
+                }
+            }
+        }
+        let mut v: Vec<(String, usize)> = scores.into_iter()
+            .map(|(file, (dbcount, grepcount))| {
+                let bonus = if dbcount > 0 && grepcount > 0 { CONCURRENCE_BONUS } else { 0 };
+                (file, dbcount + grepcount + bonus)
+            })
+            .collect();
+        v.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
         Ok(v)
     }
 
 
+    ///Mark co-occurrence: the marks most often found on the files carrying all the given marks
+    fn related(&self, marks: &[String], limit: usize) -> Result<Vec<(String, usize)>, MBError> {
+        if marks.is_empty() {
+            return Err(MBError::MarkGather("no marks given for co-occurrence".to_string()));
+        }
+        // co-occurrence counts distinct marks, so a repeated mark would never be matched
+        let mut marks = marks.to_vec();
+        marks.sort();
+        marks.dedup();
+        let analytics = LiteAnalyticsQuery::new(&self.info.table);
+        let Some(counted) = self.read(|conn| self.count_helper(conn, analytics.form_cooccurrence(&marks, limit))) else {
+            return Ok(vec![]);
+        };
+        let mut v = counted?;
+        v.retain(|(mark, _)| !mark.is_empty());
+        Ok(v)
+    }
+
+
+    ///Statistics: counts of records, files, marks and types, the most used marks, configured
+    ///types without records, files per repository root, and the size of the database file
+    fn stats(&self, topn: usize) -> Result<MBStats, MBError> {
+        let mut stats: MBStats = MBStats::new();
+        stats.dbsize = match std::fs::metadata(&self.info.src) {
+            Ok(m) => m.len(),
+            Err(_) => 0
+        };
+        let analytics = LiteAnalyticsQuery::new(&self.info.table);
+        let gathered = self.read(|conn| {
+            let single = |query: String| -> Result<usize, MBError> {
+                Ok(self.count_helper(conn, query)?.first().map(|c| c.1).unwrap_or(0))
+            };
+            stats.records = single(analytics.form_count_records())?;
+            stats.files = single(analytics.form_count("file"))?;
+            stats.marks = single(analytics.form_count("mark"))?;
+            stats.types = single(analytics.form_count("type"))?;
+            stats.top_marks = self.count_helper(conn, analytics.form_top_marks(topn))?;
+            let typesinuse: Vec<String> = self.search_helper(conn, analytics.form_types())?;
+            let mut untagged: Vec<String> = self.mime.keys()
+                .filter(|t| !typesinuse.contains(t))
+                .map(|t| t.to_string())
+                .collect();
+            untagged.sort();
+            stats.untagged_types = untagged;
+            for root in self.info.scan.iter_include() {
+                let rootstr = root.to_str().unwrap_or("").trim_end_matches('/');
+                stats.roots.push((root.to_str().unwrap_or("").to_string(), single(analytics.form_count_under(rootstr))?));
+            }
+            Ok(())
+        });
+        gathered.unwrap_or(Ok(()))?;
+        Ok(stats)
+    }
+
+
+    ///Mark suggestions for a text file in the archive, or one with a text extractor: existing marks found
+    ///in its content, then TF-IDF keywords computed against the other text files in the archive
+    fn suggest(&self, file: &str, limit: usize) -> Result<Vec<Suggestion>, MBError> {
+        self.suggest_for(file)?.run(limit)
+    }
+
+
     /// behavior of FieldReplace:
     ///   o  "field" is chosen by caller, i.e., mark, file, or type
     ///   o  The value pair is (original value, replacement value)
@@ -334,20 +743,28 @@ impl Queryable for MemoBook {
     ///   o  accepts a file name, types to be added, types to be removed
     ///   o  queries database for marks associated with the file name
     ///   o  applies type additions and deletions for the file for all marks
+    /// behavior of AddRecord:
+    ///   o  accepts files, marks, and types, inserting every combination
+    ///   o  if no types are given, each file is typed through the mime table
     fn modify(&mut self, cmd: &Modifier) -> Result<(), MBError> {
-        if let Some(conn) = self.connection.as_mut() {
-            let cmdobj: Box<dyn ModifierAssembler> = match cmd {
-                Modifier::AddRecord(_) => Box::new(LiteAddRecord),
-                Modifier::FieldReplace(_) => Box::new(LiteFieldReplace),
-                Modifier::MarkUpdate(_) => Box::new(LiteMarkUpdate),
-                Modifier::TypeUpdate(_) => Box::new(LiteTypeUpdate),
-                Modifier::TargetRemove(_) => Box::new(LiteTargetRemove)
+        if self.connection.is_some() {
+            let cmdobj: Box<dyn ModifierAssembler> = assembler_for(cmd);
+            let statements: Vec<String> = match cmd {
+                Modifier::AddRecord(ar) if ar.ftypes.is_empty() => {
+                    let mut typedstatements: Vec<String> = Vec::new();
+                    for typed in self.type_records(ar)? {
+                        typedstatements.append(&mut cmdobj.form(&self.info.table, &Modifier::AddRecord(typed))?);
+                    }
+                    typedstatements
+                },
+                _ => cmdobj.form(&self.info.table, cmd)?
             };
+            let conn = self.writer().unwrap();
             let transact = match conn.transaction() {
                 Ok(t) => t,
                 Err(e) => return Err(MBError::Sqlite(e))
             };
-            match transact.execute_batch(cmdobj.form(&self.info.table, cmd)?.join(" ").as_str()) {
+            match transact.execute_batch(statements.join(" ").as_str()) {
                 Ok(_) => {},
                 Err(e) => return Err(MBError::BadModify(format!("DB modification error: {e}")))
             }
@@ -364,63 +781,33 @@ impl Queryable for MemoBook {
     fn target(&mut self, scan: &Repository, mime: &HashMap<String,Mimer>) -> Result<(), MBError> {
         self.info.scan = scan.clone();
         self.mime = mime.clone();
+        self.extractors.retarget(mime);
         Ok(())
     }
 
 
     ///Import memobook db using json export output
     fn import(&mut self, portinfo: TransPortStruct) -> Result<String, MBError> {
-        if let Some(conn) = self.connection.as_mut() {
-            let mut fs_importer: ImportCrawler = ImportCrawler::new();
-            fs_importer.set_options(CrawlOption::FollowLinks(portinfo.links))
-                .set_options(CrawlOption::Transport(portinfo.target))
-                .set_options(CrawlOption::Log(portinfo.log))
-                .set_options(CrawlOption::Repository(self.info.scan.clone()));
-            // Setup the async runtime for use with importcrawlerp, or comment all out
-            let asyncruntime = match tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build() 
-            {
-                Ok(rt) => rt,
-                Err(e) => { return Err(MBError::Import(format!("Async error during import: {e}"))); }
-            };
-            let asyncresult = asyncruntime.block_on(async { fs_importer.crawl().await });
-            // Change the next match line according to whether async is being used or not
-            match asyncresult {
-                Ok(_) => {},
-                Err(e) => { return Err(e); }
-            }
-            ////let start = Instant::now();
-            // Start a transaction for the database calls, assert the calls, then commit
-            let transact = match conn.transaction() {
-                Ok(t) => t,
-                Err(e) => return Err(MBError::Sqlite(e))
-            };
-            for result in fs_importer.iter() {
-                let cmdobj = Box::new(LiteAddRecord);
-                let cmd: Modifier = Modifier::AddRecord(result.clone());
-                match transact.execute_batch(cmdobj.form(&self.info.table, &cmd)?.join(" ").as_str()) {
-                    Ok(_) => {},
-                    Err(e) => return Err(MBError::BadModify(format!("DB import error: {e}")))
-                }
-            }
-            match transact.commit() {
-                Ok(_) => {},
-                Err(e) => return Err(MBError::Sqlite(e))
-            }
-            ////let duration = start.elapsed().as_millis();
-            ////println!("Duration of database insertions: {}", duration);
+        if self.connection.is_none() {
+            return Err(MBError::Import("no database connected".to_string()));
         }
-        Ok("pending".to_string())
+        // Callers that can should run gather_import on their own runtime, without holding the book
+        let asyncruntime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build() 
+        {
+            Ok(rt) => rt,
+            Err(e) => { return Err(MBError::Import(format!("Async error during import: {e}"))); }
+        };
+        let found = asyncruntime.block_on(MemoBook::gather_import(self.info.scan.clone(), self.info.archives, portinfo, None))?;
+        self.insert_import(found)
     }
 
 
     ///Export database entries with file checksums to a json file
     fn export(&self, portinfo: TransPortStruct) -> Result<String, MBError> {
-        if let Some(conn) = self.connection.as_ref() {
-            let mut logger: ExportLogger = ExportLogger::new(&portinfo.log, "bookmarks");
-            logger.prepare(conn)?;
-            logger.dump(conn)?;
+        if self.connection.is_some() {
+            self.prepare_export(&portinfo)?.write(None)?;
         } 
         Ok(format!("export to {} complete",&portinfo.log))   
     }
@@ -428,8 +815,9 @@ impl Queryable for MemoBook {
 
     /// Drop rusqlite connection so that, e.g., a backup may be loaded and file pointers dropped
     fn disconnect(&mut self) {
+        self.readers = None;
         if let Some(conn) = self.connection.take() {
-            match conn.close() {
+            match conn.into_inner().unwrap_or_else(|e| e.into_inner()).close() {
                 Ok(_) => { },
                 Err(_) => {
                     //println!("Error closing connection");
@@ -443,3 +831,393 @@ impl Queryable for MemoBook {
 
 
 }
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::query::Query;
+    use crate::mbfilter::MBFilter;
+    use crate::magic::{DetectMode, MagicTable, Sniffer};
+    use crate::modifiers::{ModifyMarkUpdate, ModifyTargetRemove};
+
+    fn open_test_book(name: &str) -> MemoBook {
+        let dbpath = std::env::temp_dir().join(format!("memobook_test_{name}_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&dbpath);
+        let info = MBInfo {
+            src: dbpath.to_str().unwrap().to_string(),
+            table: "bookmarks".to_string(),
+            scan: Repository::new(),
+            alt: false,
+            deftype: "".to_string(),
+            sniff: Sniffer::default(),
+            archives: false
+        };
+        let mut book = MemoBook::new(&info, &HashMap::new());
+        book.connect(None).unwrap();
+        book
+    }
+
+    fn close_test_book(mut book: MemoBook) {
+        book.disconnect();
+        let _ = std::fs::remove_file(&book.info.src);
+    }
+
+    fn add(book: &mut MemoBook, file: &str, marks: &[&str]) {
+        let marks: Vec<String> = marks.iter().map(|m| m.to_string()).collect();
+        book.modify(&Modifier::AddRecord(ModifyAddRecord::new(&[file.to_string()], &marks, &["Text".to_string()]))).unwrap();
+    }
+
+    #[test]
+    fn test_search_scored_or() {
+        let mut book = open_test_book("scored_or");
+        add(&mut book, "one.txt", &["rust"]);
+        add(&mut book, "all.txt", &["rust", "dbus", "sqlite"]);
+        add(&mut book, "two.txt", &["rust", "dbus"]);
+        let filter = MBFilter::new("mark".to_string(), Logic::OR, vec!["rust".to_string(), "dbus".to_string(), "sqlite".to_string()]);
+        let results = book.search_scored(Query::new(vec![filter], "file", false, false, false)).unwrap();
+        assert_eq!(results, vec![("all.txt".to_string(), 3), ("two.txt".to_string(), 2), ("one.txt".to_string(), 1)]);
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_search_scored_and_repeated_mark() {
+        let mut book = open_test_book("scored_and_repeated");
+        add(&mut book, "one.txt", &["rust"]);
+        add(&mut book, "two.txt", &["rust", "dbus"]);
+        let filter = MBFilter::new("mark".to_string(), Logic::AND, vec!["rust".to_string(), "dbus".to_string(), "rust".to_string()]);
+        let results = book.search_scored(Query::new(vec![filter], "file", false, false, false)).unwrap();
+        assert_eq!(results, vec![("two.txt".to_string(), 2)]);
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_resolve_unknown_type() {
+        let book = open_test_book("unknown_type");
+        match book.resolve_type_suffix(Some(vec!["Removed".to_string()])) {
+            Err(MBError::TypeGather(msg)) => assert_eq!(msg, "unknown type Removed"),
+            other => panic!("expected a TypeGather error, got {other:?}")
+        }
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_modify_batch() {
+        let mut book = open_test_book("batch");
+        book.mime.insert("Text".to_string(), Mimer::new_by_vec(vec!["txt".to_string()]));
+        let marks = |file: &str, book: &MemoBook| {
+            let filter = MBFilter::new("file".to_string(), Logic::OR, vec![file.to_string()]);
+            book.search(Query::new(vec![filter], "mark", false, false, false)).unwrap()
+        };
+        let strs = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        // the update sees the record added before it in the same batch
+        let results = book.modify_batch(vec![
+            Modifier::AddRecord(ModifyAddRecord::new(&strs(&["a.txt"]), &strs(&["rust"]), &[])),
+            Modifier::MarkUpdate(ModifyMarkUpdate::new("a.txt", &[], &strs(&["rust"]), &strs(&["dbus", "sqlite"])))
+        ]).unwrap();
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(marks("a.txt", &book), strs(&["dbus", "sqlite"]));
+        // an untypeable file spoils the whole batch
+        let results = book.modify_batch(vec![
+            Modifier::AddRecord(ModifyAddRecord::new(&strs(&["b.txt"]), &strs(&["rust"]), &[])),
+            Modifier::AddRecord(ModifyAddRecord::new(&strs(&["c.pdf"]), &strs(&["rust"]), &[])),
+            Modifier::TargetRemove(ModifyTargetRemove::new("mark", "dbus"))
+        ]).unwrap();
+        assert_eq!(results.iter().map(|r| r.is_ok()).collect::<Vec<bool>>(), vec![true, false, true]);
+        assert!(marks("b.txt", &book).is_empty());
+        assert_eq!(marks("a.txt", &book), strs(&["dbus", "sqlite"]));
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut book = open_test_book("stats");
+        book.mime.insert("Text".to_string(), Mimer::new_by_vec(vec!["txt".to_string()]));
+        book.mime.insert("PDF".to_string(), Mimer::new_by_vec(vec!["pdf".to_string()]));
+        book.info.scan.add_include("/notes");
+        add(&mut book, "/notes/a.txt", &["rust", "dbus"]);
+        add(&mut book, "/notes/b.txt", &["rust"]);
+        add(&mut book, "/other/c.txt", &["sqlite"]);
+        let stats = book.stats(1).unwrap();
+        assert_eq!(stats.records, 4);
+        assert_eq!(stats.files, 3);
+        assert_eq!(stats.marks, 3);
+        assert_eq!(stats.types, 1);
+        assert_eq!(stats.top_marks, vec![("rust".to_string(), 2)]);
+        assert_eq!(stats.untagged_types, vec!["PDF".to_string()]);
+        assert_eq!(stats.roots, vec![("/notes".to_string(), 2)]);
+        assert!(stats.dbsize > 0);
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let mut book = open_test_book("suggest");
+        book.mime.insert("Text".to_string(), Mimer::new_by_vec(vec!["txt".to_string()]));
+        let notefile = std::env::temp_dir().join(format!("memobook_test_suggest_{}.txt", std::process::id()));
+        std::fs::write(&notefile, "Notes on the doomlike renderer and its raycaster.").unwrap();
+        let notestr = notefile.to_str().unwrap();
+        add(&mut book, "/nonexistent/a.txt", &["raycaster"]);
+        // only files in the archive
+        assert!(book.suggest(notestr, 2).is_err());
+        add(&mut book, notestr, &["draft"]);
+        let suggestions = book.suggest(notestr, 2).unwrap();
+        assert_eq!(suggestions.len(), 2);
+        assert_eq!(suggestions[0].mark, "raycaster");
+        assert!(suggestions[0].existing);
+        assert!(!suggestions[1].existing);
+        assert!(book.suggest("/nonexistent/b.pdf", 2).is_err());
+        let _ = std::fs::remove_file(&notefile);
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_add_infers_types() {
+        let mut book = open_test_book("infer");
+        book.mime.insert("Text".to_string(), Mimer::new_by_vec(vec!["txt".to_string(), "md".to_string()]));
+        book.mime.insert("Notes".to_string(), Mimer::new_by_vec(vec!["md".to_string()]));
+        book.mime.insert("Image".to_string(), Mimer::new_by_vec(vec!["png".to_string()]));
+        let files = vec!["a.txt".to_string(), "b.md".to_string(), "c.PNG".to_string()];
+        book.modify(&Modifier::AddRecord(ModifyAddRecord::new(&files, &["doom".to_string()], &[]))).unwrap();
+        let typesof = |book: &MemoBook, file: &str| {
+            let filter = MBFilter::new("file".to_string(), Logic::OR, vec![file.to_string()]);
+            book.search(Query::new(vec![filter], "type", false, false, false)).unwrap()
+        };
+        assert_eq!(typesof(&book, "a.txt"), vec!["Text".to_string()]);
+        assert_eq!(typesof(&book, "b.md"), vec!["Notes".to_string(), "Text".to_string()]);
+        assert_eq!(typesof(&book, "c.PNG"), vec!["Image".to_string()]);
+        let nofile = vec!["README".to_string()];
+        assert!(book.modify(&Modifier::AddRecord(ModifyAddRecord::new(&nofile, &["doom".to_string()], &[]))).is_err());
+        book.info.deftype = "Text".to_string();
+        book.modify(&Modifier::AddRecord(ModifyAddRecord::new(&nofile, &["doom".to_string()], &[]))).unwrap();
+        assert_eq!(typesof(&book, "README"), vec!["Text".to_string()]);
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_add_sniffs_types() {
+        let mut book = open_test_book("sniff");
+        book.mime.insert("Image".to_string(), Mimer::new_by_vec(vec!["png".to_string()]));
+        book.mime.insert("Code".to_string(), Mimer::new_by_vec(vec!["py".to_string()]));
+        book.mime.insert("Text".to_string(), Mimer::new_by_vec(vec!["txt".to_string()]));
+        let dir = std::env::temp_dir();
+        let png = dir.join(format!("memobook_test_sniff_{}.dat", std::process::id()));
+        let script = dir.join(format!("memobook_test_sniff_{}", std::process::id()));
+        std::fs::write(&png, b"\x89PNG\r\n\x1a\n0000").unwrap();
+        std::fs::write(&script, b"#!/usr/bin/python3\nprint('doom')\n").unwrap();
+        let pngstr = png.to_str().unwrap().to_string();
+        let scriptstr = script.to_str().unwrap().to_string();
+        assert!(book.resolve_file_types(&pngstr).is_empty());
+        book.info.sniff = Sniffer::new(DetectMode::Combined, MagicTable::new());
+        assert_eq!(book.resolve_file_types(&pngstr), vec!["Image".to_string()]);
+        assert_eq!(book.resolve_file_types(&scriptstr), vec!["Code".to_string()]);
+        let _ = std::fs::remove_file(&png);
+        let _ = std::fs::remove_file(&script);
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_grep_extracted() {
+        use std::io::Write;
+        let mut book = open_test_book("grepextract");
+        let dir = std::env::temp_dir().join(format!("memobook_test_grepextract_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        {
+            let mut zw = zip::ZipWriter::new(std::fs::File::create(dir.join("a.odt")).unwrap());
+            zw.start_file("content.xml", zip::write::SimpleFileOptions::default()).unwrap();
+            zw.write_all(b"<office:text><text:p>The Raycaster notes</text:p></office:text>").unwrap();
+            zw.finish().unwrap();
+        }
+        std::fs::write(dir.join("b.txt"), "raycaster").unwrap();
+        std::fs::write(dir.join("c.txt"), "renderer").unwrap();
+        let mut mime: HashMap<String, Mimer> = HashMap::new();
+        mime.insert("Docs".to_string(), Mimer::new_by_vec(vec!["odt".to_string(), "txt".to_string()]));
+        let mut scan = Repository::new();
+        scan.add_include(dir.to_str().unwrap());
+        book.target(&scan, &mime).unwrap();
+        let filters = vec![
+            MBFilter::new("mark".to_string(), Logic::OR, vec!["raycaster".to_string()]),
+            MBFilter::new("type".to_string(), Logic::OR, vec!["Docs".to_string()])
+        ];
+        let mut results = book.search(Query::new(filters, "file", true, false, false)).unwrap();
+        results.sort();
+        let _ = std::fs::remove_dir_all(&dir);
+        let expected: Vec<String> = ["a.odt", "b.txt"].iter().map(|f| dir.join(f).to_str().unwrap().to_string()).collect();
+        assert_eq!(results, expected);
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_grep_archive_members() {
+        use std::io::Write;
+        let mut book = open_test_book("greparchive");
+        let dir = std::env::temp_dir().join(format!("memobook_test_greparchive_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let zippath = dir.join("notes.zip");
+        {
+            let mut zw = zip::ZipWriter::new(std::fs::File::create(&zippath).unwrap());
+            zw.start_file("inner/a.txt", zip::write::SimpleFileOptions::default()).unwrap();
+            zw.write_all(b"raycaster").unwrap();
+            zw.start_file("inner/b.png", zip::write::SimpleFileOptions::default()).unwrap();
+            zw.write_all(b"raycaster").unwrap();
+            zw.finish().unwrap();
+        }
+        let mut mime: HashMap<String, Mimer> = HashMap::new();
+        mime.insert("Text".to_string(), Mimer::new_by_vec(vec!["txt".to_string()]));
+        let mut scan = Repository::new();
+        scan.add_include(dir.to_str().unwrap());
+        book.target(&scan, &mime).unwrap();
+        let filters = vec![
+            MBFilter::new("mark".to_string(), Logic::OR, vec!["raycaster".to_string()]),
+            MBFilter::new("type".to_string(), Logic::OR, vec!["Text".to_string()])
+        ];
+        assert!(book.search(Query::new(filters.clone(), "file", true, false, false)).unwrap().is_empty());
+        book.info.archives = true;
+        let results = book.search(Query::new(filters, "file", true, false, false)).unwrap();
+        let member = archive::member_path(zippath.to_str().unwrap(), "inner/a.txt");
+        assert_eq!(results, vec![member.to_string()]);
+        assert_eq!(book.resolve_file_types(&member), vec!["Text".to_string()]);
+        assert_eq!(book.read_text(&member).unwrap(), "raycaster");
+        let _ = std::fs::remove_dir_all(&dir);
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_related() {
+        let mut book = open_test_book("related");
+        add(&mut book, "a.txt", &["rust", "dbus", "async"]);
+        add(&mut book, "b.txt", &["rust", "dbus"]);
+        add(&mut book, "c.txt", &["rust", "async", "sqlite"]);
+        add(&mut book, "d.txt", &["python", "async"]);
+        let related = book.related(&["rust".to_string()], 10).unwrap();
+        assert_eq!(related, vec![("async".to_string(), 2), ("dbus".to_string(), 2), ("sqlite".to_string(), 1)]);
+        let related = book.related(&["rust".to_string(), "dbus".to_string()], 10).unwrap();
+        assert_eq!(related, vec![("async".to_string(), 1)]);
+        let related = book.related(&["rust".to_string(), "dbus".to_string(), "rust".to_string()], 10).unwrap();
+        assert_eq!(related, vec![("async".to_string(), 1)]);
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_concurrent_searches() {
+        let mut book = open_test_book("concurrent");
+        add(&mut book, "a.txt", &["rust", "dbus"]);
+        add(&mut book, "b.txt", &["rust"]);
+        assert_eq!(book.readers.as_ref().map(|p| p.size()), Some(DEFAULT_READERS));
+        let shared: &MemoBook = &book;
+        std::thread::scope(|scope| {
+            let searches: Vec<_> = (0..8).map(|_| scope.spawn(move || {
+                let filter = MBFilter::new("mark".to_string(), Logic::OR, vec!["rust".to_string()]);
+                shared.search(Query::new(vec![filter], "file", false, false, false)).unwrap()
+            })).collect();
+            for search in searches {
+                assert_eq!(search.join().unwrap(), vec!["a.txt".to_string(), "b.txt".to_string()]);
+            }
+        });
+        add(&mut book, "c.txt", &["rust"]);
+        assert_eq!(book.stats(1).unwrap().files, 3);
+        close_test_book(book);
+    }
+
+    #[test]
+    fn test_export_import_progress() {
+        let mut book = open_test_book("jobexport");
+        let dir = std::env::temp_dir().join(format!("memobook_test_jobexport_{}", std::process::id()));
+        let _ = std::fs::create_dir_all(&dir);
+        let one = dir.join("one.txt");
+        let two = dir.join("two.txt");
+        std::fs::write(&one, "first").unwrap();
+        std::fs::write(&two, "second").unwrap();
+        add(&mut book, one.to_str().unwrap(), &["rust"]);
+        add(&mut book, two.to_str().unwrap(), &["dbus"]);
+        let log = dir.join("export.json").to_str().unwrap().to_string();
+        let port = TransPortStruct { target: "".to_string(), log: log.to_string(), links: false };
+        let progress = JobProgress::new();
+        assert_eq!(book.prepare_export(&port).unwrap().write(Some(&progress)).unwrap(), 2);
+        assert_eq!(progress.snapshot(), (2, 2, 2));
+        close_test_book(book);
+
+        let mut book = open_test_book("jobimport");
+        book.info.scan.add_include(dir.to_str().unwrap());
+        let port = TransPortStruct { target: log, log: dir.join("import.log").to_str().unwrap().to_string(), links: false };
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let progress = Arc::new(JobProgress::new());
+        let found = runtime.block_on(MemoBook::gather_import(book.info.scan.clone(), false, port.clone(), Some(progress.clone()))).unwrap();
+        // the export and the freshly created import log are walked and hashed too
+        assert_eq!(progress.snapshot(), (4, 4, 2));
+        assert_eq!(book.insert_import(found).unwrap(), "imported 2 records");
+        let cancelled = Arc::new(JobProgress::new());
+        cancelled.cancel();
+        assert!(matches!(runtime.block_on(MemoBook::gather_import(book.info.scan.clone(), false, port, Some(cancelled))), Err(MBError::Cancelled(_))));
+        close_test_book(book);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_export_import_archive_member() {
+        use std::io::Write;
+        let mut book = open_test_book("memberexport");
+        let dir = std::env::temp_dir().join(format!("memobook_test_memberexport_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("new")).unwrap();
+        let zippath = dir.join("notes.zip");
+        {
+            let mut zw = zip::ZipWriter::new(std::fs::File::create(&zippath).unwrap());
+            zw.start_file("inner/a.txt", zip::write::SimpleFileOptions::default()).unwrap();
+            zw.write_all(b"raycaster").unwrap();
+            zw.finish().unwrap();
+        }
+        add(&mut book, &archive::member_path(zippath.to_str().unwrap(), "inner/a.txt"), &["rust"]);
+        let log = dir.join("export.json").to_str().unwrap().to_string();
+        let port = TransPortStruct { target: "".to_string(), log: log.to_string(), links: false };
+        assert_eq!(book.prepare_export(&port).unwrap().write(None).unwrap(), 1);
+        close_test_book(book);
+        let moved = dir.join("new").join("moved.zip");
+        std::fs::rename(&zippath, &moved).unwrap();
+
+        let mut book = open_test_book("memberimport");
+        book.info.scan.add_include(dir.join("new").to_str().unwrap());
+        let port = TransPortStruct { target: log, log: dir.join("import.log").to_str().unwrap().to_string(), links: false };
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        // members are only hashed with archives on
+        let found = runtime.block_on(MemoBook::gather_import(book.info.scan.clone(), false, port.clone(), None)).unwrap();
+        assert!(found.is_empty());
+        let found = runtime.block_on(MemoBook::gather_import(book.info.scan.clone(), true, port, None)).unwrap();
+        assert_eq!(book.insert_import(found).unwrap(), "imported 1 records");
+        let filter = MBFilter::new("mark".to_string(), Logic::OR, vec!["rust".to_string()]);
+        let member = archive::member_path(moved.to_str().unwrap(), "inner/a.txt");
+        assert_eq!(book.search(Query::new(vec![filter], "file", false, false, false)).unwrap(), vec![member]);
+        close_test_book(book);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_import_moved_file() {
+        let mut book = open_test_book("moveexport");
+        let dir = std::env::temp_dir().join(format!("memobook_test_moveexport_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("new")).unwrap();
+        let old = dir.join("old.txt");
+        std::fs::write(&old, "moved about").unwrap();
+        add(&mut book, old.to_str().unwrap(), &["rust"]);
+        let log = dir.join("export.json").to_str().unwrap().to_string();
+        let port = TransPortStruct { target: "".to_string(), log: log.to_string(), links: false };
+        assert_eq!(book.prepare_export(&port).unwrap().write(None).unwrap(), 1);
+        close_test_book(book);
+        let moved = dir.join("new").join("renamed.txt");
+        std::fs::rename(&old, &moved).unwrap();
+
+        let mut book = open_test_book("moveimport");
+        book.info.scan.add_include(dir.join("new").to_str().unwrap());
+        let port = TransPortStruct { target: log, log: dir.join("import.log").to_str().unwrap().to_string(), links: false };
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let found = runtime.block_on(MemoBook::gather_import(book.info.scan.clone(), false, port, None)).unwrap();
+        assert_eq!(book.insert_import(found).unwrap(), "imported 1 records");
+        let filter = MBFilter::new("mark".to_string(), Logic::OR, vec!["rust".to_string()]);
+        assert_eq!(book.search(Query::new(vec![filter], "file", false, false, false)).unwrap(), vec![moved.to_str().unwrap().to_string()]);
+        close_test_book(book);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+}
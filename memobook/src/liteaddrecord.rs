@@ -61,7 +61,7 @@ mod tests {
     #[test]
     fn test_add() {
         let container: ModifyAddRecord = ModifyAddRecord::new(
-            "linux_pros.txt", 
+            &["linux_pros.txt".to_string()],
             &vec![
                 "grub".to_string(), 
                 "grep".to_string(), 
@@ -90,7 +90,7 @@ mod tests {
     #[test]
     fn test_add_w_types() {
         let container: ModifyAddRecord = ModifyAddRecord::new(
-            "linux_pros.txt", 
+            &["linux_pros.txt".to_string()],
             &vec![
                 "grub".to_string(), 
                 "grep".to_string(), 
@@ -0,0 +1,105 @@
+//  liteanalyticsquery.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+use crate::litequeryassembler::lite_query_assembler::quoted_list;
+
+
+pub struct LiteAnalyticsQuery {
+    table: String
+}
+
+
+impl LiteAnalyticsQuery {
+
+    pub fn new(tablenm: &str) -> LiteAnalyticsQuery {
+        LiteAnalyticsQuery {
+            table: tablenm.to_string()
+        }
+    }
+
+    /// Marks found on the files carrying every one of the given marks, with the number of
+    /// such files per mark, most frequent first.
+    pub fn form_cooccurrence(&self, marks: &[String], limit: usize) -> String {
+        format!("select mark, count(distinct file) as hits from {0} where file in \
+            (select file from {0} where mark in ({1}) group by file having count(distinct mark) = {2}) \
+            and mark not in ({1}) group by mark order by hits desc, mark limit {3};",
+            self.table, quoted_list(marks), marks.len(), limit)
+    }
+
+    pub fn form_count(&self, column: &str) -> String {
+        format!("select '{column}', count(distinct {column}) from {};", self.table)
+    }
+
+    pub fn form_count_records(&self) -> String {
+        format!("select 'records', count(*) from {};", self.table)
+    }
+
+    /// Marks by the number of files carrying them, most used first
+    pub fn form_top_marks(&self, limit: usize) -> String {
+        format!("select mark, count(distinct file) as hits from {} group by mark order by hits desc, mark limit {limit};", self.table)
+    }
+
+    /// Files under a directory root, by path prefix
+    pub fn form_count_under(&self, root: &str) -> String {
+        format!("select '{root}', count(distinct file) from {} where file like '{root}/%';", self.table)
+    }
+
+    pub fn form_types(&self) -> String {
+        format!("select distinct type from {};", self.table)
+    }
+
+    pub fn form_marks(&self) -> String {
+        format!("select distinct mark from {};", self.table)
+    }
+
+    pub fn form_files(&self) -> String {
+        format!("select distinct file from {};", self.table)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_form_cooccurrence() {
+        let former = LiteAnalyticsQuery::new("bookmarks");
+        assert_eq!(former.form_cooccurrence(&["rust".to_string(), "dbus".to_string()], 5),
+            "select mark, count(distinct file) as hits from bookmarks where file in \
+            (select file from bookmarks where mark in ('rust', 'dbus') group by file having count(distinct mark) = 2) \
+            and mark not in ('rust', 'dbus') group by mark order by hits desc, mark limit 5;".to_string());
+    }
+
+    #[test]
+    fn test_form_top_marks() {
+        let former = LiteAnalyticsQuery::new("bookmarks");
+        assert_eq!(former.form_top_marks(3),
+            "select mark, count(distinct file) as hits from bookmarks group by mark order by hits desc, mark limit 3;".to_string());
+    }
+
+}
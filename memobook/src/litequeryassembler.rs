@@ -51,6 +51,16 @@ where
 }
 
 
+/// Terms as a quoted, comma-separated sql list, for the "in (...)" clauses of the other queries
+#[inline]
+pub(crate) fn quoted_list(terms: &[String]) -> String {
+    terms.iter()
+        .map(|t| format!("\'{t}\'"))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+
 fn process_query_string<Q>(filt: &Q, equalcol: &str) -> Result<String, MBError>
 where
     Q: FilterContainer
@@ -0,0 +1,85 @@
+//  litescorequery.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+use crate::logic::Logic;
+use crate::litequeryassembler::lite_query_assembler::quoted_list;
+
+
+pub struct LiteScoreQuery {
+    table: String
+}
+
+
+impl LiteScoreQuery {
+
+    pub fn new(tablenm: &str) -> LiteScoreQuery {
+        LiteScoreQuery {
+            table: tablenm.to_string()
+        }
+    }
+
+    /// Count the distinct marks matched per file. For AND logic, only files matching every
+    /// mark are returned. Types and files, if given, restrict the records considered.
+    pub fn form(&self, marks: &(Logic, Vec<String>), types: Option<&[String]>, files: Option<&[String]>) -> String {
+        let mut buildstr: String = format!("select file, count(distinct mark) from {} where mark in ({})", self.table, quoted_list(&marks.1));
+        if let Some(typev) = types {
+            buildstr += format!(" and type in ({})", quoted_list(typev)).as_str();
+        }
+        if let Some(filev) = files {
+            buildstr += format!(" and file in ({})", quoted_list(filev)).as_str();
+        }
+        buildstr += " group by file";
+        if marks.0 == Logic::AND {
+            buildstr += format!(" having count(distinct mark) = {}", marks.1.len()).as_str();
+        }
+        buildstr + ";"
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_form_or() {
+        let former = LiteScoreQuery::new("bookmarks");
+        let marks = (Logic::OR, vec!["rust".to_string(), "dbus".to_string()]);
+        assert_eq!(former.form(&marks, None, None),
+            "select file, count(distinct mark) from bookmarks where mark in ('rust', 'dbus') group by file;".to_string());
+    }
+
+    #[test]
+    fn test_form_and_w_types() {
+        let former = LiteScoreQuery::new("bookmarks");
+        let marks = (Logic::AND, vec!["rust".to_string(), "dbus".to_string()]);
+        let types = vec!["Text".to_string()];
+        assert_eq!(former.form(&marks, Some(&types), None),
+            "select file, count(distinct mark) from bookmarks where mark in ('rust', 'dbus') and type in ('Text') group by file having count(distinct mark) = 2;".to_string());
+    }
+
+}
@@ -0,0 +1,411 @@
+//  magic.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+/********************************************
+   Magic-number type detection. The sniffer
+    reports candidate file suffixes for a
+    file, which the mime table then maps to
+    types, so content and extension detection
+    share one lookup.
+*********************************************/
+
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use json::object;
+use crate::archive;
+
+
+/// Bytes read from the head of a file for detection
+const SNIFF_LENGTH: usize = 512;
+
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub enum DetectMode {
+    #[default]
+    Suffix,
+    Magic,
+    Combined
+}
+
+
+impl DetectMode {
+
+    pub fn from_name(name: &str) -> Option<DetectMode> {
+        match name {
+            "suffix" => Some(DetectMode::Suffix),
+            "magic" => Some(DetectMode::Magic),
+            "combined" => Some(DetectMode::Combined),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            DetectMode::Suffix => "suffix",
+            DetectMode::Magic => "magic",
+            DetectMode::Combined => "combined"
+        }
+    }
+
+}
+
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct MagicSignature {
+    pub suffix: String,
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+    pub weak: bool      // short or shared by many formats, so a known extension goes first
+}
+
+
+impl MagicSignature {
+
+    pub fn new(suffix: &str, offset: usize, bytes: &[u8]) -> MagicSignature {
+        MagicSignature { suffix: suffix.to_string(), offset, bytes: bytes.to_vec(), weak: false }
+    }
+
+    pub fn weak(suffix: &str, offset: usize, bytes: &[u8]) -> MagicSignature {
+        MagicSignature { weak: true, ..MagicSignature::new(suffix, offset, bytes) }
+    }
+
+    pub fn matches(&self, head: &[u8]) -> bool {
+        head.len() >= self.offset + self.bytes.len()
+            && head[self.offset..self.offset + self.bytes.len()] == self.bytes[..]
+    }
+
+}
+
+
+fn builtin_signatures() -> Vec<MagicSignature> {
+    vec![
+        MagicSignature::new("png", 0, b"\x89PNG\r\n\x1a\n"),
+        MagicSignature::new("jpg", 0, b"\xff\xd8\xff"),
+        MagicSignature::new("gif", 0, b"GIF87a"),
+        MagicSignature::new("gif", 0, b"GIF89a"),
+        MagicSignature::new("tiff", 0, b"II*\x00"),
+        MagicSignature::new("tiff", 0, b"MM\x00*"),
+        MagicSignature::new("psd", 0, b"8BPS"),
+        MagicSignature::new("ico", 0, b"\x00\x00\x01\x00"),
+        MagicSignature::new("webp", 8, b"WEBP"),
+        MagicSignature::weak("bmp", 0, b"BM"),
+        MagicSignature::new("pdf", 0, b"%PDF-"),
+        MagicSignature::new("ps", 0, b"%!PS"),
+        MagicSignature::new("rtf", 0, b"{\\rtf"),
+        MagicSignature::weak("zip", 0, b"PK\x03\x04"),
+        MagicSignature::new("gz", 0, b"\x1f\x8b"),
+        MagicSignature::new("bz2", 0, b"BZh"),
+        MagicSignature::new("xz", 0, b"\xfd7zXZ\x00"),
+        MagicSignature::new("7z", 0, b"7z\xbc\xaf\x27\x1c"),
+        MagicSignature::new("tar", 257, b"ustar"),
+        MagicSignature::new("ogg", 0, b"OggS"),
+        MagicSignature::new("flac", 0, b"fLaC"),
+        MagicSignature::new("mp3", 0, b"ID3"),
+        MagicSignature::new("wav", 8, b"WAVE"),
+        MagicSignature::new("mp4", 4, b"ftyp"),
+        MagicSignature::new("db", 0, b"SQLite format 3\x00"),
+        MagicSignature::weak("xml", 0, b"<?xml")
+    ]
+}
+
+
+/// Suffix for a script's interpreter, from its #! line
+fn shebang_suffix(head: &[u8]) -> Option<String> {
+    if !head.starts_with(b"#!") {
+        return None;
+    }
+    let line: String = String::from_utf8_lossy(&head[2..]).lines().next()?.to_string();
+    let mut words = line.split_whitespace();
+    let mut interpreter: &str = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|w| !w.starts_with('-'))?;
+    }
+    let suffix = match interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "python" => "py",
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => "sh",
+        "perl" => "pl",
+        "ruby" => "rb",
+        "node" => "js",
+        "lua" => "lua",
+        _ => { return None; }
+    };
+    Some(suffix.to_string())
+}
+
+
+/// Plain text: valid UTF-8 (allowing a cut-off final character) with no NUL bytes
+fn looks_textual(head: &[u8]) -> bool {
+    if head.is_empty() || head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none() && head.len() - e.valid_up_to() < 4
+    }
+}
+
+
+/// The first SNIFF_LENGTH bytes of a file or archive member
+fn read_head(path: &Path) -> Option<Vec<u8>> {
+    let mut head: Vec<u8> = Vec::with_capacity(SNIFF_LENGTH);
+    if archive::split_member(path.to_str()?).is_some() {
+        head = archive::read_member(path.to_str()?).ok()?;
+        head.truncate(SNIFF_LENGTH);
+    } else {
+        let file = File::open(path).ok()?;
+        file.take(SNIFF_LENGTH as u64).read_to_end(&mut head).ok()?;
+    }
+    Some(head)
+}
+
+
+/// True if the start of the file reads as plain text
+pub fn is_text(path: &Path) -> bool {
+    read_head(path).is_some_and(|head| looks_textual(&head))
+}
+
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Detection {
+    pub suffix: String,
+    pub strong: bool    // false when only judged to be text, or from a weak signature
+}
+
+
+#[derive(Clone)]
+pub struct MagicTable {
+    builtin: Vec<MagicSignature>,
+    user: Vec<MagicSignature>
+}
+
+
+impl Default for MagicTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl MagicTable {
+
+    pub fn new() -> MagicTable {
+        MagicTable { builtin: builtin_signatures(), user: Vec::new() }
+    }
+
+    pub fn add(&mut self, sig: MagicSignature) -> &mut MagicTable {
+        self.user.push(sig);
+        self
+    }
+
+    pub fn user_signatures(&self) -> &Vec<MagicSignature> {
+        &self.user
+    }
+
+    /// User signatures are checked before built-in ones, then scripts, then plain text
+    pub fn detect_bytes(&self, head: &[u8]) -> Option<Detection> {
+        for sig in self.user.iter().chain(self.builtin.iter()) {
+            if sig.matches(head) {
+                return Some(Detection { suffix: sig.suffix.to_string(), strong: !sig.weak });
+            }
+        }
+        if let Some(suffix) = shebang_suffix(head) {
+            return Some(Detection { suffix, strong: true });
+        }
+        if looks_textual(head) {
+            return Some(Detection { suffix: "txt".to_string(), strong: false });
+        }
+        None
+    }
+
+    pub fn detect(&self, path: &Path) -> Option<Detection> {
+        self.detect_bytes(&read_head(path)?)
+    }
+
+    /// Read user signatures from the "magic" array: {"suffix": "x", "offset": 0, "bytes": "hex"}
+    pub fn read(&mut self, source: &json::JsonValue) -> Result<(), String> {
+        self.user.clear();
+        let json::JsonValue::Array(sigs) = source else {
+            return Ok(());
+        };
+        for jsig in sigs {
+            let Some(suffix) = jsig["suffix"].as_str() else {
+                return Err("Parse error on magic signature suffix".to_string());
+            };
+            let offset: usize = jsig["offset"].as_usize().unwrap_or(0);
+            let Some(hex) = jsig["bytes"].as_str() else {
+                return Err("Parse error on magic signature bytes".to_string());
+            };
+            if hex.is_empty() || !hex.len().is_multiple_of(2) {
+                return Err(format!("Parse error on magic signature bytes [{hex}]"));
+            }
+            let mut bytes: Vec<u8> = Vec::new();
+            for i in (0..hex.len()).step_by(2) {
+                match u8::from_str_radix(&hex[i..i+2], 16) {
+                    Ok(b) => bytes.push(b),
+                    Err(_) => { return Err(format!("Parse error on magic signature bytes [{hex}]")); }
+                }
+            }
+            self.user.push(MagicSignature::new(suffix, offset, &bytes));
+        }
+        Ok(())
+    }
+
+    pub fn write(&self) -> json::JsonValue {
+        let mut jsigs = json::JsonValue::new_array();
+        for sig in self.user.iter() {
+            let hex: String = sig.bytes.iter().map(|b| format!("{b:02x}")).collect();
+            let _ = jsigs.push(object!{ suffix: sig.suffix.as_str(), offset: sig.offset, bytes: hex });
+        }
+        jsigs
+    }
+
+}
+
+
+/// Candidate suffixes for a file, per the detection mode, most trusted first
+#[derive(Clone, Default)]
+pub struct Sniffer {
+    pub mode: DetectMode,
+    pub magic: MagicTable
+}
+
+
+impl Sniffer {
+
+    pub fn new(mode: DetectMode, magic: MagicTable) -> Sniffer {
+        Sniffer { mode, magic }
+    }
+
+    pub fn suffixes(&self, path: &Path) -> Vec<String> {
+        self.rank(path, || self.magic.detect(path))
+    }
+
+    /// As suffixes, for content already in memory, e.g. an archive member
+    pub fn suffixes_of_bytes(&self, path: &Path, content: &[u8]) -> Vec<String> {
+        self.rank(path, || self.magic.detect_bytes(&content[..content.len().min(SNIFF_LENGTH)]))
+    }
+
+    fn rank<F>(&self, path: &Path, detect: F) -> Vec<String>
+    where F: FnOnce() -> Option<Detection>
+    {
+        let extension: Option<String> = path.extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_lowercase());
+        match self.mode {
+            DetectMode::Suffix => extension.into_iter().collect(),
+            DetectMode::Magic => detect().map(|d| d.suffix).into_iter().collect(),
+            DetectMode::Combined => {
+                let mut retvec: Vec<String> = Vec::new();
+                let detection: Option<Detection> = detect();
+                if let Some(d) = detection.as_ref().filter(|d| d.strong) {
+                    retvec.push(d.suffix.to_string());
+                }
+                if let Some(ext) = extension {
+                    if !retvec.contains(&ext) {
+                        retvec.push(ext);
+                    }
+                }
+                if let Some(d) = detection.filter(|d| !d.strong) {
+                    if !retvec.contains(&d.suffix) {
+                        retvec.push(d.suffix);
+                    }
+                }
+                retvec
+            }
+        }
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_detect_builtin() {
+        let table = MagicTable::new();
+        assert_eq!(table.detect_bytes(b"\x89PNG\r\n\x1a\n....").unwrap().suffix, "png");
+        assert_eq!(table.detect_bytes(b"%PDF-1.7\n").unwrap().suffix, "pdf");
+        let mut tar: Vec<u8> = vec![0; 300];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(table.detect_bytes(&tar).unwrap().suffix, "tar");
+        assert!(table.detect_bytes(b"\x00\x01\x02\x03").is_none());
+    }
+
+    #[test]
+    fn test_detect_scripts_and_text() {
+        let table = MagicTable::new();
+        assert_eq!(table.detect_bytes(b"#!/usr/bin/env python3\nprint()").unwrap(),
+            Detection { suffix: "py".to_string(), strong: true });
+        assert_eq!(table.detect_bytes(b"#!/bin/bash\necho").unwrap().suffix, "sh");
+        assert_eq!(table.detect_bytes("Read me first. Ünïcode ok.".as_bytes()).unwrap(),
+            Detection { suffix: "txt".to_string(), strong: false });
+    }
+
+    #[test]
+    fn test_user_signatures() {
+        let mut table = MagicTable::new();
+        let jsigs = json::parse(r#"[{"suffix": "mbk", "offset": 2, "bytes": "4d424b"}]"#).unwrap();
+        table.read(&jsigs).unwrap();
+        assert_eq!(table.detect_bytes(b"..MBK").unwrap().suffix, "mbk");
+        assert_eq!(table.write().dump(), r#"[{"suffix":"mbk","offset":2,"bytes":"4d424b"}]"#);
+        assert!(table.read(&json::parse(r#"[{"suffix": "x", "bytes": "4d4"}]"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_sniffer_modes() {
+        let misnamed = std::env::temp_dir().join(format!("memobook_test_magic_{}.jpg", std::process::id()));
+        std::fs::write(&misnamed, b"\x89PNG\r\n\x1a\n0000").unwrap();
+        let suffixonly = Sniffer::new(DetectMode::Suffix, MagicTable::new());
+        let magiconly = Sniffer::new(DetectMode::Magic, MagicTable::new());
+        let combined = Sniffer::new(DetectMode::Combined, MagicTable::new());
+        assert_eq!(suffixonly.suffixes(&misnamed), vec!["jpg".to_string()]);
+        assert_eq!(magiconly.suffixes(&misnamed), vec!["png".to_string()]);
+        assert_eq!(combined.suffixes(&misnamed), vec!["png".to_string(), "jpg".to_string()]);
+        let _ = std::fs::remove_file(&misnamed);
+    }
+
+    #[test]
+    fn test_weak_signatures() {
+        let combined = Sniffer::new(DetectMode::Combined, MagicTable::new());
+        let magiconly = Sniffer::new(DetectMode::Magic, MagicTable::new());
+        let suffixes = |sniffer: &Sniffer, name: &str, content: &[u8]| sniffer.suffixes_of_bytes(Path::new(name), content);
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        assert_eq!(suffixes(&combined, "notes.txt", b"BMW service log"), strings(&["txt", "bmp"]));
+        assert_eq!(suffixes(&combined, "logo.svg", b"<?xml version=\"1.0\"?><svg/>"), strings(&["svg", "xml"]));
+        assert_eq!(suffixes(&combined, "report.docx", b"PK\x03\x04\x14\x00"), strings(&["docx", "zip"]));
+        assert_eq!(suffixes(&combined, "letter.odt", b"PK\x03\x04\x14\x00"), strings(&["odt", "zip"]));
+        // with nothing else to go on, the signature still decides
+        assert_eq!(suffixes(&combined, "picture", b"BM\x36\x00\x0c\x00"), strings(&["bmp"]));
+        assert_eq!(suffixes(&magiconly, "report.docx", b"PK\x03\x04\x14\x00"), strings(&["zip"]));
+        // strong signatures still outrank the extension
+        assert_eq!(suffixes(&combined, "photo.txt", b"\x89PNG\r\n\x1a\n0000"), strings(&["png", "txt"]));
+    }
+
+}
@@ -44,6 +44,9 @@ pub enum MBError {
     FileRemError(String),
     Import(String),
     Backup(String),
+    Suggest(String),
+    Extract(String),
+    Cancelled(String),
     Nil
 }
 
@@ -69,6 +72,9 @@ impl fmt::Display for MBError {
             MBError::FileRemError(x) => write!(f, "File deletion attempt error: {x}"),
             MBError::Import(x) => write!(f, "Import error: {x}"),
             MBError::Backup(x) => write!(f, "Error backing up bookmarks: {x}"),
+            MBError::Suggest(x) => write!(f, "Mark suggestion error: {x}"),
+            MBError::Extract(x) => write!(f, "Text extraction error: {x}"),
+            MBError::Cancelled(x) => write!(f, "Cancelled: {x}"),
             MBError::Nil => write!(f, "nil")
         }
     }
@@ -0,0 +1,78 @@
+//  mbstats.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+/********************************************
+   MBStats: summary numbers for an archive,
+    used by clients to decide on pruning and
+    on mark vocabulary cleanup.
+    (Growth over time awaits record timestamps,
+    which the table does not yet have.)
+*********************************************/
+
+
+use json::object;
+
+
+#[derive(Clone,Default)]
+pub struct MBStats {
+    pub records: usize,
+    pub files: usize,
+    pub marks: usize,
+    pub types: usize,
+    pub top_marks: Vec<(String, usize)>,
+    pub untagged_types: Vec<String>,
+    pub roots: Vec<(String, usize)>,
+    pub dbsize: u64
+}
+
+
+impl MBStats {
+
+    pub fn new() -> MBStats {
+        MBStats::default()
+    }
+
+    pub fn to_json(&self) -> json::JsonValue {
+        let mut jtop = json::JsonValue::new_array();
+        for (mark, count) in self.top_marks.iter() {
+            let _ = jtop.push(object!{ mark: mark.as_str(), count: *count });
+        }
+        let mut jroots = json::JsonValue::new_array();
+        for (root, count) in self.roots.iter() {
+            let _ = jroots.push(object!{ root: root.as_str(), files: *count });
+        }
+        object!{
+            records: self.records,
+            files: self.files,
+            marks: self.marks,
+            types: self.types,
+            top_marks: jtop,
+            untagged_types: self.untagged_types.clone(),
+            roots: jroots,
+            dbsize: self.dbsize
+        }
+    }
+
+}
@@ -34,7 +34,7 @@
 
 
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Mimer 
 {
     suffixes: Vec<String>,
@@ -59,6 +59,20 @@ impl Mimer {
         self.suffixes.join(" ").to_string()
     }
 
+    pub fn contains(&self, suffix: &str) -> bool {
+        self.suffixes.iter().any(|s| s.eq_ignore_ascii_case(suffix))
+    }
+
+    pub fn add(&mut self, suffix: &str) {
+        if !self.suffixes.iter().any(|s| s == suffix) {
+            self.suffixes.push(suffix.to_string());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.suffixes.is_empty()
+    }
+
     pub fn rem(&mut self, target: &str) {
         let mut ind: i32 = -1;
         for (i,item) in self.suffixes.iter().enumerate() {
@@ -0,0 +1,107 @@
+//  readpool.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+use crate::mberror::MBError;
+use rusqlite::{Connection, OpenFlags};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+
+/// Read connections opened by default
+pub const DEFAULT_READERS: usize = 4;
+
+/// How long a reader waits on a writer's lock before giving up with SQLITE_BUSY
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+
+/// Read-only connections on the database file, so that searches may run side by side. A
+/// rusqlite Connection may not be shared between threads, so each search borrows one of
+/// its own; the writer keeps the book's connection.
+pub struct ReadPool {
+    conns: Vec<Mutex<Connection>>,
+    next: AtomicUsize
+}
+
+
+impl ReadPool {
+
+    pub fn open(src: &str, size: usize) -> Result<ReadPool, MBError> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_URI;
+        let mut conns: Vec<Mutex<Connection>> = Vec::new();
+        for _ in 0..size.max(1) {
+            let conn = Connection::open_with_flags(src, flags).map_err(MBError::Sqlite)?;
+            conn.busy_timeout(BUSY_TIMEOUT).map_err(MBError::Sqlite)?;
+            conns.push(Mutex::new(conn));
+        }
+        Ok(ReadPool { conns, next: AtomicUsize::new(0) })
+    }
+
+
+    pub fn size(&self) -> usize {
+        self.conns.len()
+    }
+
+
+    /// Run a read on a free connection, or, when all are busy, wait for the next in turn
+    pub fn with<T>(&self, read: impl FnOnce(&Connection) -> T) -> T {
+        let start: usize = self.next.fetch_add(1, Ordering::Relaxed) % self.conns.len();
+        for i in 0..self.conns.len() {
+            if let Ok(conn) = self.conns[(start + i) % self.conns.len()].try_lock() {
+                return read(&conn);
+            }
+        }
+        let conn = self.conns[start].lock().unwrap_or_else(|e| e.into_inner());
+        read(&conn)
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_pool_reads_side_by_side() {
+        let dbpath = std::env::temp_dir().join(format!("memobook_test_readpool_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&dbpath);
+        let writer = Connection::open(&dbpath).unwrap();
+        writer.execute_batch("create table t (v INTEGER); insert into t values (1), (2), (3);").unwrap();
+        let pool = ReadPool::open(dbpath.to_str().unwrap(), 2).unwrap();
+        assert_eq!(pool.size(), 2);
+        let sum = |conn: &Connection| conn.query_row("select sum(v) from t", [], |r| r.get::<usize, i64>(0)).unwrap();
+        // a second read while the first still holds its connection takes the other one
+        let nested = pool.with(|outer| (sum(outer), pool.with(sum)));
+        assert_eq!(nested, (6, 6));
+        assert!(pool.with(|conn| conn.execute("insert into t values (4)", [])).is_err());
+        writer.execute("insert into t values (4)", []).unwrap();
+        assert_eq!(pool.with(sum), 10);
+        drop(pool);
+        drop(writer);
+        let _ = std::fs::remove_file(&dbpath);
+    }
+
+}
@@ -0,0 +1,227 @@
+//  savedsearch.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+/********************************************
+   SavedSearches: named queries kept in the
+    configuration json under "saved", so
+    that clients can rerun them by name.
+*********************************************/
+
+
+use std::collections::HashMap;
+use json::object;
+use crate::query::Query;
+use crate::queryer::Queryer;
+use crate::mbfilter::MBFilter;
+use crate::filtercontainer::FilterContainer;
+use crate::logic::Logic;
+use crate::backerparserjson::BackerParserJSON;
+
+
+#[inline]
+fn json_to_string(src: &json::JsonValue) -> Option<String> {
+    match src {
+        json::JsonValue::String(x) => Some(x.to_owned()),
+        json::JsonValue::Short(x) => Some(x.to_string()),
+        _ => None
+    }
+}
+
+
+#[inline]
+fn json_to_bool(src: &json::JsonValue) -> bool {
+    match src {
+        json::JsonValue::Boolean(x) => *x,
+        _ => false
+    }
+}
+
+
+pub fn query_to_json(query: &Query<MBFilter>) -> json::JsonValue {
+    let mut jfilters = json::JsonValue::new_array();
+    for filter in query.iter_filters() {
+        let _ = jfilters.push(object!{
+            type: filter.filtertype(),
+            logic: match filter.logic() {
+                Logic::AND => "and",
+                Logic::OR => "or"
+            },
+            terms: filter.iter().collect::<Vec<&str>>()
+        });
+    }
+    object!{
+        equality: query.equality(),
+        grep: query.grep(),
+        grepcase: query.grepcase(),
+        greplink: query.greplink(),
+        filters: jfilters
+    }
+}
+
+
+pub fn query_from_json(source: &json::JsonValue) -> Result<Query<MBFilter>, String> {
+    let equality: String = match json_to_string(&source["equality"]) {
+        Some(e) => e,
+        None => { return Err("Parse error on saved search equality".to_string()); }
+    };
+    let mut filtervec: Vec<MBFilter> = Vec::new();
+    if let json::JsonValue::Array(filters) = &source["filters"] {
+        for jfilter in filters {
+            let Some(ftype) = json_to_string(&jfilter["type"]) else {
+                return Err("Parse error on saved search filter type".to_string());
+            };
+            let flogic: Logic = match json_to_string(&jfilter["logic"]).as_deref() {
+                Some("and") => Logic::AND,
+                Some("or") => Logic::OR,
+                _ => { return Err("Parse error on saved search filter logic".to_string()); }
+            };
+            let terms: Vec<String> = match &jfilter["terms"] {
+                json::JsonValue::Array(a) => a.iter().filter_map(json_to_string).collect(),
+                _ => { return Err("Parse error on saved search filter terms".to_string()); }
+            };
+            filtervec.push(MBFilter::new(ftype, flogic, terms));
+        }
+    }
+    Ok(Query::new(filtervec,
+            equality.as_str(),
+            json_to_bool(&source["grep"]),
+            json_to_bool(&source["grepcase"]),
+            json_to_bool(&source["greplink"])))
+}
+
+
+#[derive(Clone,Default)]
+pub struct SavedSearches {
+    searches: HashMap<String, Query<MBFilter>>
+}
+
+
+impl SavedSearches {
+
+    pub fn new() -> SavedSearches {
+        SavedSearches { searches: HashMap::new() }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Query<MBFilter>> {
+        self.searches.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.searches.contains_key(name)
+    }
+
+    /// Store a new search or replace the one of the same name. Returns true if one was replaced.
+    pub fn insert(&mut self, name: &str, query: Query<MBFilter>) -> bool {
+        self.searches.insert(name.to_string(), query).is_some()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Query<MBFilter>> {
+        self.searches.remove(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut namevec: Vec<String> = self.searches.keys().map(|k| k.to_string()).collect();
+        namevec.sort();
+        namevec
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.searches.is_empty()
+    }
+
+}
+
+
+impl BackerParserJSON for SavedSearches {
+
+    fn read(&mut self, source: &json::JsonValue) -> Result<(), String> {
+        self.searches.clear();
+        if source["saved"].is_null() {
+            return Ok(());
+        }
+        if !source["saved"].is_object() {
+            return Err("Parse error on saved searches object".to_string());
+        }
+        for (name, jquery) in source["saved"].entries() {
+            self.searches.insert(name.to_string(), query_from_json(jquery)?);
+        }
+        Ok(())
+    }
+
+    fn write(&self) -> Result<json::JsonValue, String> {
+        let mut jsaved = json::JsonValue::new_object();
+        for name in self.names() {
+            let _ = jsaved.insert(name.as_str(), query_to_json(self.searches.get(&name).unwrap()));
+        }
+        Ok(jsaved)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_query_round_trip() {
+        let m1: MBFilter = MBFilter::new("mark".to_string(), Logic::AND, vec!["rust".to_string(), "dbus".to_string()]);
+        let m2: MBFilter = MBFilter::new("type".to_string(), Logic::OR, vec!["Text".to_string()]);
+        let q1: Query<MBFilter> = Query::new(vec![m1, m2], "file", true, false, true);
+        let q2: Query<MBFilter> = query_from_json(&query_to_json(&q1)).unwrap();
+        assert_eq!(q2.equality(), "file");
+        assert!(q2.grep());
+        assert!(!q2.grepcase());
+        assert!(q2.greplink());
+        let filters: Vec<&MBFilter> = q2.iter_filters().collect();
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].filtertype(), "mark");
+        assert!(*filters[0].logic() == Logic::AND);
+        assert_eq!(filters[0].iter().collect::<Vec<&str>>(), vec!["rust", "dbus"]);
+        assert_eq!(filters[1].filtertype(), "type");
+        assert!(*filters[1].logic() == Logic::OR);
+    }
+
+    #[test]
+    fn test_read_write() {
+        let mut saved: SavedSearches = SavedSearches::new();
+        let m1: MBFilter = MBFilter::new("mark".to_string(), Logic::OR, vec!["notes".to_string()]);
+        assert!(!saved.insert("daily", Query::new(vec![m1], "file", false, false, false)));
+        let jroot = object!{ saved: saved.write().unwrap() };
+        let mut reread: SavedSearches = SavedSearches::new();
+        reread.read(&jroot).unwrap();
+        assert_eq!(reread.names(), vec!["daily".to_string()]);
+        assert!(reread.get("daily").is_some());
+    }
+
+    #[test]
+    fn test_read_bad_logic() {
+        let jroot = json::parse(r#"{"saved": {"x": {"equality": "file", "filters": [{"type": "mark", "logic": "xor", "terms": []}]}}}"#).unwrap();
+        let mut saved: SavedSearches = SavedSearches::new();
+        assert!(saved.read(&jroot).is_err());
+    }
+
+}
@@ -0,0 +1,234 @@
+//  sharedmime.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+/********************************************
+   SharedMime: type definitions generated from
+    a freedesktop shared-mime-info package
+    file, grouped by media type, e.g. image/png
+    becomes suffix png of type "Image".
+*********************************************/
+
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use crate::mimer::Mimer;
+
+
+pub const DEFAULT_SHARED_MIME: &str = "/usr/share/mime/packages/freedesktop.org.xml";
+
+
+
+#[derive(Clone)]
+pub struct SharedMime {
+    pub path: String,
+    generated: HashMap<String, Mimer>,
+    removed: BTreeSet<String>   //generated types removed or renamed by the user
+}
+
+
+impl SharedMime {
+
+    pub fn load(path: &str) -> Result<SharedMime, String> {
+        let xml: String = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read shared mime file {path}: {e}"))?;
+        Ok(SharedMime { path: path.to_string(), generated: parse_shared_mime(&xml), removed: BTreeSet::new() })
+    }
+
+    pub fn generated(&self) -> &HashMap<String, Mimer> {
+        &self.generated
+    }
+
+    /// The generated types less those the user removed
+    pub fn types(&self) -> HashMap<String, Mimer> {
+        self.generated.iter()
+            .filter(|(name, _)| !self.removed.contains(*name))
+            .map(|(name, mime)| (name.to_string(), mime.clone()))
+            .collect()
+    }
+
+    pub fn removed(&self) -> &BTreeSet<String> {
+        &self.removed
+    }
+
+    /// Keep a generated type from coming back on the next load; other names are ignored
+    pub fn remove(&mut self, name: &str) {
+        if self.generated.contains_key(name) {
+            self.removed.insert(name.to_string());
+        }
+    }
+
+    /// Let a removed generated type be generated again
+    pub fn restore(&mut self, name: &str) {
+        self.removed.remove(name);
+    }
+
+    /// True if the type is generated and unchanged, hence needn't be written to conf.json
+    pub fn is_generated(&self, name: &str, mime: &Mimer) -> bool {
+        self.generated.get(name).is_some_and(|m| m == mime)
+    }
+
+}
+
+
+/// Media type to type name: "image/png" yields "Image"
+fn group_name(mimetype: &str) -> Option<String> {
+    let media = mimetype.split('/').next()?.trim();
+    let mut chars = media.chars();
+    let first = chars.next()?;
+    Some(first.to_uppercase().chain(chars).collect())
+}
+
+
+/// Glob to suffix; only plain "*.ext" globs qualify
+fn glob_suffix(pattern: &str) -> Option<&str> {
+    let suffix = pattern.strip_prefix("*.")?;
+    if suffix.is_empty() || suffix.contains(['*', '?', '[', ']', '/']) {
+        None
+    } else {
+        Some(suffix)
+    }
+}
+
+
+pub(crate) fn unescape(src: &str) -> String {
+    src.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+
+/// Attribute value from the inside of a tag, e.g. attribute(r#"glob pattern="*.png""#, "pattern")
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(pos) = rest.find(name) {
+        let before_ok = pos == 0 || rest[..pos].ends_with(char::is_whitespace);
+        let after = rest[pos + name.len()..].trim_start();
+        rest = &rest[pos + name.len()..];
+        if !before_ok {
+            continue;
+        }
+        if let Some(value) = after.strip_prefix('=') {
+            let value = value.trim_start();
+            let quote = value.chars().next()?;
+            if quote != '"' && quote != '\'' {
+                return None;
+            }
+            let end = value[1..].find(quote)?;
+            return Some(unescape(&value[1..end + 1]));
+        }
+    }
+    None
+}
+
+
+/// Minimal scan of the shared-mime-info package format: only mime-type elements
+/// and their glob children are of interest, everything else is skipped.
+pub fn parse_shared_mime(xml: &str) -> HashMap<String, Mimer> {
+    let mut mimemap: HashMap<String, Mimer> = HashMap::new();
+    let mut current: Option<String> = None;
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = match comment.find("-->") {
+                Some(end) => &comment[end + 3..],
+                None => ""
+            };
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(e) => e,
+            None => break
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with("/mime-type") {
+            current = None;
+        } else if tag.starts_with("mime-type") && tag[9..].starts_with(char::is_whitespace) {
+            current = attribute(tag, "type").and_then(|t| group_name(&t));
+            if tag.ends_with('/') {
+                current = None;
+            }
+        } else if tag.starts_with("glob") && tag[4..].starts_with(char::is_whitespace) {
+            let (Some(group), Some(pattern)) = (&current, attribute(tag, "pattern")) else {
+                continue;
+            };
+            if let Some(suffix) = glob_suffix(&pattern) {
+                mimemap.entry(group.to_string())
+                    .or_insert(Mimer::new_by_vec(vec![]))
+                    .add(suffix);
+            }
+        }
+    }
+    mimemap
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const SAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<mime-info xmlns="http://www.freedesktop.org/standards/shared-mime-info">
+  <!-- <mime-type type="bogus/commented"><glob pattern="*.nope"/></mime-type> -->
+  <mime-type type="image/png">
+    <comment>PNG image</comment>
+    <glob pattern="*.png"/>
+  </mime-type>
+  <mime-type type="image/jpeg">
+    <glob pattern="*.jpg"/>
+    <glob pattern='*.jpeg' weight="60"/>
+  </mime-type>
+  <mime-type type="text/x-readme">
+    <glob pattern="README*"/>
+    <glob pattern="*.[ch]"/>
+  </mime-type>
+  <mime-type type="text/plain">
+    <sub-class-of type="application/octet-stream"/>
+    <glob pattern="*.txt"/>
+  </mime-type>
+</mime-info>"#;
+
+    #[test]
+    fn test_parse_shared_mime() {
+        let mimemap = parse_shared_mime(SAMPLE);
+        assert_eq!(mimemap.len(), 2);
+        assert_eq!(mimemap.get("Image").unwrap().display(), "png jpg jpeg");
+        assert_eq!(mimemap.get("Text").unwrap().display(), "txt");
+        assert!(!mimemap.contains_key("Bogus"));
+    }
+
+    #[test]
+    fn test_attribute() {
+        assert_eq!(attribute(r#"glob weight="60" pattern="*.a&amp;b""#, "pattern"), Some("*.a&b".to_string()));
+        assert_eq!(attribute(r#"sub-class-of type="text/plain""#, "pattern"), None);
+        assert_eq!(attribute(r#"mime-type subtype="x" type="y/z""#, "type"), Some("y/z".to_string()));
+    }
+
+}
@@ -37,6 +37,11 @@ impl SuffixHash {
         SuffixHash { sethash: HashMap::new(), star: false }
     }
 
+    /// Test a bare suffix, e.g. one reported by content detection, rather than a file name
+    pub fn test_suffix(&self, suffix: &str) -> bool {
+        self.star || self.sethash.contains_key(suffix)
+    }
+
 }
 
 impl<'a> MaskingSet<'a> for SuffixHash {
@@ -0,0 +1,251 @@
+//  suggester.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+/********************************************
+   Suggester: proposes marks for a text file.
+    Marks already in the archive that occur in
+    the file come first; after those, keywords
+    ranked by TF-IDF against the corpus of
+    already-marked text files.
+*********************************************/
+
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::extractor::ExtractorRegistry;
+use crate::magic;
+use crate::mberror::MBError;
+use crate::mimer::Mimer;
+
+
+/// Most already-marked files read when computing keyword document frequencies
+const MAX_SUGGEST_CORPUS: usize = 500;
+
+
+const STOPWORDS: [&str; 40] = [
+    "the", "and", "for", "are", "but", "not", "you", "all", "any", "can",
+    "had", "her", "was", "one", "our", "out", "has", "have", "him", "his",
+    "how", "its", "may", "new", "now", "see", "who", "did", "get", "let",
+    "this", "that", "with", "from", "they", "will", "would", "there", "their", "what"
+];
+
+
+/// Weight placing marks already in the archive ahead of new keywords
+const EXISTING_WEIGHT: f64 = 1000.0;
+
+const MIN_TOKEN_LEN: usize = 3;
+
+
+#[derive(Clone)]
+pub struct Suggestion {
+    pub mark: String,
+    pub score: f64,
+    pub existing: bool
+}
+
+
+/// Lowercased word tokens of a text, minus stopwords and numbers
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .map(|t| t.trim_matches('-').to_lowercase())
+        .filter(|t| t.chars().count() >= MIN_TOKEN_LEN)
+        .filter(|t| !t.chars().all(|c| c.is_numeric() || c == '_' || c == '-'))
+        .filter(|t| !STOPWORDS.contains(&t.as_str()))
+        .collect()
+}
+
+
+/// True if the file's suffix belongs to one of the configured types and its content reads as text
+pub fn is_textual(file: &str, mime: &HashMap<String,Mimer>) -> bool {
+    let Some(suffix) = Path::new(file).extension().and_then(|s| s.to_str()) else {
+        return false;
+    };
+    let suffix = suffix.to_lowercase();
+    mime.values().any(|m| m.iter().any(|s| s.to_lowercase() == suffix)) && magic::is_text(Path::new(file))
+}
+
+
+#[derive(Default)]
+pub struct Suggester {
+    marks: Vec<String>,
+    docfreq: HashMap<String, usize>,
+    corpussize: usize
+}
+
+
+impl Suggester {
+
+    pub fn new() -> Suggester {
+        Suggester { marks: Vec::new(), docfreq: HashMap::new(), corpussize: 0 }
+    }
+
+    pub fn set_marks(&mut self, marks: Vec<String>) -> &mut Suggester {
+        self.marks = marks;
+        self
+    }
+
+    pub fn add_document(&mut self, text: &str) -> &mut Suggester {
+        let unique: HashSet<String> = tokenize(text).into_iter().collect();
+        for token in unique {
+            self.docfreq.entry(token).and_modify(|c| *c += 1).or_insert(1);
+        }
+        self.corpussize += 1;
+        self
+    }
+
+    pub fn suggest(&self, text: &str, limit: usize) -> Vec<Suggestion> {
+        let tokens: Vec<String> = tokenize(text);
+        let mut termfreq: HashMap<&str, usize> = HashMap::new();
+        for token in tokens.iter() {
+            termfreq.entry(token.as_str()).and_modify(|c| *c += 1).or_insert(1);
+        }
+        let lowertext: String = text.to_lowercase();
+        let mut taken: HashSet<String> = HashSet::new();
+        let mut existing: Vec<Suggestion> = Vec::new();
+        for mark in self.marks.iter() {
+            let lowermark: String = mark.to_lowercase();
+            let hits: usize = if tokenize(&lowermark) == vec![lowermark.clone()] {
+                *termfreq.get(lowermark.as_str()).unwrap_or(&0)
+            } else if lowermark.trim().is_empty() {
+                0
+            } else {
+                lowertext.matches(lowermark.as_str()).count()
+            };
+            if hits > 0 {
+                taken.insert(lowermark);
+                existing.push(Suggestion { mark: mark.to_string(), score: EXISTING_WEIGHT + hits as f64, existing: true });
+            }
+        }
+        let total: f64 = tokens.len().max(1) as f64;
+        let mut keywords: Vec<Suggestion> = termfreq.iter()
+            .filter(|(token, _)| !taken.contains(**token))
+            .map(|(token, tf)| {
+                let df: f64 = *self.docfreq.get(*token).unwrap_or(&0) as f64;
+                let idf: f64 = ((1.0 + self.corpussize as f64) / (1.0 + df)).ln() + 1.0;
+                Suggestion { mark: token.to_string(), score: (*tf as f64 / total) * idf, existing: false }
+            })
+            .collect();
+        existing.append(&mut keywords);
+        existing.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.mark.cmp(&b.mark)));
+        existing.truncate(limit);
+        existing
+    }
+
+}
+
+
+/// What a suggestion needs from the book, gathered while holding it; run reads the files
+pub struct SuggestWork {
+    pub file: String,
+    pub marks: Vec<String>,
+    pub corpus: Vec<String>,    // the other files in the archive
+    pub mime: HashMap<String,Mimer>,
+    pub extractors: ExtractorRegistry
+}
+
+
+impl SuggestWork {
+
+    fn is_readable(&self, file: &str) -> bool {
+        self.extractors.for_path(Path::new(file)).is_some() || is_textual(file, &self.mime)
+    }
+
+    pub fn run(&self, limit: usize) -> Result<Vec<Suggestion>, MBError> {
+        if !self.is_readable(&self.file) {
+            return Err(MBError::Suggest(format!("{} is not of a textual type", self.file)));
+        }
+        let content: String = self.extractors.read_text(&self.file)?;
+        let mut suggester: Suggester = Suggester::new();
+        suggester.set_marks(self.marks.clone());
+        for doc in self.corpus.iter()
+            .filter(|f| self.is_readable(f))
+            .take(MAX_SUGGEST_CORPUS)
+        {
+            if let Ok(d) = self.extractors.read_text(doc) {
+                suggester.add_document(&d);
+            }
+        }
+        Ok(suggester.suggest(&content, limit))
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("The D-Bus service, and 2025 notes: sqlite3!"),
+            vec!["d-bus".to_string(), "service".to_string(), "notes".to_string(), "sqlite3".to_string()]);
+    }
+
+    #[test]
+    fn test_existing_marks_first() {
+        let mut sug: Suggester = Suggester::new();
+        sug.set_marks(vec!["Rust".to_string(), "python".to_string(), "async runtime".to_string()]);
+        sug.add_document("tokio runtime scheduling");
+        sug.add_document("zbus interface");
+        let out = sug.suggest("Rust uses an async runtime. Scheduling in rust is cooperative.", 3);
+        assert_eq!(out.len(), 3);
+        assert_eq!(out[0].mark, "Rust");
+        assert!(out[0].existing);
+        assert_eq!(out[1].mark, "async runtime");
+        assert!(!out[2].existing);
+    }
+
+    #[test]
+    fn test_idf_favors_rare_terms() {
+        let mut sug: Suggester = Suggester::new();
+        sug.add_document("common words here");
+        sug.add_document("common words there");
+        let out = sug.suggest("common doomlike", 1);
+        assert_eq!(out[0].mark, "doomlike");
+    }
+
+    #[test]
+    fn test_is_textual() {
+        let mut mime: HashMap<String, Mimer> = HashMap::new();
+        mime.insert("Text".to_string(), Mimer::new_by_vec(vec!["txt".to_string(), "log".to_string()]));
+        mime.insert("PDF".to_string(), Mimer::new_by_vec(vec!["pdf".to_string()]));
+        let dir = std::env::temp_dir().join(format!("memobook_test_textual_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, content: &[u8]| {
+            std::fs::write(dir.join(name), content).unwrap();
+            dir.join(name).to_str().unwrap().to_string()
+        };
+        assert!(is_textual(&file("a.TXT", b"plain notes"), &mime));
+        assert!(!is_textual(&file("b.log", b"\x00\x01binary"), &mime));
+        assert!(!is_textual(&file("c.pdf", b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n"), &mime));
+        assert!(!is_textual(&file("d.md", b"# notes"), &mime));
+        assert!(!is_textual(&file("README", b"notes"), &mime));
+        assert!(!is_textual(dir.join("gone.txt").to_str().unwrap(), &mime));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+}
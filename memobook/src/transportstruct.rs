@@ -22,6 +22,7 @@
 //  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
 
 
+#[derive(Clone)]
 pub struct TransPortStruct {
     pub target: String,
     pub log: String,
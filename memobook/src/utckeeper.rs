@@ -760,9 +760,9 @@ mod tests {
         let b3: UtcBackup = UtcBackup::new("monser.d", "conf3.json", &Utc.with_ymd_and_hms(2012, 9, 9, 5, 5, 5).unwrap());//2012-09-09T05:05:05Z 
         let mut holder = UtcKeeper::new(20, 16, vec![b1, b2, b3]);
         holder.make("/home/illudens/code/rust/memoserv/archive.db", &vec!["/home/illudens/code/rust/memoserv/conf.json"]);
-        assert_eq!("mbsnapshot0000.db", holder.most_recent().unwrap().path().as_str());
+        assert_eq!("mbsnapshot0000.db", holder.get_most_recent().unwrap().path().as_str());
         holder.make("/home/illudens/code/rust/memoserv/archive.db", &vec!["/home/illudens/code/rust/memoserv/conf.json"]);
-        assert_eq!("mbsnapshot0001.db", holder.most_recent().unwrap().path().as_str());
+        assert_eq!("mbsnapshot0001.db", holder.get_most_recent().unwrap().path().as_str());
         fs::remove_file("mbsnapshot0000.db");
         fs::remove_file("mbsnapshot0000.json");
         fs::remove_file("mbsnapshot0001.db");
@@ -815,7 +815,7 @@ mod tests {
                 Ok(_) => {},
                 Err(e) => panic!("{}", format!("Cannot make {}: {e}", item))
             }
-            assert_eq!(*item, holder.most_recent().unwrap().path().as_str());
+            assert_eq!(*item, holder.get_most_recent().unwrap().path().as_str());
         }
         match holder.make(
             "/home/illudens/code/rust/memoserv/archive.db", 
@@ -824,7 +824,7 @@ mod tests {
             Ok(_) => {},
             Err(e) => panic!("{}", format!("Cannot make backup: {e}"))
         }
-        assert_eq!(namesvec[0], holder.most_recent().unwrap().path().as_str());
+        assert_eq!(namesvec[0], holder.get_most_recent().unwrap().path().as_str());
         namesvec.remove(1);
         auxvec.remove(1);
         for item in namesvec.iter() {
@@ -847,7 +847,7 @@ mod tests {
         holder.set_suffix(&vec!["doll".to_string(),"house".to_string()]);
         //let place1: String = holder.generate().unwrap();
         holder.make("/home/illudens/code/rust/memoserv/archive.db", &vec!["/home/illudens/code/rust/memoserv/conf.json"]);
-        assert_eq!("/home/illudens/code/rust/barbie0000.doll", holder.most_recent().unwrap().path().as_str());
+        assert_eq!("/home/illudens/code/rust/barbie0000.doll", holder.get_most_recent().unwrap().path().as_str());
         fs::remove_file("/home/illudens/code/rust/barbie0000.doll");
         fs::remove_file("/home/illudens/code/rust/barbie0000.house");
     }
@@ -0,0 +1,295 @@
+//  access.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use zbus::Connection;
+use zbus::fdo::DBusProxy;
+use zbus::message::Header;
+use zbus::names::BusName;
+use crate::ops::OpError;
+
+
+// Who may do what. Each caller gets a role from its uid or bus name, looked up from the
+// message header; anyone may read. The "access" section of conf.json holds the policy:
+//   "access": { "readonly": false, "default": "read-only",
+//               "uids": { "1000": "admin" }, "names": { "org.memobook.memoface": "read-write" } }
+// Names may be unique (":1.42") or well-known, in which case the caller must own them.
+// Callers not listed get the default role: read-only once anyone is listed, otherwise admin,
+// as before. The readonly switch refuses every change whatever the role.
+
+/// Uids remembered per unique name before the memory is cleared
+const MAX_SEEN: usize = 256;
+
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Role {
+    ReadOnly,
+    ReadWrite,
+    Admin
+}
+
+
+impl Role {
+
+    pub fn from_name(name: &str) -> Result<Role, String> {
+        match name {
+            "read-only" => Ok(Role::ReadOnly),
+            "read-write" => Ok(Role::ReadWrite),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("unknown role: {name}"))
+        }
+    }
+
+}
+
+
+impl fmt::Display for Role {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::ReadOnly => write!(f, "read-only"),
+            Role::ReadWrite => write!(f, "read-write"),
+            Role::Admin => write!(f, "admin")
+        }
+    }
+
+}
+
+
+/// What a request needs
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Access {
+    Read,       // listings that any caller may see
+    Write,      // records and saved searches
+    Jobs,       // cancelling jobs
+    Control,    // exit, export
+    Configure   // configuration, backups, import
+}
+
+
+impl Access {
+
+    pub fn role(&self) -> Role {
+        match self {
+            Access::Read => Role::ReadOnly,
+            Access::Write | Access::Jobs => Role::ReadWrite,
+            Access::Control | Access::Configure => Role::Admin
+        }
+    }
+
+    /// Whether the request changes the archive or its configuration
+    pub fn writes(&self) -> bool {
+        matches!(self, Access::Write | Access::Configure)
+    }
+
+}
+
+
+pub struct AccessPolicy {
+    pub readonly: bool,
+    pub default: Role,
+    pub uids: HashMap<u32, Role>,
+    pub names: HashMap<String, Role>,
+    seen: Mutex<HashMap<String, u32>>
+}
+
+
+impl Default for AccessPolicy {
+
+    fn default() -> Self {
+        AccessPolicy::new(false, Role::Admin)
+    }
+
+}
+
+
+impl AccessPolicy {
+
+    pub fn new(readonly: bool, default: Role) -> AccessPolicy {
+        AccessPolicy { readonly, default, uids: HashMap::new(), names: HashMap::new(), seen: Mutex::new(HashMap::new()) }
+    }
+
+    /// Read the optional "access" section
+    pub fn read(rawjson: &json::JsonValue) -> Result<AccessPolicy, String> {
+        let section = &rawjson["access"];
+        if section.is_null() {
+            return Ok(AccessPolicy::default());
+        }
+        if !section.is_object() {
+            return Err("Parse error on access settings".to_string());
+        }
+        let mut policy = AccessPolicy::default();
+        if !section["readonly"].is_null() {
+            policy.readonly = section["readonly"].as_bool().ok_or("Parse error on access readonly switch")?;
+        }
+        for (uid, role) in section["uids"].entries() {
+            let uid: u32 = uid.parse().map_err(|_| format!("Parse error on access uid {uid}"))?;
+            policy.uids.insert(uid, Role::from_name(role.as_str().ok_or("Parse error on access role")?)?);
+        }
+        for (name, role) in section["names"].entries() {
+            policy.names.insert(name.to_string(), Role::from_name(role.as_str().ok_or("Parse error on access role")?)?);
+        }
+        if !policy.uids.is_empty() || !policy.names.is_empty() {
+            policy.default = Role::ReadOnly;
+        }
+        if !section["default"].is_null() {
+            policy.default = Role::from_name(section["default"].as_str().ok_or("Parse error on access default role")?)?;
+        }
+        Ok(policy)
+    }
+
+    /// The role for a caller: its listed uid's, or the default. A listed name can only narrow
+    /// that role, since any process of the same user may own the name.
+    pub fn role_of(&self, uid: Option<u32>, names: &[&str]) -> Role {
+        let role: Role = uid.and_then(|u| self.uids.get(&u).copied()).unwrap_or(self.default);
+        match names.iter().filter_map(|n| self.names.get(*n)).min() {
+            Some(named) => role.min(*named),
+            None => role
+        }
+    }
+
+    pub fn check(&self, access: Access, role: Role) -> Result<(), OpError> {
+        if access.writes() && self.readonly {
+            return Err(OpError::new("Access denied", "the archive is read-only"));
+        }
+        if role < access.role() {
+            return Err(OpError::new("Access denied", format!("{} role needed, caller is {}", access.role(), role)));
+        }
+        Ok(())
+    }
+
+    /// Check a request against the caller found in its header. With no sender the connection
+    /// is peer to peer, and the peer's socket credentials are used.
+    pub async fn authorize(&self, access: Access, header: &Header<'_>, conn: &Connection) -> Result<(), OpError> {
+        if self.uids.is_empty() && self.names.is_empty() {
+            return self.check(access, self.default);
+        }
+        let role = match header.sender() {
+            Some(sender) => {
+                let uid = self.sender_uid(sender.as_str(), conn).await;
+                let mut names: Vec<&str> = vec![sender.as_str()];
+                let owned = self.owned_names(sender.as_str(), conn).await;
+                names.extend(owned.iter().map(|n| n.as_str()));
+                self.role_of(uid, &names)
+            },
+            None => {
+                #[allow(deprecated)]
+                let uid = conn.peer_credentials().await.ok().and_then(|c| c.unix_user_id());
+                self.role_of(uid, &[])
+            }
+        };
+        self.check(access, role)
+    }
+
+    async fn sender_uid(&self, sender: &str, conn: &Connection) -> Option<u32> {
+        if let Some(uid) = self.seen.lock().unwrap().get(sender) {
+            return Some(*uid);
+        }
+        let proxy = DBusProxy::new(conn).await.ok()?;
+        let uid = proxy.get_connection_unix_user(BusName::try_from(sender).ok()?).await.ok()?;
+        let mut seen = self.seen.lock().unwrap();
+        if seen.len() >= MAX_SEEN {
+            seen.clear();
+        }
+        seen.insert(sender.to_string(), uid);
+        Some(uid)
+    }
+
+    /// Listed well-known names owned by the sender
+    async fn owned_names(&self, sender: &str, conn: &Connection) -> Vec<String> {
+        let mut owned: Vec<String> = vec![];
+        let Ok(proxy) = DBusProxy::new(conn).await else {
+            return owned;
+        };
+        for name in self.names.keys().filter(|n| !n.starts_with(':')) {
+            let Ok(busname) = BusName::try_from(name.as_str()) else { continue; };
+            if proxy.get_name_owner(busname).await.is_ok_and(|o| o.as_str() == sender) {
+                owned.push(name.to_string());
+            }
+        }
+        owned
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use memobook::transportstruct::TransPortStruct;
+    use crate::manager::Manager;
+    use crate::configmodifier::ConfigModifier;
+
+    #[test]
+    fn test_read_policy() {
+        let policy = AccessPolicy::read(&json::parse(r#"{"database": {}}"#).unwrap()).unwrap();
+        assert_eq!((policy.readonly, policy.default), (false, Role::Admin));
+        let raw = json::parse(r#"{"access": {"uids": {"1000": "admin", "1001": "read-write"},
+            "names": {"org.memobook.memoface": "read-write"}}}"#).unwrap();
+        let policy = AccessPolicy::read(&raw).unwrap();
+        assert_eq!(policy.default, Role::ReadOnly);
+        assert_eq!(policy.role_of(Some(1000), &[":1.7"]), Role::Admin);
+        assert_eq!(policy.role_of(Some(1001), &[]), Role::ReadWrite);
+        assert_eq!(policy.role_of(Some(1002), &[":1.9"]), Role::ReadOnly);
+        // a name narrows the uid's role but never raises it
+        assert_eq!(policy.role_of(None, &[":1.9", "org.memobook.memoface"]), Role::ReadOnly);
+        assert_eq!(policy.role_of(Some(1000), &["org.memobook.memoface"]), Role::ReadWrite);
+        assert_eq!(policy.role_of(Some(1001), &["org.memobook.memoface"]), Role::ReadWrite);
+        let policy = AccessPolicy::read(&json::parse(r#"{"access": {"readonly": true}}"#).unwrap()).unwrap();
+        assert_eq!((policy.readonly, policy.default), (true, Role::Admin));
+        assert!(AccessPolicy::read(&json::parse(r#"{"access": {"default": "root"}}"#).unwrap()).is_err());
+        assert!(AccessPolicy::read(&json::parse(r#"{"access": {"uids": {"me": "admin"}}}"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let mut policy = AccessPolicy::new(false, Role::ReadOnly);
+        assert!(policy.check(Access::Write, Role::ReadOnly).is_err());
+        assert!(policy.check(Access::Write, Role::ReadWrite).is_ok());
+        assert!(policy.check(Access::Jobs, Role::ReadWrite).is_ok());
+        assert!(policy.check(Access::Configure, Role::ReadWrite).is_err());
+        assert!(policy.check(Access::Control, Role::Admin).is_ok());
+        policy.readonly = true;
+        assert!(policy.check(Access::Write, Role::Admin).is_err());
+        assert!(policy.check(Access::Configure, Role::Admin).is_err());
+        assert!(policy.check(Access::Jobs, Role::ReadWrite).is_ok());
+        assert!(policy.check(Access::Control, Role::Admin).is_ok());
+        assert!(policy.check(Access::Read, Role::ReadOnly).is_ok());
+    }
+
+    #[test]
+    fn test_manage_access() {
+        let policy = AccessPolicy::new(false, Role::ReadOnly);
+        let export = Manager::Export(TransPortStruct { target: "".to_string(), log: "/tmp/export.json".to_string(), links: false });
+        assert!(policy.check(crate::manage_access(&export), Role::ReadWrite).is_err());
+        assert!(policy.check(crate::manage_access(&export), Role::Admin).is_ok());
+        let list = Manager::Configure(ConfigModifier::ListTypes);
+        assert!(policy.check(crate::manage_access(&list), Role::ReadOnly).is_ok());
+    }
+
+}
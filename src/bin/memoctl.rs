@@ -0,0 +1,401 @@
+//  memoctl.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+// memoctl: the service from the command line. Requests are built with memoserv::message
+// and sent to org.memobook.memoserv1; replies are printed for people, or as json with --json.
+
+use std::env;
+use std::process;
+use std::thread;
+use std::time::Duration;
+use zbus::blocking::Connection;
+use memoserv::busconfig::{BusConfig, BusType};
+use memoserv::message::*;
+
+
+const USAGE: &str = "usage: memoctl [--session | --system] [--name BUSNAME] [--path OBJECTPATH] [--json] COMMAND
+
+commands:
+  search [--equality file|mark|type] [--grep] [--case] [--links] FILTER...
+         FILTER is FIELD=TERM[,TERM...] for any of the terms, all:FIELD=... for all of them
+  toc file|mark|type
+  add FILE... --mark MARK... [--type TYPE...]
+  tag FILE MARK... [--type TYPE...]
+  untag FILE MARK... [--type TYPE...]
+  rm [--field file|mark|type] VALUE
+  import SOURCE LOG [--links] [--wait]
+  export TARGET [--wait]
+  backup [info | now | clear | load FILE | set [--mult N] [--freq N] [--base NAME] [--loc DIR]]
+  config show | types | source DB
+         | setrepo [--trunk DIR] [--include DIR...] [--exclude DIR...]
+         | modrepo [--add-include DIR...] [--add-exclude DIR...] [--remove-include DIR...] [--remove-exclude DIR...]
+         | addtype NAME SUFFIX... | addsuffix NAME SUFFIX... | remsuffix NAME SUFFIX...
+         | remtype NAME | renametype OLD NEW [--rewrite]
+
+Options taking values may be repeated; values may also be separated by commas.";
+
+const INTERFACE: &str = "org.memobook.memoserv1";
+const JOB_POLL: Duration = Duration::from_millis(500);
+
+
+/// Command-line words, taken out as they are recognised
+struct Args {
+    words: Vec<String>
+}
+
+
+impl Args {
+
+    fn flag(&mut self, name: &str) -> bool {
+        let found = self.words.iter().any(|w| w == name);
+        self.words.retain(|w| w != name);
+        found
+    }
+
+    /// Every value given to an option, comma lists split
+    fn values(&mut self, name: &str) -> Result<Vec<String>, String> {
+        let mut values: Vec<String> = vec![];
+        while let Some(i) = self.words.iter().position(|w| w == name) {
+            if i + 1 >= self.words.len() {
+                return Err(format!("{name} needs a value"));
+            }
+            let value = self.words.remove(i + 1);
+            self.words.remove(i);
+            values.extend(value.split(',').filter(|v| !v.is_empty()).map(|v| v.to_string()));
+        }
+        Ok(values)
+    }
+
+    fn value(&mut self, name: &str) -> Result<Option<String>, String> {
+        let mut values = self.values(name)?;
+        match values.len() {
+            0 => Ok(None),
+            1 => Ok(values.pop()),
+            _ => Err(format!("{name} takes one value"))
+        }
+    }
+
+    /// What is left, once every option has been taken
+    fn rest(&mut self) -> Result<Vec<String>, String> {
+        if let Some(w) = self.words.iter().find(|w| w.starts_with("--")) {
+            return Err(format!("unknown option: {w}"));
+        }
+        Ok(std::mem::take(&mut self.words))
+    }
+
+}
+
+
+/// The outcome of a command, printed one way or the other
+enum Output {
+    Done,
+    List(Vec<String>),
+    Json(json::JsonValue),
+    Job(u32)
+}
+
+
+struct Client {
+    conn: Connection,
+    bus: BusConfig
+}
+
+
+impl Client {
+
+    fn call(&self, method: &str, body: &Vec<String>) -> Result<String, String> {
+        self.call_with(method, &(body,))
+    }
+
+    fn call_with<B>(&self, method: &str, body: &B) -> Result<String, String>
+    where B: zbus::export::serde::ser::Serialize + zbus::zvariant::DynamicType
+    {
+        let reply = self.conn.call_method(Some(self.bus.name.as_str()), self.bus.path.as_str(), Some(INTERFACE), method, body)
+            .map_err(|e| e.to_string())?;
+        reply.body().deserialize::<String>().map_err(|e| e.to_string())
+    }
+
+    /// Modify and manage reply with nothing on success and the error otherwise
+    fn change(&self, method: &str, body: &Vec<String>) -> Result<Output, String> {
+        let reply = self.call(method, body)?;
+        if reply.is_empty() { Ok(Output::Done) } else { Err(reply) }
+    }
+
+    fn json(&self, method: &str, body: &Vec<String>) -> Result<Output, String> {
+        let reply = if body.is_empty() { self.call_with(method, &())? } else { self.call(method, body)? };
+        json::parse(&reply).map(Output::Json).map_err(|_| reply)
+    }
+
+    fn search(&self, msg: &Vec<String>) -> Result<Vec<String>, String> {
+        let reply = self.call("Search", msg)?;
+        if reply.starts_with("Search error") || reply.starts_with("Error in search") {
+            return Err(reply);
+        }
+        Ok(split_list(&reply))
+    }
+
+    fn job(&self, msg: &Vec<String>, wait: bool) -> Result<Output, String> {
+        let reply = self.call("Manage", msg)?;
+        let id: u32 = reply.parse().map_err(|_| reply)?;
+        if !wait {
+            return Ok(Output::Job(id));
+        }
+        loop {
+            let status = self.call_with("JobStatus", &(id.to_string(),))?;
+            let jstatus = json::parse(&status).map_err(|_| status)?;
+            match jstatus["state"].as_str() {
+                Some("running") => thread::sleep(JOB_POLL),
+                Some("done") => return Ok(Output::Json(jstatus)),
+                _ => return Err(jstatus["message"].as_str().unwrap_or("job failed").to_string())
+            }
+        }
+    }
+
+    /// Types of the records of a file, needed to update its marks
+    fn types_of(&self, file: &str) -> Result<Vec<String>, String> {
+        let filter = FilterSpec { field: "file".to_string(), logic: "or".to_string(), terms: vec![file.to_string()] };
+        let types = self.search(&search_msg(&[filter], "type", false, false, false))?;
+        if types.is_empty() {
+            return Err(format!("no records for {file}"));
+        }
+        Ok(types)
+    }
+
+}
+
+
+fn split_list(reply: &str) -> Vec<String> {
+    if reply.is_empty() {
+        return vec![];
+    }
+    reply.split(", ").map(|s| s.to_string()).collect()
+}
+
+
+fn parse_filter(word: &str) -> Result<FilterSpec, String> {
+    let (logic, spec) = match word.strip_prefix("all:") {
+        Some(s) => ("and", s),
+        None => ("or", word.strip_prefix("any:").unwrap_or(word))
+    };
+    let Some((field, terms)) = spec.split_once('=') else {
+        return Err(format!("filter {word} is not FIELD=TERMS"));
+    };
+    if !matches!(field, "file" | "mark" | "type") {
+        return Err(format!("unknown filter field: {field}"));
+    }
+    let terms: Vec<String> = terms.split(',').filter(|t| !t.is_empty()).map(|t| t.to_string()).collect();
+    if terms.is_empty() {
+        return Err(format!("filter {word} has no terms"));
+    }
+    Ok(FilterSpec { field: field.to_string(), logic: logic.to_string(), terms })
+}
+
+
+fn field_arg(field: &str) -> Result<&str, String> {
+    match field {
+        "file" | "mark" | "type" => Ok(field),
+        _ => Err(format!("field must be file, mark or type, not {field}"))
+    }
+}
+
+
+fn run(client: &Client, command: &str, mut args: Args) -> Result<Output, String> {
+    match command {
+        "search" => {
+            let equality = args.value("--equality")?.unwrap_or("file".to_string());
+            field_arg(&equality)?;
+            let (grep, case, links) = (args.flag("--grep"), args.flag("--case"), args.flag("--links"));
+            let filters = args.rest()?.iter().map(|w| parse_filter(w)).collect::<Result<Vec<FilterSpec>, String>>()?;
+            client.search(&search_msg(&filters, &equality, grep, case, links)).map(Output::List)
+        },
+        "toc" => {
+            let rest = args.rest()?;
+            let [field] = rest.as_slice() else { return Err("toc takes one field".to_string()); };
+            client.call_with("Toc", &(field_arg(field)?,)).map(|r| Output::List(split_list(&r)))
+        },
+        "add" => {
+            let marks = args.values("--mark")?;
+            let types = args.values("--type")?;
+            let files = args.rest()?;
+            if files.is_empty() || marks.is_empty() {
+                return Err("add needs files and at least one --mark".to_string());
+            }
+            client.change("Modify", &add_record_msg(&files, &marks, &types))
+        },
+        "tag" | "untag" => {
+            let mut types = args.values("--type")?;
+            let rest = args.rest()?;
+            let Some((file, marks)) = rest.split_first() else {
+                return Err(format!("{command} needs a file and marks"));
+            };
+            if marks.is_empty() {
+                return Err(format!("{command} needs at least one mark"));
+            }
+            if types.is_empty() {
+                types = client.types_of(file)?;
+            }
+            let msg = if command == "tag" { mark_update_msg(file, &types, &[], marks) }
+                else { mark_update_msg(file, &types, marks, &[]) };
+            client.change("Modify", &msg)
+        },
+        "rm" => {
+            let field = args.value("--field")?.unwrap_or("file".to_string());
+            let rest = args.rest()?;
+            let [value] = rest.as_slice() else { return Err("rm takes one value".to_string()); };
+            client.change("Modify", &target_remove_msg(field_arg(&field)?, value))
+        },
+        "import" => {
+            let (links, wait) = (args.flag("--links"), args.flag("--wait"));
+            let rest = args.rest()?;
+            let [source, log] = rest.as_slice() else { return Err("import takes a source and a log file".to_string()); };
+            client.job(&import_msg(source, log, links), wait)
+        },
+        "export" => {
+            let wait = args.flag("--wait");
+            let rest = args.rest()?;
+            let [target] = rest.as_slice() else { return Err("export takes one target file".to_string()); };
+            client.job(&export_msg(target), wait)
+        },
+        "backup" => backup(client, args),
+        "config" => config(client, args),
+        _ => Err(format!("unknown command: {command}"))
+    }
+}
+
+
+fn backup(client: &Client, mut args: Args) -> Result<Output, String> {
+    let mut options: Vec<(&str, String)> = vec![];
+    for (option, key) in [("--mult", "mult"), ("--freq", "freq"), ("--base", "base"), ("--loc", "loc")] {
+        if let Some(v) = args.value(option)? {
+            options.push((key, v));
+        }
+    }
+    let rest = args.rest()?;
+    let words: Vec<&str> = rest.iter().map(|w| w.as_str()).collect();
+    match words.as_slice() {
+        [] | ["info"] if options.is_empty() => client.json("Backup", &vec![]),
+        ["now"] if options.is_empty() => client.change("Manage", &backup_msg(&[("force", "true".to_string())])),
+        ["clear"] if options.is_empty() => client.change("Manage", &backup_msg(&[("clear", "true".to_string())])),
+        ["load", file] if options.is_empty() => client.change("Manage", &backup_msg(&[("load", file.to_string())])),
+        ["set"] if !options.is_empty() => client.change("Manage", &backup_msg(&options)),
+        _ => Err("backup takes info, now, clear, load FILE, or set with options".to_string())
+    }
+}
+
+
+fn config(client: &Client, mut args: Args) -> Result<Output, String> {
+    let trunk = args.value("--trunk")?;
+    let include = args.values("--include")?;
+    let exclude = args.values("--exclude")?;
+    let add_include = args.values("--add-include")?;
+    let add_exclude = args.values("--add-exclude")?;
+    let remove_include = args.values("--remove-include")?;
+    let remove_exclude = args.values("--remove-exclude")?;
+    let rewrite = args.flag("--rewrite");
+    let rest = args.rest()?;
+    let words: Vec<&str> = rest.iter().map(|w| w.as_str()).collect();
+    let names = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    match words.as_slice() {
+        ["show"] => client.json("Repositories", &vec![]),
+        ["types"] => client.json("Manage", &list_types_msg()),
+        ["source", db] => client.change("Manage", &set_source_msg(db)),
+        ["setrepo"] => client.change("Manage", &set_repo_msg(trunk.as_deref(), &include, &exclude)),
+        ["modrepo"] => client.change("Manage", &modify_repo_msg((&remove_include, &remove_exclude), (&add_include, &add_exclude))),
+        [kw @ ("addtype" | "addsuffix" | "remsuffix"), name, suffixes @ ..] if !suffixes.is_empty() => {
+            client.change("Manage", &type_suffixes_msg(kw, name, &names(suffixes)))
+        },
+        ["remtype", name] => client.change("Manage", &remove_type_msg(name)),
+        ["renametype", old, new] => client.change("Manage", &rename_type_msg(old, new, rewrite)),
+        _ => Err("unknown or incomplete config command".to_string())
+    }
+}
+
+
+fn print(output: Output, as_json: bool) {
+    match (output, as_json) {
+        (Output::Done, true) => println!("{}", json::object!{ ok: true }.dump()),
+        (Output::Done, false) => {},
+        (Output::List(items), true) => println!("{}", json::JsonValue::from(items).dump()),
+        (Output::List(items), false) => items.iter().for_each(|i| println!("{i}")),
+        (Output::Json(j), true) => println!("{}", j.dump()),
+        (Output::Json(j), false) => println!("{}", json::stringify_pretty(j, 4)),
+        (Output::Job(id), true) => println!("{}", json::object!{ job: id }.dump()),
+        (Output::Job(id), false) => println!("started job {id}")
+    }
+}
+
+
+fn fail(message: &str, as_json: bool) -> ! {
+    if as_json {
+        println!("{}", json::object!{ error: message }.dump());
+    } else {
+        eprintln!("memoctl: {message}");
+    }
+    process::exit(1);
+}
+
+
+fn main() {
+    let mut args = Args { words: env::args().skip(1).collect() };
+    if args.flag("--help") {
+        println!("{USAGE}");
+        return;
+    }
+    let as_json = args.flag("--json");
+    let mut bus = BusConfig::default();
+    if args.flag("--system") {
+        bus.bus = BusType::System;
+    }
+    if args.flag("--session") {
+        bus.bus = BusType::Session;
+    }
+    match (args.value("--name"), args.value("--path")) {
+        (Ok(name), Ok(path)) => {
+            bus.name = name.unwrap_or(bus.name);
+            bus.path = path.unwrap_or(bus.path);
+        },
+        (Err(e), _) | (_, Err(e)) => fail(&e, as_json)
+    }
+    if let Err(e) = bus.validate() {
+        fail(&e, as_json);
+    }
+    // only global flags were given
+    if args.words.is_empty() {
+        println!("{USAGE}");
+        return;
+    }
+    let conn = match bus.bus {
+        BusType::Session => Connection::session(),
+        BusType::System => Connection::system()
+    };
+    let client = match conn {
+        Ok(conn) => Client { conn, bus },
+        Err(e) => fail(&format!("could not connect to the {} bus: {e}", bus.bus), as_json)
+    };
+    let command = args.words.remove(0);
+    match run(&client, &command, args) {
+        Ok(output) => print(output, as_json),
+        Err(e) => fail(&e, as_json)
+    }
+}
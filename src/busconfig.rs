@@ -0,0 +1,176 @@
+//  busconfig.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+use std::fmt;
+use zbus::connection::Builder;
+use zbus::names::WellKnownName;
+use zbus::zvariant::ObjectPath;
+
+
+// Where the service sits on the bus. Defaults are the session bus and the names below; the
+// "dbus" section of conf.json overrides them, and command-line flags (see options.rs) override
+// both, so that several instances (one per archive) can run side by side under different names.
+
+pub const DEFAULT_BUS_NAME: &str = "org.memobook.memoserv1";
+pub const DEFAULT_OBJECT_PATH: &str = "/org/memobook/memoserv1";
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BusType {
+    Session,
+    System
+}
+
+
+impl BusType {
+
+    pub fn from_name(name: &str) -> Result<BusType, String> {
+        match name {
+            "session" => Ok(BusType::Session),
+            "system" => Ok(BusType::System),
+            _ => Err(format!("unknown bus type: {name}"))
+        }
+    }
+
+}
+
+
+impl fmt::Display for BusType {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusType::Session => write!(f, "session"),
+            BusType::System => write!(f, "system")
+        }
+    }
+
+}
+
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct BusConfig {
+    pub bus: BusType,
+    pub name: String,
+    pub path: String,
+    pub search_provider: bool,  // also serve org.gnome.Shell.SearchProvider2 at the path
+    pub krunner: bool           // also serve org.kde.krunner1 at the path
+}
+
+
+impl Default for BusConfig {
+
+    fn default() -> Self {
+        BusConfig {
+            bus: BusType::Session,
+            name: DEFAULT_BUS_NAME.to_string(),
+            path: DEFAULT_OBJECT_PATH.to_string(),
+            search_provider: false,
+            krunner: false
+        }
+    }
+
+}
+
+
+impl BusConfig {
+
+    /// Read the optional "dbus" section; missing entries keep their defaults
+    pub fn read(rawjson: &json::JsonValue) -> Result<BusConfig, String> {
+        let mut bc = BusConfig::default();
+        let section = &rawjson["dbus"];
+        if section.is_null() {
+            return Ok(bc);
+        }
+        if !section.is_object() {
+            return Err("Parse error on dbus settings".to_string());
+        }
+        if !section["bus"].is_null() {
+            bc.bus = BusType::from_name(section["bus"].as_str().ok_or("Parse error on dbus bus type")?)?;
+        }
+        if !section["name"].is_null() {
+            bc.name = section["name"].as_str().ok_or("Parse error on dbus name")?.to_string();
+        }
+        if !section["path"].is_null() {
+            bc.path = section["path"].as_str().ok_or("Parse error on dbus path")?.to_string();
+        }
+        if !section["search_provider"].is_null() {
+            bc.search_provider = section["search_provider"].as_bool().ok_or("Parse error on dbus search provider switch")?;
+        }
+        if !section["krunner"].is_null() {
+            bc.krunner = section["krunner"].as_bool().ok_or("Parse error on dbus krunner switch")?;
+        }
+        bc.validate()?;
+        Ok(bc)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        WellKnownName::try_from(self.name.as_str()).map_err(|_| format!("invalid bus name: {}", self.name))?;
+        ObjectPath::try_from(self.path.as_str()).map_err(|_| format!("invalid object path: {}", self.path))?;
+        Ok(())
+    }
+
+    /// A connection builder on the chosen bus, requesting the chosen name
+    pub fn builder(&self) -> zbus::Result<Builder<'_>> {
+        let builder = match self.bus {
+            BusType::Session => Builder::session()?,
+            BusType::System => Builder::system()?
+        };
+        builder.name(self.name.as_str())
+    }
+
+}
+
+
+impl fmt::Display for BusConfig {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bus, name {}, path {}", self.bus, self.name, self.path)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_read_section() {
+        let bc = BusConfig::read(&json::parse(r#"{"database": {}}"#).unwrap()).unwrap();
+        assert_eq!(bc, BusConfig::default());
+        let raw = json::parse(r#"{"dbus": {"bus": "system", "name": "org.memobook.work"}}"#).unwrap();
+        let bc = BusConfig::read(&raw).unwrap();
+        assert_eq!((bc.bus, bc.name.as_str(), bc.path.as_str()), (BusType::System, "org.memobook.work", DEFAULT_OBJECT_PATH));
+        assert!(!bc.search_provider);
+        assert!(BusConfig::read(&json::parse(r#"{"dbus": {"search_provider": true}}"#).unwrap()).unwrap().search_provider);
+        assert!(BusConfig::read(&json::parse(r#"{"dbus": {"search_provider": "yes"}}"#).unwrap()).is_err());
+        assert!(BusConfig::read(&json::parse(r#"{"dbus": {"krunner": true}}"#).unwrap()).unwrap().krunner);
+        assert!(BusConfig::read(&json::parse(r#"{"dbus": {"bus": "tram"}}"#).unwrap()).is_err());
+        assert!(BusConfig::read(&json::parse(r#"{"dbus": {"name": "no dots"}}"#).unwrap()).is_err());
+        assert!(BusConfig::read(&json::parse(r#"{"dbus": {"path": "relative/path"}}"#).unwrap()).is_err());
+    }
+
+}
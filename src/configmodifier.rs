@@ -30,6 +30,12 @@ use memobook::repository::Repository;
 pub enum ConfigModifier {
     SetSource(String),
     SetRepo(Repository),
-    ModifyRepo((Repository, Repository))//,
+    ModifyRepo((Repository, Repository)),
     //ModifyBackup(String)
+    ListTypes,
+    AddType(String, Vec<String>),
+    RemoveType(String),
+    AddSuffixes(String, Vec<String>),
+    RemoveSuffixes(String, Vec<String>),
+    RenameType(String, String, bool)    // old name, new name, rewrite database records
 }
@@ -0,0 +1,667 @@
+//  http.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+use std::convert::Infallible;
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{AUTHORIZATION, CONNECTION, CONTENT_TYPE, HOST, WWW_AUTHENTICATE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use json::JsonValue;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use memobook::backer::Backer;
+use memobook::backerparserjson::BackerParserJSON;
+use memobook::mberror::MBError;
+use crate::{MemoBookServer, manage_access, scored_json};
+use crate::access::{Access, Role};
+use crate::message::*;
+use crate::ops::OpError;
+use crate::parse::*;
+use crate::rpc::{bind_socket, reply_json};
+
+
+// A small REST interface for curl and the browser, on localhost or a Unix socket. Bodies and
+// replies are json; the routes build the same messages the D-Bus methods take, so they parse,
+// lock and check access the same way.
+//   POST   /search                      {"filters": [{"field", "logic", "terms"}], "equality",
+//                                        "grep", "grepcase", "greplinks", "scored"}
+//   GET    /toc/FIELD
+//   POST   /records                     {"files", "marks", "types"}
+//   DELETE /records                     {"file"}
+//   GET    /marks
+//   POST   /marks                       {"file", "types", "remove", "add"}
+//   DELETE /marks/MARK
+//   GET    /backups
+//   POST   /backups                     {"force": true, "mult": 3, ...} as for the backup message
+//   GET    /configuration
+//   PUT    /configuration/source        {"source"}
+//   PUT    /configuration/repositories  {"trunk", "include", "exclude"}
+//   POST   /configuration/types         {"name", "suffixes"}
+//   DELETE /configuration/types/NAME
+//   POST   /exit
+// With a token set, requests need "Authorization: Bearer TOKEN". Callers on the Unix socket get
+// their role from their uid; those over tcp get the section's role, or the default one, once
+// they give the token, and are read-only without one. Against pages in a browser, the Host must
+// be localhost and requests other than GET must carry "Content-Type: application/json".
+
+pub const MAX_BODY: usize = 1 << 20;
+
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Listen {
+    Tcp(SocketAddr),
+    Unix(String)
+}
+
+
+impl Listen {
+
+    /// A loopback address and port (or localhost:PORT), or the path of a Unix socket
+    pub fn parse(s: &str) -> Result<Listen, String> {
+        if s.starts_with('/') {
+            return Ok(Listen::Unix(s.to_string()));
+        }
+        let addr: SocketAddr = match s.strip_prefix("localhost:") {
+            Some(port) => SocketAddr::from((Ipv4Addr::LOCALHOST, port.parse::<u16>().map_err(|_| format!("bad http port in {s}"))?)),
+            None => s.parse().map_err(|_| format!("bad http address {s}"))?
+        };
+        if !addr.ip().is_loopback() {
+            return Err(format!("http address {s} is not on localhost"));
+        }
+        Ok(Listen::Tcp(addr))
+    }
+
+}
+
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Listen::Tcp(addr) => write!(f, "http://{addr}"),
+            Listen::Unix(path) => write!(f, "the http socket {path}")
+        }
+    }
+}
+
+
+/// The "http" section of conf.json:
+///   "http": { "listen": "127.0.0.1:8377", "token": "...", "role": "read-write", "shared": false }
+#[derive(Clone, PartialEq, Debug)]
+pub struct HttpConfig {
+    pub listen: Listen,
+    pub token: Option<String>,
+    pub role: Option<Role>,     // for tcp callers
+    pub shared: bool            // for a Unix socket
+}
+
+
+pub enum HttpListener {
+    Tcp(TcpListener),
+    Unix(UnixListener)
+}
+
+
+impl HttpConfig {
+
+    pub fn new(listen: Listen) -> HttpConfig {
+        HttpConfig { listen, token: None, role: None, shared: false }
+    }
+
+    pub fn read(rawjson: &JsonValue) -> Result<Option<HttpConfig>, String> {
+        let section = &rawjson["http"];
+        if section.is_null() {
+            return Ok(None);
+        }
+        let listen = Listen::parse(section["listen"].as_str().ok_or("Parse error on http listen address")?)?;
+        let token = match &section["token"] {
+            JsonValue::Null => None,
+            x => Some(x.as_str().filter(|t| !t.is_empty()).ok_or("Parse error on http token")?.to_string())
+        };
+        let role = match &section["role"] {
+            JsonValue::Null => None,
+            x => Some(Role::from_name(x.as_str().ok_or("Parse error on http role")?)?)
+        };
+        let shared = match &section["shared"] {
+            JsonValue::Null => false,
+            x => x.as_bool().ok_or("Parse error on http shared switch")?
+        };
+        if role.is_some() && token.is_none() && matches!(listen, Listen::Tcp(_)) {
+            return Err("an http role over tcp needs a token".to_string());
+        }
+        Ok(Some(HttpConfig { listen, token, role, shared }))
+    }
+
+    pub async fn bind(&self) -> io::Result<HttpListener> {
+        match &self.listen {
+            Listen::Tcp(addr) => Ok(HttpListener::Tcp(TcpListener::bind(addr).await?)),
+            Listen::Unix(path) => Ok(HttpListener::Unix(bind_socket(path, self.shared)?))
+        }
+    }
+
+}
+
+
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: StatusCode,
+    pub message: String
+}
+
+
+impl HttpError {
+
+    pub fn new(status: StatusCode, message: impl ToString) -> HttpError {
+        HttpError { status, message: message.to_string() }
+    }
+
+    fn bad(message: impl ToString) -> HttpError {
+        HttpError::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    fn invalid(e: MBError) -> HttpError {
+        HttpError::bad(e)
+    }
+
+    fn failed(e: OpError) -> HttpError {
+        HttpError::new(StatusCode::INTERNAL_SERVER_ERROR, e)
+    }
+
+    fn denied(e: OpError) -> HttpError {
+        HttpError::new(StatusCode::FORBIDDEN, e)
+    }
+
+}
+
+
+/// Accept clients until the server exits
+pub async fn serve<B>(core: MemoBookServer<B>, config: HttpConfig, listener: HttpListener)
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    let config = Arc::new(config);
+    loop {
+        let accepted = match &listener {
+            HttpListener::Tcp(l) => l.accept().await.map(|(stream, _)| {
+                // any local process, or page in a browser, can reach a port
+                let role = match config.token {
+                    Some(_) => config.role.unwrap_or(core.access.default),
+                    None => Role::ReadOnly
+                };
+                tokio::spawn(connection(core.clone(), config.clone(), stream, role));
+            }),
+            HttpListener::Unix(l) => l.accept().await.map(|(stream, _)| {
+                let uid = stream.peer_cred().ok().map(|c| c.uid());
+                let role = core.access.role_of(uid, &[]);
+                tokio::spawn(connection(core.clone(), config.clone(), stream, role));
+            })
+        };
+        if let Err(e) = accepted {
+            println!("http socket error: {e}");
+        }
+    }
+}
+
+
+async fn connection<B, S>(core: MemoBookServer<B>, config: Arc<HttpConfig>, stream: S, role: Role)
+where B: Backer+BackerParserJSON + std::marker::Send+'static,
+      S: AsyncRead+AsyncWrite + Unpin+Send+'static
+{
+    let exiting = Arc::new(AtomicBool::new(false));
+    let service = service_fn(|req| {
+        let (core, config, exiting) = (core.clone(), config.clone(), exiting.clone());
+        async move {
+            let response = handle(&core, &config, role, &exiting, req).await;
+            core.touch();
+            Ok::<_, Infallible>(response)
+        }
+    });
+    if let Err(e) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+        println!("http connection error: {e}");
+    }
+    // the exit reply closes the connection, so it has gone out by now
+    if exiting.load(Ordering::SeqCst) {
+        core.request_exit();
+    }
+}
+
+
+fn reply(status: StatusCode, body: Option<JsonValue>) -> Response<Full<Bytes>> {
+    let mut response = Response::builder().status(status);
+    let body = match body {
+        Some(b) => {
+            response = response.header(CONTENT_TYPE, "application/json");
+            Full::new(Bytes::from(b.dump()))
+        },
+        None => Full::new(Bytes::new())
+    };
+    response.body(body).unwrap()
+}
+
+
+fn error_reply(e: HttpError) -> Response<Full<Bytes>> {
+    reply(e.status, Some(json::object!{ error: e.message }))
+}
+
+
+/// Whether the Host header names this machine, so that a page whose name was pointed at
+/// 127.0.0.1 cannot reach the server through the browser
+fn local_host(host: &str) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !name.is_empty() && !port.contains(']') && port.parse::<u16>().is_ok() => name,
+        _ => host
+    };
+    name.eq_ignore_ascii_case("localhost") || name.trim_start_matches('[').trim_end_matches(']')
+        .parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+
+/// Whether the body is declared json; forms posted across sites cannot say so
+fn json_content(req: &Request<Incoming>) -> bool {
+    req.headers().get(CONTENT_TYPE).and_then(|h| h.to_str().ok())
+        .and_then(|t| t.split(';').next())
+        .is_some_and(|t| t.trim().eq_ignore_ascii_case("application/json"))
+}
+
+
+/// Compared in full, so that the time taken says nothing of where a guess went wrong
+fn same_token(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len() && given.iter().zip(token).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+
+async fn handle<B>(core: &MemoBookServer<B>, config: &HttpConfig, role: Role, exiting: &AtomicBool, req: Request<Incoming>) -> Response<Full<Bytes>>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    if !req.headers().get(HOST).and_then(|h| h.to_str().ok()).is_some_and(local_host) {
+        return error_reply(HttpError::new(StatusCode::MISDIRECTED_REQUEST, "the host must be localhost"));
+    }
+    if let Some(token) = &config.token {
+        let given = req.headers().get(AUTHORIZATION).and_then(|h| h.as_bytes().strip_prefix(b"Bearer "));
+        if !given.is_some_and(|g| same_token(g, token.as_bytes())) {
+            let mut response = error_reply(HttpError::new(StatusCode::UNAUTHORIZED, "a bearer token is needed"));
+            response.headers_mut().insert(WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+            return response;
+        }
+    }
+    if req.method() != Method::GET && !json_content(&req) {
+        return error_reply(HttpError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "the content type must be application/json"));
+    }
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let body = match Limited::new(req.into_body(), MAX_BODY).collect().await {
+        Ok(b) => b.to_bytes(),
+        Err(_) => return error_reply(HttpError::new(StatusCode::PAYLOAD_TOO_LARGE, "request body too large"))
+    };
+    let body = if body.is_empty() {
+        JsonValue::Null
+    } else {
+        match std::str::from_utf8(&body).ok().and_then(|b| json::parse(b).ok()) {
+            Some(b) => b,
+            None => return error_reply(HttpError::bad("request body is not json"))
+        }
+    };
+    match route(core, role, &method, &path, &body).await {
+        Ok(result) => {
+            let mut response = match result {
+                JsonValue::Null => reply(StatusCode::NO_CONTENT, None),
+                r => reply(StatusCode::OK, Some(r))
+            };
+            if method == Method::POST && path == "/exit" {
+                exiting.store(true, Ordering::SeqCst);
+                response.headers_mut().insert(CONNECTION, "close".parse().unwrap());
+            }
+            response
+        },
+        Err(e) => error_reply(e)
+    }
+}
+
+
+/// Undo the percent-encoding of a path segment
+fn decode(segment: &str) -> Result<String, HttpError> {
+    let bytes = segment.as_bytes();
+    let mut out: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = segment.get(i+1..i+3).and_then(|h| u8::from_str_radix(h, 16).ok())
+                .ok_or_else(|| HttpError::bad(format!("bad escape in {segment}")))?;
+            out.push(hex);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| HttpError::bad(format!("bad escape in {segment}")))
+}
+
+
+/// A list of strings from the body, empty when missing
+fn strings(body: &JsonValue, key: &str) -> Result<Vec<String>, HttpError> {
+    match &body[key] {
+        JsonValue::Null => Ok(vec![]),
+        JsonValue::Array(items) => items.iter()
+            .map(|i| i.as_str().map(|s| s.to_string()))
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| HttpError::bad(format!("{key} must hold strings"))),
+        _ => Err(HttpError::bad(format!("{key} must be a list")))
+    }
+}
+
+
+fn string(body: &JsonValue, key: &str) -> Result<String, HttpError> {
+    body[key].as_str().map(|s| s.to_string()).ok_or_else(|| HttpError::bad(format!("{key} is needed")))
+}
+
+
+fn switch(body: &JsonValue, key: &str) -> Result<bool, HttpError> {
+    match &body[key] {
+        JsonValue::Null => Ok(false),
+        x => x.as_bool().ok_or_else(|| HttpError::bad(format!("{key} must be true or false")))
+    }
+}
+
+
+fn refs(msg: &[String]) -> Vec<&str> {
+    msg.iter().map(|s| s.as_str()).collect()
+}
+
+
+fn search_body(body: &JsonValue) -> Result<Vec<String>, HttpError> {
+    let mut filters: Vec<FilterSpec> = vec![];
+    for f in body["filters"].members() {
+        filters.push(FilterSpec { field: string(f, "field")?, logic: string(f, "logic")?, terms: strings(f, "terms")? });
+    }
+    let equality = match &body["equality"] {
+        JsonValue::Null => "file".to_string(),
+        _ => string(body, "equality")?
+    };
+    Ok(search_msg(&filters, &equality, switch(body, "grep")?, switch(body, "grepcase")?, switch(body, "greplinks")?))
+}
+
+
+/// Backup options are keyword and value pairs; values may be given as json numbers and switches
+fn backup_body(body: &JsonValue) -> Result<Vec<String>, HttpError> {
+    let options: Vec<(&str, String)> = body.entries()
+        .map(|(k, v)| (k, v.as_str().map(|s| s.to_string()).unwrap_or_else(|| v.dump())))
+        .collect();
+    Ok(backup_msg(&options))
+}
+
+
+async fn modify<B>(core: &MemoBookServer<B>, role: Role, msg: Vec<String>) -> Result<JsonValue, HttpError>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    core.access.check(Access::Write, role).map_err(HttpError::denied)?;
+    let cmd = parse_modification_msg(refs(&msg)).map_err(HttpError::invalid)?;
+    let reply = core.op_modify(cmd).await.map(|_| JsonValue::Null).map_err(HttpError::failed);
+    core.emit_changes().await;
+    reply
+}
+
+
+async fn manage<B>(core: &MemoBookServer<B>, role: Role, msg: Vec<String>) -> Result<JsonValue, HttpError>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    let request = parse_manage_msg(refs(&msg)).map_err(HttpError::invalid)?;
+    core.access.check(manage_access(&request), role).map_err(HttpError::denied)?;
+    let reply = core.op_manage(request).await.map(reply_json).map_err(HttpError::failed);
+    core.emit_changes().await;
+    reply
+}
+
+
+async fn route<B>(core: &MemoBookServer<B>, role: Role, method: &Method, path: &str, body: &JsonValue) -> Result<JsonValue, HttpError>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    let segments = path.trim_matches('/').split('/').map(decode).collect::<Result<Vec<String>, HttpError>>()?;
+    let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+    match (method, segments.as_slice()) {
+        (&Method::POST, ["search"]) => {
+            let query = parse_search_msg(refs(&search_body(body)?)).map_err(HttpError::invalid)?;
+            if switch(body, "scored")? {
+                core.op_search_scored(query).await.map(scored_json).map_err(HttpError::failed)
+            } else {
+                core.op_search(query, "Error in search").await.map(JsonValue::from).map_err(HttpError::failed)
+            }
+        },
+        (&Method::GET, ["toc", field]) => {
+            let query = parse_toc_msg(field).map_err(HttpError::invalid)?;
+            core.op_search(query, "Error in table of contents").await.map(JsonValue::from).map_err(HttpError::failed)
+        },
+        (&Method::POST, ["records"]) => {
+            modify(core, role, add_record_msg(&strings(body, "files")?, &strings(body, "marks")?, &strings(body, "types")?)).await
+        },
+        (&Method::DELETE, ["records"]) => modify(core, role, target_remove_msg("file", &string(body, "file")?)).await,
+        (&Method::GET, ["marks"]) => {
+            let query = parse_toc_msg("mark").map_err(HttpError::invalid)?;
+            core.op_search(query, "Error in table of contents").await.map(JsonValue::from).map_err(HttpError::failed)
+        },
+        (&Method::POST, ["marks"]) => {
+            let msg = mark_update_msg(&string(body, "file")?, &strings(body, "types")?, &strings(body, "remove")?, &strings(body, "add")?);
+            modify(core, role, msg).await
+        },
+        (&Method::DELETE, ["marks", mark]) => modify(core, role, target_remove_msg("mark", mark)).await,
+        (&Method::GET, ["backups"]) => Ok(reply_json(core.cfg.lock().unwrap().assemble_backup_info())),
+        (&Method::POST, ["backups"]) => manage(core, role, backup_body(body)?).await,
+        (&Method::GET, ["configuration"]) => {
+            let repositories = reply_json(core.cfg.lock().unwrap().assemble_repo_info());
+            let mut types = JsonValue::new_object();
+            for (name, suffixes) in core.op_types() {
+                types[name] = suffixes.into();
+            }
+            Ok(json::object!{ repositories: repositories, types: types })
+        },
+        (&Method::PUT, ["configuration", "source"]) => manage(core, role, set_source_msg(&string(body, "source")?)).await,
+        (&Method::PUT, ["configuration", "repositories"]) => {
+            let trunk = match &body["trunk"] {
+                JsonValue::Null => None,
+                _ => Some(string(body, "trunk")?)
+            };
+            manage(core, role, set_repo_msg(trunk.as_deref(), &strings(body, "include")?, &strings(body, "exclude")?)).await
+        },
+        (&Method::POST, ["configuration", "types"]) => {
+            manage(core, role, type_suffixes_msg("addtype", &string(body, "name")?, &strings(body, "suffixes")?)).await
+        },
+        (&Method::DELETE, ["configuration", "types", name]) => manage(core, role, remove_type_msg(name)).await,
+        (&Method::POST, ["exit"]) => {
+            core.access.check(Access::Control, role).map_err(HttpError::denied)?;
+            Ok(JsonValue::Null)
+        },
+        _ => Err(HttpError::new(StatusCode::NOT_FOUND, format!("no route {method} {path}")))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::access::AccessPolicy;
+    use crate::ops::tests::test_server;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    #[test]
+    fn test_listen() {
+        assert_eq!(Listen::parse("/run/user/1000/memoserv.http").unwrap(), Listen::Unix("/run/user/1000/memoserv.http".to_string()));
+        assert_eq!(Listen::parse("localhost:8377").unwrap(), Listen::Tcp("127.0.0.1:8377".parse().unwrap()));
+        assert!(Listen::parse("[::1]:8377").is_ok());
+        assert!(Listen::parse("0.0.0.0:8377").is_err());
+        assert!(Listen::parse("192.168.1.4:8377").is_err());
+        assert!(Listen::parse("localhost").is_err());
+    }
+
+    #[test]
+    fn test_read_config() {
+        assert_eq!(HttpConfig::read(&json::parse("{}").unwrap()).unwrap(), None);
+        let conf = HttpConfig::read(&json::parse(r#"{"http": {"listen": "127.0.0.1:8377", "token": "s3cret", "role": "read-write"}}"#).unwrap())
+            .unwrap().unwrap();
+        assert_eq!((conf.token.as_deref(), conf.role, conf.shared), (Some("s3cret"), Some(Role::ReadWrite), false));
+        assert!(HttpConfig::read(&json::parse(r#"{"http": {"listen": "127.0.0.1:8377", "token": ""}}"#).unwrap()).is_err());
+        assert!(HttpConfig::read(&json::parse(r#"{"http": {"listen": "127.0.0.1:8377", "role": "root"}}"#).unwrap()).is_err());
+        assert!(HttpConfig::read(&json::parse(r#"{"http": {"token": "s3cret"}}"#).unwrap()).is_err());
+        assert!(HttpConfig::read(&json::parse(r#"{"http": {"listen": "127.0.0.1:8377", "role": "read-write"}}"#).unwrap()).is_err());
+        assert!(HttpConfig::read(&json::parse(r#"{"http": {"listen": "/run/memoserv.http", "role": "read-write"}}"#).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_local_host() {
+        assert!(local_host("localhost"));
+        assert!(local_host("localhost:8377"));
+        assert!(local_host("127.0.0.1:8377"));
+        assert!(local_host("[::1]:8377"));
+        assert!(local_host("[::1]"));
+        assert!(!local_host("evil.example:8377"));
+        assert!(!local_host("localhost.evil.example"));
+        assert!(!local_host("192.168.1.4"));
+        assert!(!local_host(""));
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("doom%20like").unwrap(), "doom like");
+        assert_eq!(decode("caf%C3%A9").unwrap(), "café");
+        assert!(decode("100%").is_err());
+        assert!(decode("%zz").is_err());
+    }
+
+    /// One request on its own connection: the status and the json reply, if any
+    async fn request(addr: SocketAddr, method: &str, path: &str, token: Option<&str>, body: Option<JsonValue>) -> (u16, JsonValue) {
+        let auth = token.map(|t| format!("Authorization: Bearer {t}\r\n")).unwrap_or_default();
+        let content = if method == "GET" { "" } else { "Content-Type: application/json\r\n" };
+        send(addr, method, path, &format!("Host: localhost\r\n{auth}{content}"), body).await
+    }
+
+    /// A request with the given header lines
+    async fn send(addr: SocketAddr, method: &str, path: &str, headers: &str, body: Option<JsonValue>) -> (u16, JsonValue) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let body = body.map(|b| b.dump()).unwrap_or_default();
+        let head = format!("{method} {path} HTTP/1.1\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+        stream.write_all(format!("{head}{body}").as_bytes()).await.unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).await.unwrap();
+        let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse::<u16>().unwrap();
+        (status, if body.is_empty() { JsonValue::Null } else { json::parse(body).unwrap() })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_routes() {
+        let (core, dir) = test_server("http", AccessPolicy::default());
+        let mut config = HttpConfig::new(Listen::parse("127.0.0.1:0").unwrap());
+        config.token = Some("s3cret".to_string());
+        let listener = config.bind().await.unwrap();
+        let HttpListener::Tcp(l) = &listener else { panic!("not a tcp listener") };
+        let addr = l.local_addr().unwrap();
+        tokio::spawn(serve(core.clone(), config, listener));
+        let token = Some("s3cret");
+
+        assert_eq!(request(addr, "GET", "/marks", None, None).await.0, 401);
+        assert_eq!(request(addr, "GET", "/marks", Some("guess"), None).await.0, 401);
+        let auth = "Authorization: Bearer s3cret\r\n";
+        assert_eq!(send(addr, "GET", "/marks", &format!("Host: rebound.example:8377\r\n{auth}"), None).await.0, 421);
+        assert_eq!(send(addr, "GET", "/marks", auth, None).await.0, 421);
+        let form = format!("Host: localhost\r\n{auth}Content-Type: text/plain\r\n");
+        assert_eq!(send(addr, "POST", "/exit", &form, None).await.0, 415);
+        assert_eq!(send(addr, "POST", "/exit", &format!("Host: localhost\r\n{auth}"), None).await.0, 415);
+        let typed = format!("Host: 127.0.0.1:8377\r\n{auth}Content-Type: application/json; charset=utf-8\r\n");
+        assert_eq!(send(addr, "POST", "/search", &typed, Some(json::object!{ filters: [] })).await.0, 200);
+        let a = dir.join("a.txt").to_str().unwrap().to_string();
+        let b = dir.join("b.md").to_str().unwrap().to_string();
+        let added = request(addr, "POST", "/records", token, Some(json::object!{ files: [a.as_str(), b.as_str()], marks: ["doom like", "rust"] })).await;
+        assert_eq!(added, (204, JsonValue::Null));
+        assert_eq!(request(addr, "GET", "/marks", token, None).await, (200, json::array!["doom like", "rust"]));
+
+        let search = json::object!{ filters: [{ field: "mark", logic: "or", terms: ["rust"] }, { field: "type", logic: "or", terms: ["Text"] }] };
+        assert_eq!(request(addr, "POST", "/search", token, Some(search)).await, (200, json::array![a.as_str()]));
+        let update = json::object!{ file: b.as_str(), types: ["Markdown"], remove: ["rust"], add: ["notes"] };
+        assert_eq!(request(addr, "POST", "/marks", token, Some(update)).await.0, 204);
+        assert_eq!(request(addr, "DELETE", "/marks/doom%20like", token, None).await.0, 204);
+        assert_eq!(request(addr, "GET", "/toc/mark", token, None).await, (200, json::array!["notes", "rust"]));
+        assert_eq!(request(addr, "DELETE", "/records", token, Some(json::object!{ file: a.as_str() })).await.0, 204);
+        assert_eq!(request(addr, "GET", "/toc/file", token, None).await, (200, json::array![b.as_str()]));
+
+        assert_eq!(request(addr, "POST", "/configuration/types", token, Some(json::object!{ name: "Image", suffixes: ["png"] })).await.0, 204);
+        let (status, conf) = request(addr, "GET", "/configuration", token, None).await;
+        assert_eq!((status, &conf["types"]["Image"]), (200, &json::array!["png"]));
+        assert_eq!(request(addr, "DELETE", "/configuration/types/Image", token, None).await.0, 204);
+
+        assert_eq!(request(addr, "GET", "/toc/colour", token, None).await.0, 400);
+        assert_eq!(request(addr, "POST", "/records", token, Some(json::object!{ files: "a.txt" })).await.0, 400);
+        assert_eq!(request(addr, "GET", "/nowhere", token, None).await.0, 404);
+        let (status, reply) = request(addr, "PUT", "/configuration/source", token, Some(json::object!{})).await;
+        assert_eq!((status, reply["error"].as_str()), (400, Some("source is needed")));
+
+        assert_eq!(request(addr, "POST", "/exit", token, None).await.0, 204);
+        tokio::time::timeout(std::time::Duration::from_secs(5), core.stop.notified()).await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tcp_role() {
+        let (core, dir) = test_server("http_role", AccessPolicy::default());
+        let mut config = HttpConfig::new(Listen::parse("127.0.0.1:0").unwrap());
+        config.token = Some("s3cret".to_string());
+        config.role = Some(Role::ReadWrite);
+        let listener = config.bind().await.unwrap();
+        let HttpListener::Tcp(l) = &listener else { panic!("not a tcp listener") };
+        let addr = l.local_addr().unwrap();
+        tokio::spawn(serve(core.clone(), config, listener));
+        let token = Some("s3cret");
+
+        let a = dir.join("a.txt").to_str().unwrap().to_string();
+        assert_eq!(request(addr, "POST", "/records", token, Some(json::object!{ files: [a.as_str()], marks: ["rust"] })).await.0, 204);
+        let (status, reply) = request(addr, "POST", "/backups", token, Some(json::object!{ force: true })).await;
+        assert_eq!((status, reply["error"].is_string()), (403, true));
+        assert_eq!(request(addr, "POST", "/exit", token, None).await.0, 403);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(100), core.stop.notified()).await.is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tcp_without_token() {
+        // the default role is admin here, but a caller without a token may only read
+        let (core, dir) = test_server("http_tokenless", AccessPolicy::default());
+        let config = HttpConfig::new(Listen::parse("127.0.0.1:0").unwrap());
+        let listener = config.bind().await.unwrap();
+        let HttpListener::Tcp(l) = &listener else { panic!("not a tcp listener") };
+        let addr = l.local_addr().unwrap();
+        tokio::spawn(serve(core.clone(), config, listener));
+
+        let a = dir.join("a.txt").to_str().unwrap().to_string();
+        assert_eq!(request(addr, "GET", "/marks", None, None).await, (200, json::array![]));
+        assert_eq!(request(addr, "POST", "/records", None, Some(json::object!{ files: [a.as_str()], marks: ["rust"] })).await.0, 403);
+        assert_eq!(request(addr, "POST", "/exit", None, None).await.0, 403);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+}
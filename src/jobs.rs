@@ -0,0 +1,295 @@
+//  jobs.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use memobook::jobprogress::JobProgress;
+use memobook::mbfilter::MBFilter;
+use memobook::query::Query;
+use memobook::transportstruct::TransPortStruct;
+
+
+// Long operations (import, export, grep searches) run in the background as jobs. Each is
+// watched through a shared JobProgress and looked up by its id.
+
+/// Finished jobs kept for job_status; the oldest are dropped first
+pub const MAX_FINISHED_JOBS: usize = 32;
+
+
+pub enum JobWork {
+    Import(TransPortStruct),
+    Export(TransPortStruct),
+    Grep(Query<MBFilter>)
+}
+
+
+impl JobWork {
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JobWork::Import(_) => "import",
+            JobWork::Export(_) => "export",
+            JobWork::Grep(_) => "grep"
+        }
+    }
+
+}
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum JobState {
+    Running,
+    Done,
+    Failed,
+    Cancelled
+}
+
+
+impl fmt::Display for JobState {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Running => write!(f, "running"),
+            JobState::Done => write!(f, "done"),
+            JobState::Failed => write!(f, "failed"),
+            JobState::Cancelled => write!(f, "cancelled")
+        }
+    }
+
+}
+
+
+/// Why a job ended without a report
+#[derive(Clone, PartialEq, Debug)]
+pub enum JobFailure {
+    Cancelled,
+    Failed(String)
+}
+
+
+impl fmt::Display for JobFailure {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobFailure::Cancelled => write!(f, ""),
+            JobFailure::Failed(e) => write!(f, "{e}")
+        }
+    }
+
+}
+
+
+pub struct Job {
+    kind: &'static str,
+    state: JobState,
+    progress: Arc<JobProgress>,
+    message: String,     // report or error once finished
+    results: Vec<String> // hits of a grep job
+}
+
+
+/// A job as seen by a client
+#[derive(Clone, PartialEq, Debug)]
+pub struct JobStatus {
+    pub kind: &'static str,
+    pub state: JobState,
+    pub scanned: usize,
+    pub hashed: usize,
+    pub matched: usize,
+    pub message: String,
+    pub results: Vec<String>
+}
+
+
+impl JobStatus {
+
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object!{
+            kind: self.kind,
+            state: self.state.to_string(),
+            scanned: self.scanned,
+            hashed: self.hashed,
+            matched: self.matched,
+            message: self.message.as_str(),
+            results: self.results.clone()
+        }
+    }
+
+}
+
+
+#[derive(Default)]
+pub struct JobTable {
+    last: u32,
+    jobs: HashMap<u32, Job>,
+    finished: VecDeque<u32>
+}
+
+
+impl JobTable {
+
+    pub fn new() -> JobTable {
+        JobTable::default()
+    }
+
+
+    /// Register a running job; ids start at 1
+    pub fn start(&mut self, kind: &'static str) -> (u32, Arc<JobProgress>) {
+        self.last += 1;
+        let progress: Arc<JobProgress> = Arc::new(JobProgress::new());
+        self.jobs.insert(self.last, Job {
+            kind,
+            state: JobState::Running,
+            progress: progress.clone(),
+            message: "".to_string(),
+            results: vec![]
+        });
+        (self.last, progress)
+    }
+
+
+    /// Record how a job ended: its report and any hits, or its error
+    pub fn finish(&mut self, id: u32, outcome: Result<(String, Vec<String>), JobFailure>) -> JobState {
+        let Some(job) = self.jobs.get_mut(&id) else {
+            return JobState::Failed;
+        };
+        (job.state, job.message, job.results) = match outcome {
+            Ok((report, results)) => (JobState::Done, report, results),
+            Err(JobFailure::Cancelled) => (JobState::Cancelled, "".to_string(), vec![]),
+            Err(JobFailure::Failed(e)) => (JobState::Failed, e, vec![])
+        };
+        let state: JobState = job.state;
+        self.finished.push_back(id);
+        while self.finished.len() > MAX_FINISHED_JOBS {
+            if let Some(old) = self.finished.pop_front() {
+                self.jobs.remove(&old);
+            }
+        }
+        state
+    }
+
+
+    /// Ask a running job to stop; false if there is no such job running
+    pub fn cancel(&self, id: u32) -> bool {
+        match self.jobs.get(&id) {
+            Some(job) if job.state == JobState::Running => {
+                job.progress.cancel();
+                true
+            },
+            _ => false
+        }
+    }
+
+
+    /// Ask every running job to stop; returns how many were asked
+    pub fn cancel_all(&self) -> usize {
+        self.jobs.values()
+            .filter(|job| job.state == JobState::Running)
+            .inspect(|job| job.progress.cancel())
+            .count()
+    }
+
+
+    /// Jobs not yet finished
+    pub fn running(&self) -> usize {
+        self.jobs.values().filter(|job| job.state == JobState::Running).count()
+    }
+
+
+    pub fn status(&self, id: u32) -> Option<JobStatus> {
+        let job = self.jobs.get(&id)?;
+        let (scanned, hashed, matched) = job.progress.snapshot();
+        Some(JobStatus {
+            kind: job.kind,
+            state: job.state,
+            scanned,
+            hashed,
+            matched,
+            message: job.message.to_string(),
+            results: job.results.clone()
+        })
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_job_lifecycle() {
+        let mut table = JobTable::new();
+        let (first, progress) = table.start("import");
+        let (second, _) = table.start("grep");
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(table.running(), 2);
+        progress.hashed(3);
+        let status = table.status(first).unwrap();
+        assert_eq!((status.kind, status.state, status.hashed), ("import", JobState::Running, 3));
+        assert_eq!(table.finish(first, Ok(("imported 3 records".to_string(), vec![]))), JobState::Done);
+        assert_eq!(table.status(first).unwrap().message, "imported 3 records");
+        assert!(!table.cancel(first));
+        assert!(table.cancel(second));
+        assert_eq!(table.finish(second, Err(JobFailure::Cancelled)), JobState::Cancelled);
+        assert_eq!(table.running(), 0);
+        assert!(!table.cancel(7));
+        assert!(table.status(7).is_none());
+        let (third, progress) = table.start("grep");
+        assert_eq!(table.cancel_all(), 1);
+        assert!(progress.check().is_err());
+        table.finish(third, Err(JobFailure::Cancelled));
+        assert_eq!(table.cancel_all(), 0);
+    }
+
+    #[test]
+    fn test_cancel_after_work_done() {
+        // a job that finished its work before it saw the cancel keeps its report
+        let mut table = JobTable::new();
+        let (id, _) = table.start("export");
+        assert!(table.cancel(id));
+        assert_eq!(table.finish(id, Ok(("export to out.json complete".to_string(), vec![]))), JobState::Done);
+        assert_eq!(table.status(id).unwrap().message, "export to out.json complete");
+        let (id, _) = table.start("grep");
+        assert!(table.cancel(id));
+        assert_eq!(table.finish(id, Err(JobFailure::Failed("grep error".to_string()))), JobState::Failed);
+    }
+
+    #[test]
+    fn test_finished_jobs_pruned() {
+        let mut table = JobTable::new();
+        for _ in 0..MAX_FINISHED_JOBS + 2 {
+            let (id, _) = table.start("export");
+            table.finish(id, Err(JobFailure::Failed("export error".to_string())));
+        }
+        let (running, _) = table.start("export");
+        assert!(table.status(1).is_none());
+        assert!(table.status(2).is_none());
+        assert_eq!(table.status(3).unwrap().state, JobState::Failed);
+        assert_eq!(table.status(running).unwrap().state, JobState::Running);
+    }
+
+}
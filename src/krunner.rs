@@ -0,0 +1,178 @@
+//  krunner.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+use std::collections::HashMap;
+use std::path::Path;
+use zbus::{fdo, interface};
+use zbus::zvariant::OwnedValue;
+use memobook::backer::Backer;
+use memobook::backerparserjson::BackerParserJSON;
+use crate::{MemoBookServer, failed, owned_value};
+use crate::searchprovider::icon_for;
+
+
+// org.kde.krunner1, so that typing a mark in KRunner lists the files carrying it. The query's
+// words are matched as the GNOME provider matches its terms (see searchprovider.rs), files are
+// grouped by their types in the mime table, and those whose marks equal the words rank first.
+// Served at the object path when "krunner" is set in the "dbus" section (or --krunner);
+// KRunner finds it from a .desktop file, see the README.
+
+/// Matches reported per query, as each costs a lookup
+pub const MAX_MATCHES: usize = 25;
+
+/// KRunner's moderate category relevance
+pub const CATEGORY_RELEVANCE: i32 = 50;
+
+pub const OPEN_FOLDER: &str = "open-folder";
+
+/// A match on the wire: id, text, icon, category relevance, relevance and properties
+pub type RunnerMatch = (String, String, String, i32, f64, HashMap<String, OwnedValue>);
+
+
+pub struct Runner<B: Backer+BackerParserJSON + std::marker::Send+'static> {
+    core: MemoBookServer<B>
+}
+
+
+impl<B> Runner<B>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+
+    pub fn new(core: MemoBookServer<B>) -> Runner<B> {
+        Runner { core }
+    }
+
+}
+
+
+/// Half for matching at all, the rest for words that are whole marks
+fn relevance(words: &[String], marks: &[String]) -> f64 {
+    let whole = words.iter().filter(|w| marks.iter().any(|m| m.eq_ignore_ascii_case(w))).count();
+    0.5 + 0.5 * whole as f64 / words.len() as f64
+}
+
+
+#[interface(name = "org.kde.krunner1")]
+impl<B> Runner<B>
+where B: Backer+BackerParserJSON + std::marker::Send+std::marker::Sync+'static
+{
+
+    async fn actions(&self) -> Vec<(String, String, String)> {
+        vec![(OPEN_FOLDER.to_string(), "Open containing folder".to_string(), "document-open-folder".to_string())]
+    }
+
+    #[zbus(name = "Match")]
+    async fn find(&self, query: String) -> fdo::Result<Vec<RunnerMatch>> {
+        let words: Vec<String> = query.split_whitespace().map(|w| w.to_string()).collect();
+        if words.is_empty() {
+            return Ok(vec![]);
+        }
+        let files = self.core.op_mark_prefix_search(&words).await.map_err(failed)?;
+        let mut matches = vec![];
+        for file in files.into_iter().take(MAX_MATCHES) {
+            let marks = self.core.op_marks_of(&file).await.map_err(failed)?;
+            let types = self.core.op_types_of(&file);
+            let path = Path::new(&file);
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or(file.clone());
+            let folder = path.parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+            let mut properties = HashMap::new();
+            properties.insert("subtext".to_string(), owned_value(format!("{} in {folder}", marks.join(", "))));
+            properties.insert("category".to_string(), owned_value(&if types.is_empty() { "Files".to_string() } else { types.join(", ") }));
+            let score = relevance(&words, &marks);
+            matches.push((file.clone(), name, icon_for(&types).to_string(), CATEGORY_RELEVANCE, score, properties));
+        }
+        Ok(matches)
+    }
+
+    /// Opens the file, or its folder for that action
+    async fn run(&self, match_id: String, action_id: String) -> fdo::Result<()> {
+        self.core.op_open(match_id, action_id == OPEN_FOLDER).await.map_err(failed)
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use zbus::connection::Builder;
+    use zbus::Guid;
+    use crate::access::AccessPolicy;
+    use crate::message::add_record_msg;
+    use crate::ops::tests::test_server;
+    use crate::parse::parse_modification_msg;
+
+    const PATH: &str = "/org/memobook/memoserv1";
+
+    #[test]
+    fn test_relevance() {
+        let words = |w: &[&str]| w.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        assert_eq!(relevance(&words(&["doom"]), &words(&["Doom", "rust"])), 1.0);
+        assert_eq!(relevance(&words(&["doo", "rust"]), &words(&["Doomlike", "rust"])), 0.75);
+        assert_eq!(relevance(&words(&["doo"]), &words(&["Doomlike"])), 0.5);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_runner() {
+        let (core, dir) = test_server("krunner", AccessPolicy::default());
+        let a = dir.join("a.txt").to_str().unwrap().to_string();
+        let b = dir.join("b.md").to_str().unwrap().to_string();
+        for (file, marks) in [(&a, ["doom", "rust"]), (&b, ["doomlike", "notes"])] {
+            let msg = add_record_msg(std::slice::from_ref(file), &marks.map(|m| m.to_string()), &[]);
+            core.op_modify(parse_modification_msg(msg.iter().map(|s| s.as_str()).collect()).unwrap()).await.unwrap();
+        }
+
+        let (p0, p1) = UnixStream::pair().unwrap();
+        let server = Builder::async_io_unix_stream(p0).server(Guid::generate()).unwrap().p2p()
+            .serve_at(PATH, Runner::new(core.clone())).unwrap()
+            .build();
+        let (server, client) = tokio::join!(server, Builder::async_io_unix_stream(p1).p2p().build());
+        let (_server, client) = (server.unwrap(), client.unwrap());
+        let call = |method: &'static str, body: String| {
+            let client = client.clone();
+            async move { client.call_method(None::<&str>, PATH, Some("org.kde.krunner1"), method, &(body,)).await }
+        };
+
+        let mut found: Vec<RunnerMatch> = call("Match", "Doom".to_string()).await.unwrap().body().deserialize().unwrap();
+        found.sort_by(|x, y| y.4.total_cmp(&x.4));
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].0.as_str(), found[0].1.as_str(), found[0].4), (a.as_str(), "a.txt", 1.0));
+        assert_eq!((found[1].0.as_str(), found[1].2.as_str(), found[1].4), (b.as_str(), "text-x-generic", 0.5));
+        assert_eq!(found[0].5["category"].downcast_ref::<String>().unwrap(), "Text");
+        assert_eq!(found[1].5["subtext"].downcast_ref::<String>().unwrap(), format!("doomlike, notes in {}", dir.to_str().unwrap()));
+        let found: Vec<RunnerMatch> = call("Match", "doom no".to_string()).await.unwrap().body().deserialize().unwrap();
+        assert_eq!(found.len(), 1);
+        let found: Vec<RunnerMatch> = call("Match", "  ".to_string()).await.unwrap().body().deserialize().unwrap();
+        assert!(found.is_empty());
+
+        let actions: Vec<(String, String, String)> = client.call_method(None::<&str>, PATH, Some("org.kde.krunner1"), "Actions", &())
+            .await.unwrap().body().deserialize().unwrap();
+        assert_eq!(actions[0].0, OPEN_FOLDER);
+        let refused = client.call_method(None::<&str>, PATH, Some("org.kde.krunner1"), "Run", &("/etc/passwd", "")).await;
+        assert!(refused.is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+}
@@ -26,34 +26,67 @@ pub mod parse;
 pub mod prepare;
 pub mod manager;
 pub mod configmodifier;
+pub mod searchsaver;
+pub mod ops;
+pub mod serverv2;
+pub mod jobs;
+pub mod busconfig;
+pub mod access;
+pub mod message;
+pub mod rpc;
+pub mod http;
+pub mod searchprovider;
+pub mod krunner;
+pub mod stop;
+pub mod systemd;
+pub mod options;
 
 
-use event_listener::{Event};
 use memobook::configuration::Configuration;
 use memobook::backer::Backer;
 use memobook::backerparserjson::BackerParserJSON;
-use memobook::{MemoBook, Queryable};
-use zbus::{interface, object_server::SignalEmitter, Result};
-use std::sync::{Arc,Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
+use memobook::MemoBook;
+use zbus::{interface, object_server::{ResponseDispatchNotifier, SignalEmitter}, fdo, message::Header, Connection, Result};
+use zbus::zvariant::{OwnedValue, Value};
+use std::sync::{Arc,Mutex,OnceLock};
+use tokio::sync::RwLock;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
 use memobook::mbfilter::MBFilter;
 use memobook::query::Query;
 use memobook::modifiers::Modifier;
+use memobook::savedsearch::query_to_json;
 use crate::parse::*;
-use crate::prepare::*;
+use crate::searchsaver::SearchSaver;
+use crate::ops::{OpError, SavedReply};
+use crate::jobs::{JobTable, JobWork};
 use crate::manager::Manager;
 use crate::configmodifier::ConfigModifier;
+use crate::access::{Access, AccessPolicy};
 
 
 
+/// Notices kept for a socket client that has fallen behind
+pub const NOTICE_BACKLOG: usize = 64;
+
+
 pub struct MemoBookServer<B>
 where B: Backer+BackerParserJSON + std::marker::Send+ 'static
 {
     pub name: String,
-    pub events: Arc<Event>,
+    pub stop: Arc<tokio::sync::Notify>,   // exit requests, waited on by main
+    pub last_request: Arc<Mutex<Instant>>,  // for the idle timeout
     pub exitflag: Arc<AtomicBool>,
     pub cfg: Arc<Mutex<Configuration<B>>>,
-    pub mb: Arc<Mutex<MemoBook>>
+    pub mb: Arc<RwLock<MemoBook>>,  // read-locked by searches, write-locked by changes
+    pub cache: Arc<Mutex<HashMap<String, Vec<String>>>>,
+    pub changes: Arc<Mutex<Vec<ops::Change>>>,
+    pub jobs: Arc<Mutex<JobTable>>,
+    pub runtime: tokio::runtime::Handle,   // where background jobs run
+    pub access: Arc<AccessPolicy>,
+    pub signals: Arc<OnceLock<SignalEmitter<'static>>>,   // set once the D-Bus connection is up
+    pub notices: tokio::sync::broadcast::Sender<ops::Notice>
 }
 
 
@@ -62,28 +95,45 @@ unsafe impl<B: Backer+BackerParserJSON + std::marker::Send> Send for MemoBookSer
 
 
 
+impl<B> MemoBookServer<B>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+
+    /// A server over an opened book; background work goes to the given runtime
+    pub fn new(cfg: Configuration<B>, mb: MemoBook, access: AccessPolicy, runtime: tokio::runtime::Handle) -> MemoBookServer<B> {
+        MemoBookServer {
+            name: "MemoBook".to_string(),
+            stop: Arc::new(tokio::sync::Notify::new()),
+            last_request: Arc::new(Mutex::new(Instant::now())),
+            exitflag: Arc::new(AtomicBool::new(false)),
+            cfg: Arc::new(Mutex::new(cfg)),
+            mb: Arc::new(RwLock::new(mb)),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            changes: Arc::new(Mutex::new(Vec::new())),
+            jobs: Arc::new(Mutex::new(JobTable::new())),
+            runtime,
+            access: Arc::new(access),
+            signals: Arc::new(OnceLock::new()),
+            notices: tokio::sync::broadcast::channel(NOTICE_BACKLOG).0
+        }
+    }
+
+}
+
+
+
 #[interface(name = "org.memobook.memoserv1")]
 impl<B> MemoBookServer<B>
 where B: Backer+BackerParserJSON + std::marker::Send+'static
 {
 
     async fn toc(&self, toctype: &str) -> String {
-        let result = match parse_toc_msg(toctype) {
-            Ok(q) => {
-                let memobk = self.mb.lock().unwrap();
-                memobk.search(q)
-            },
-            Err(e) => { 
-                return format!("{e}");
-            }
-        };
-        match result {
-            Ok(r) => {
-                r.join(", ")
+        match parse_toc_msg(toctype) {
+            Ok(q) => match self.op_search(q, "").await {
+                Ok(r) => r.join(", "),
+                Err(e) => format!("{e}")
             },
-            Err(e) => { 
-                format!("{e}")
-            }
+            Err(e) => format!("{e}")
         }
     }
 
@@ -105,62 +155,203 @@ where B: Backer+BackerParserJSON + std::marker::Send+'static
             Ok(q) => q,
             Err(e) => return format!("Search error: {e}")
         };
-        let memobk = self.mb.lock().unwrap();
-        match memobk.search(clientquery) {
+        match self.op_search(clientquery, "Error in search").await {
             Ok(cq) => cq.join(", "),
-            Err(e) => { format!("Error in search: {e}") }
+            Err(e) => format!("{e}")
+        }
+    }
+
+
+    async fn search_scored(&self, vfilter: Vec<&str>) -> String {
+        let clientquery: Query<MBFilter> = match parse_search_msg(vfilter) {
+            Ok(q) => q,
+            Err(e) => return format!("Search error: {e}")
+        };
+        match self.op_search_scored(clientquery).await {
+            Ok(cq) => scored_json(cq).dump(),
+            Err(e) => format!("{e}")
+        }
+    }
+
+
+    async fn related(&self, vmarks: Vec<&str>) -> String {
+        let (marks, limit) = match parse_related_msg(vmarks) {
+            Ok(r) => r,
+            Err(e) => return format!("Related marks request error: {e}")
+        };
+        match self.op_related(marks, limit).await {
+            Ok(rel) => related_json(rel).dump(),
+            Err(e) => format!("{e}")
         }
     }
 
 
-    async fn modify(&mut self, vcommand: Vec<&str>) -> String {
-        let mut clientcmd: Modifier = match parse_modification_msg(vcommand) {
+    async fn stats(&self, topn: &str) -> String {
+        let topn: usize = match parse_stats_msg(topn) {
+            Ok(n) => n,
+            Err(e) => return format!("Stats request error: {e}")
+        };
+        match self.op_stats(topn).await {
+            Ok(st) => st.to_json().dump(),
+            Err(e) => format!("{e}")
+        }
+    }
+
+
+    async fn suggest(&self, vcommand: Vec<&str>) -> String {
+        let (file, limit) = match parse_suggest_msg(vcommand) {
+            Ok(r) => r,
+            Err(e) => return format!("Suggestion request error: {e}")
+        };
+        match self.op_suggest(file, limit).await {
+            Ok(sug) => suggestions_json(sug).dump(),
+            Err(e) => format!("{e}")
+        }
+    }
+
+
+    async fn modify(&mut self, vcommand: Vec<&str>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> String {
+        if let Err(e) = self.access.authorize(Access::Write, &header, conn).await {
+            return format!("{e}");
+        }
+        let clientcmd: Modifier = match parse_modification_msg(vcommand) {
             Ok(m) => m,
             Err(e) => return format!("Modify request error: {e}")
         };
-        { // lock memobook
-            let mut memobk = self.mb.lock().unwrap();
-            match prepare_modification(&memobk, &mut clientcmd) {
-                Ok(_) => { },
-                Err(e) => { return format!("Error in modification auxiliary search: {e}"); }
+        let reply = match self.op_modify(clientcmd).await {
+            Ok(()) => "".to_string(),
+            Err(e) => format!("{e}")
+        };
+        self.emit_changes().await;
+        reply
+    }
+
+
+    /// Modification messages back to back, applied in one transaction: all are kept, or none.
+    /// The reply is json: whether the batch was committed, and a message per modification,
+    /// empty for success.
+    async fn modify_batch(&mut self, vcommand: Vec<&str>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> String {
+        if let Err(e) = self.access.authorize(Access::Write, &header, conn).await {
+            return format!("{e}");
+        }
+        let clientcmds: Vec<Modifier> = match parse_batch_msg(vcommand) {
+            Ok(m) => m,
+            Err(e) => return format!("Modify request error: {e}")
+        };
+        let reply = match self.op_modify_batch(clientcmds).await {
+            Ok(report) => report.to_json().dump(),
+            Err(e) => format!("{e}")
+        };
+        self.emit_changes().await;
+        reply
+    }
+
+
+    /// Import and export start background jobs and reply with the job id
+    async fn manage(&mut self, vcommand: Vec<&str>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> String {
+        let request = parse_manage_msg(vcommand);
+        if let Ok(m) = &request {
+            if let Err(e) = self.access.authorize(manage_access(m), &header, conn).await {
+                return format!("{e}");
             }
-            { // lock config
-                let mut memocfg = self.cfg.lock().unwrap();
-                memocfg.check_backup(true);
-                match memobk.modify(&clientcmd) {
-                    Ok(()) => { 
-                        memocfg.mb_alt(true);
-                        "".to_string()
-                    },
-                    Err(th) => format!("Modify request returned error: {th}")
-                }
-            } // release config
-        } // release memobook
+        }
+        let reply = match request {
+            Ok(Manager::Import(imp)) => self.op_start_job(JobWork::Import(imp)).to_string(),
+            Ok(Manager::Export(exp)) => self.op_start_job(JobWork::Export(exp)).to_string(),
+            Ok(m) => self.op_manage(m).await.unwrap_or_else(|e| format!("{e}")),
+            Err(e) => format!("Manage request error: {e}")
+        };
+        self.emit_changes().await;
+        reply
+    }
+
+
+    async fn manage_no_reponse(&mut self, vcommand: Vec<&str>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) {
+        let request = parse_manage_msg(vcommand);
+        if let Ok(m) = &request {
+            if self.access.authorize(manage_access(m), &header, conn).await.is_err() {
+                return;
+            }
+        }
+        match request {
+            Ok(Manager::Import(imp)) => { self.op_start_job(JobWork::Import(imp)); },
+            Ok(Manager::Export(exp)) => { self.op_start_job(JobWork::Export(exp)); },
+            Ok(m) => { _ = self.op_manage(m).await; },
+            Err(_) => {}
+        }
+        self.emit_changes().await;
     }
 
 
-    async fn manage(&mut self, vcommand: Vec<&str>) -> String {
-        self.manage_helper(vcommand) 
+    /// A search run as a background job; replies with the job id, results come with job_status
+    async fn search_job(&self, vfilter: Vec<&str>) -> String {
+        match parse_search_msg(vfilter) {
+            Ok(q) => self.op_start_job(JobWork::Grep(q)).to_string(),
+            Err(e) => format!("Search error: {e}")
+        }
+    }
+
+
+    async fn cancel_job(&self, id: &str, #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> String {
+        if let Err(e) = self.access.authorize(Access::Jobs, &header, conn).await {
+            return format!("{e}");
+        }
+        let Ok(id) = id.trim().parse::<u32>() else {
+            return format!("Job error: bad job id {id}");
+        };
+        match self.op_cancel_job(id) {
+            Ok(()) => "".to_string(),
+            Err(e) => format!("{e}")
+        }
+    }
+
+
+    /// Kind, state, progress counts, report and results of a job, as json
+    async fn job_status(&self, id: &str) -> String {
+        let Ok(id) = id.trim().parse::<u32>() else {
+            return format!("Job error: bad job id {id}");
+        };
+        match self.op_job_status(id) {
+            Ok(status) => status.to_json().dump(),
+            Err(e) => format!("{e}")
+        }
     }
 
 
-    async fn manage_no_reponse(&mut self, vcommand: Vec<&str>) {
-        _ = self.manage_helper(vcommand);
+    async fn saved(&mut self, vcommand: Vec<&str>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> String {
+        let clientcmd: SearchSaver = match parse_saved_msg(vcommand) {
+            Ok(s) => s,
+            Err(e) => return format!("Saved search request error: {e}")
+        };
+        if clientcmd.writes() {
+            if let Err(e) = self.access.authorize(Access::Write, &header, conn).await {
+                return format!("{e}");
+            }
+        }
+        let reply = match self.op_saved(clientcmd).await {
+            Ok(SavedReply::Names(names)) => names.join(", "),
+            Ok(SavedReply::Query(q)) => query_to_json(&q).dump(),
+            Ok(SavedReply::Results(results)) => results.join(", "),
+            Ok(SavedReply::Done) => "".to_string(),
+            Err(e) => format!("{e}")
+        };
+        self.emit_changes().await;
+        reply
     }
 
 
-    async fn exit(&self) -> String {
-        { // lock memobook
-            let mut memobk = self.mb.lock().unwrap();
-            memobk.disconnect();
-            { // lock config
-                let memocfg = self.cfg.lock().unwrap();
-                memocfg.finish();
-            } // release config
-            self.exitflag.store(true, Ordering::SeqCst);
-            self.events.notify(1);
-        } // release memobook
-        "Exiting".to_string()
+    async fn exit(&self, #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> ResponseDispatchNotifier<String> {
+        if let Err(e) = self.access.authorize(Access::Control, &header, conn).await {
+            return ResponseDispatchNotifier::new(format!("{e}")).0;
+        }
+        let (reply, sent) = ResponseDispatchNotifier::new("Exiting".to_string());
+        self.exit_after(sent);
+        reply
     }
 
 
@@ -171,107 +362,97 @@ where B: Backer+BackerParserJSON + std::marker::Send+'static
 
 
     #[zbus(property)]
-    async fn set_memobook_name(&mut self, name: String) {
+    async fn set_memobook_name(&mut self, name: String, #[zbus(header)] header: Option<Header<'_>>,
+        #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        if let Some(hdr) = header {
+            self.access.authorize(Access::Write, &hdr, conn).await
+                .map_err(|e| fdo::Error::AccessDenied(e.to_string()))?;
+        }
         self.name = name;
+        Ok(())
     }
 
 
+    /// Files whose records were added, changed or removed; empty when the whole set may have changed
+    #[zbus(signal)]
+    async fn records_changed(emitter: &SignalEmitter<'_>, files: &[String]) -> Result<()>;
+
+
+    /// Section is one of source, repositories, types, backup or saved
+    #[zbus(signal)]
+    async fn configuration_changed(emitter: &SignalEmitter<'_>, section: &str) -> Result<()>;
+
+
+    /// Action is created or loaded
+    #[zbus(signal)]
+    async fn backup_changed(emitter: &SignalEmitter<'_>, action: &str) -> Result<()>;
+
+
+    /// Files scanned, hashed and matched so far by a running job
+    #[zbus(signal)]
+    async fn job_progress(emitter: &SignalEmitter<'_>, id: u32, scanned: u64, hashed: u64, matched: u64) -> Result<()>;
+
+
+    /// State is done, failed or cancelled; message is the report or error
+    #[zbus(signal)]
+    async fn job_finished(emitter: &SignalEmitter<'_>, id: u32, state: &str, message: &str) -> Result<()>;
+
+
     #[zbus(signal)]
     async fn exited(emitter: &SignalEmitter<'_>) -> Result<()>;
 
-//}
-
-
-fn manage_helper(&mut self, vcommand: Vec<&str>) -> String {
-    let clientcmd: Manager = match parse_manage_msg(vcommand) {
-        Ok(m) => m,
-        Err(e) => return format!("Manage request error: {e}")
-    };
-    match clientcmd {
-        Manager::Configure(cfg) => {
-            match cfg {
-                ConfigModifier::SetSource(ss) => {
-                    let mut memobk = self.mb.lock().unwrap();
-                    {
-                        let mut memocfg = self.cfg.lock().unwrap();
-                        memocfg.check_backup(true);
-                        memocfg.set_source(&ss);
-                    }
-                    match memobk.connect(Some(ss)) {
-                        Ok(()) => "".to_string(),
-                        Err(e) => format!("Error managing source: {e}")
-                    }
-                },
-                ConfigModifier::SetRepo(sr) => {
-                    let mut memobk = self.mb.lock().unwrap();
-                    {
-                        let mut memocfg = self.cfg.lock().unwrap();
-                        memocfg.check_backup(true);
-                        memocfg.set_repo_by_repo(sr);   
-                        match memobk.target(&memocfg.mb().scan, memocfg.mime()) {
-                            Ok(()) => "".to_string(),
-                            Err(e) => format!("Error managing repo: {e}")
-                        }
-                    }
-                },
-                ConfigModifier::ModifyRepo(mrtuple) => { 
-                    let mut memobk = self.mb.lock().unwrap();
-                    {
-                        let mut memocfg = self.cfg.lock().unwrap();
-                        memocfg.check_backup(true);
-                        memocfg.modify_repo_by_repo(mrtuple);
-                        match memobk.target(&memocfg.mb().scan, memocfg.mime()) {
-                            Ok(()) => "".to_string(),
-                            Err(e) => format!("Error managing repo: {e}")
-                        }
-                    }
-                },
-                /*ConfigModifier::ModifyBackup(bu) => {
-                    let _memobk = self.mb.lock().unwrap();
-                    match self.cfg.modify_backup(&bu) {
-                        Ok(()) => "".to_string(),
-                        Err(e) => format!("Error altering backup information: {e}")
-                    }
-                }*/
-            }
-        },
-        // IMPORT WILL ALMOST CERTAINLY ALTER THE DB, SO DO A BACKUP
-        Manager::Import(imp) => {
-            let mut memobk = self.mb.lock().unwrap();
-            {
-                let mut memocfg = self.cfg.lock().unwrap();
-                memocfg.check_backup(true);
-            }
-            match memobk.import(imp) {
-                Ok(s) => s,
-                Err(e) => format!("Error importing files: {e}")
-            }
-        },
-        Manager::Export(exp) => {
-            let memobk = self.mb.lock().unwrap();
-            match memobk.export(exp) {
-                Ok(s) => s,
-                Err(e) => format!("Error exporting: {e}")
-            }
-        },
-        Manager::Backup(bup) => {
-            let mut memobk = self.mb.lock().unwrap();
-            memobk.disconnect();
-            let connectable: Option<String>;
-            {
-                let mut memocfg = self.cfg.lock().unwrap();
-                connectable = match memocfg.process_modify_backup(&bup) {
-                    Ok(Some(s)) => Some(s),
-                    Ok(None) => None,
-                    Err(e) => return format!("Error in backup modification call: {e}")
-                };
-            }
-            match memobk.connect(connectable) {
-                Ok(_) => "".to_string(),
-                Err(x) => format!("Backup data could not be loaded: {:?}", x)
-            }
-        }
+}
+
+
+
+/// What a manage request needs. Exporting leaves the archive alone but writes a file wherever
+/// the caller names on the server, so it takes an admin like exit does.
+pub(crate) fn manage_access(request: &Manager) -> Access {
+    match request {
+        Manager::Export(_) => Access::Control,
+        Manager::Configure(ConfigModifier::ListTypes) => Access::Read,
+        _ => Access::Configure
     }
 }
 
+
+// Helpers for more than one D-Bus interface
+
+pub(crate) fn failed(e: OpError) -> fdo::Error {
+    fdo::Error::Failed(e.to_string())
+}
+
+
+/// A plain value for an a{sv} map; only values holding file descriptors fail to convert
+pub(crate) fn owned_value<'a>(v: impl Into<Value<'a>>) -> OwnedValue {
+    v.into().try_into().unwrap()
+}
+
+
+// Replies given as json by more than one transport
+
+pub(crate) fn scored_json(scored: Vec<(String, usize)>) -> json::JsonValue {
+    let mut jresults = json::JsonValue::new_array();
+    for (file, score) in scored {
+        let _ = jresults.push(json::object!{ file: file, score: score });
+    }
+    jresults
+}
+
+
+pub(crate) fn related_json(related: Vec<(String, usize)>) -> json::JsonValue {
+    let mut jresults = json::JsonValue::new_array();
+    for (mark, count) in related {
+        let _ = jresults.push(json::object!{ mark: mark, count: count });
+    }
+    jresults
+}
+
+
+pub(crate) fn suggestions_json(suggestions: Vec<memobook::suggester::Suggestion>) -> json::JsonValue {
+    let mut jresults = json::JsonValue::new_array();
+    for item in suggestions {
+        let _ = jresults.push(json::object!{ mark: item.mark, score: item.score, existing: item.existing });
+    }
+    jresults
 }
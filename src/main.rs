@@ -22,31 +22,41 @@
 //  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
 
 
-use zbus::{connection::Builder, Result};
-use event_listener::{Listener};
+use zbus::Result;
+use zbus::object_server::SignalEmitter;
 use memoserv::MemoBookServer;
+use memoserv::serverv2::MemoBookServer2;
+use memoserv::searchprovider::SearchProvider;
+use memoserv::krunner::Runner;
+use memoserv::options::{Options, USAGE};
+use memoserv::rpc;
+use memoserv::stop::{self, Stopper};
+use memoserv::systemd::{self, Units, UNITS_USAGE};
+use memoserv::http::{self, Listen};
 use memobook::{MemoBook, Queryable};
 use memobook::configuration::Configuration;
 use memobook::utckeeper::UtcKeeper;
 use std::env;
-use std::sync::{Arc,Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
 
 
 
 #[tokio::main]
 async fn main() -> Result<()> {
 
-    let cmdline: Vec<String> = env::args().collect();
-    let confaddr: &str = if cmdline.len() == 2 { &cmdline[1] }
-        else { "" };
-
-    if confaddr.is_empty() {
-        println!("Valid configuration file needed.");
+    let cmdline: Vec<String> = env::args().skip(1).collect();
+    if cmdline.first().is_some_and(|c| c == "units") {
+        units(&cmdline[1..]);
         return Ok(());
     }
+    let options = match Options::from_args(&cmdline) {
+        Ok(o) => o,
+        Err(e) => {
+            println!("{e}\n{USAGE}");
+            return Ok(());
+        }
+    };
 
-    let mut conf: Configuration<UtcKeeper> = match Configuration::read(confaddr, Some(UtcKeeper::default())) {
+    let mut conf: Configuration<UtcKeeper> = match Configuration::read(&options.confaddr, Some(UtcKeeper::default())) {
         Ok(c) => c,
         Err(e) => { 
             println!("Could not open configuration file: {:?}", e);
@@ -72,33 +82,122 @@ async fn main() -> Result<()> {
         }
     }
     
-    let mbcover = Arc::new(Mutex::new(d));
-    let exitflag = Arc::new(AtomicBool::new(false));
-
-    let memobook = MemoBookServer {
-        name: "MemoBook".to_string(),
-        cfg: Arc::new(Mutex::new(conf)),
-        events: Arc::new(event_listener::Event::new()),
-        exitflag: exitflag.clone(),
-        mb: mbcover
+    let memobook = MemoBookServer::new(conf, d, options.access, tokio::runtime::Handle::current());
+    let mut stopper = match Stopper::new(options.idle) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("Could not catch signals: {e}");
+            return Ok(());
+        }
     };
-    let events_clone = memobook.events.clone();
-    let _connection = Builder::session()?
-        .name("org.memobook.memoserv1")?
-        .serve_at("/org/memobook/memoserv1", memobook)?
-        .build()
-        .await?;
-
-    loop {
-        if exitflag.load(Ordering::SeqCst) {
-            break;
+
+    if let Some(rpcconf) = &options.rpc {
+        match rpcconf.bind() {
+            Ok(listener) => {
+                tokio::spawn(rpc::serve(memobook.clone(), listener));
+                println!("serving on the socket {}", rpcconf.socket);
+            },
+            Err(e) => {
+                println!("Could not open socket {}: {e}", rpcconf.socket);
+                return Ok(());
+            }
         }
-        let events_listener = events_clone.listen();
-        events_listener.wait();
     }
-    
+
+    if let Some(httpconf) = &options.http {
+        match httpconf.bind().await {
+            Ok(listener) => {
+                tokio::spawn(http::serve(memobook.clone(), httpconf.clone(), listener));
+                println!("serving on {}", httpconf.listen);
+            },
+            Err(e) => {
+                println!("Could not listen on {}: {e}", httpconf.listen);
+                return Ok(());
+            }
+        }
+    }
+
+    let mut _connection = None;
+    if let Some(busconf) = &options.bus {
+        let memobook2 = MemoBookServer2::new(memobook.clone());
+        let mut builder = busconf.builder()?
+            .serve_at(busconf.path.as_str(), memobook.clone())?
+            .serve_at(busconf.path.as_str(), memobook2)?;
+        if busconf.search_provider {
+            builder = builder.serve_at(busconf.path.as_str(), SearchProvider::new(memobook.clone()))?;
+        }
+        if busconf.krunner {
+            builder = builder.serve_at(busconf.path.as_str(), Runner::new(memobook.clone()))?;
+        }
+        let conn = builder.build().await?;
+        let _ = memobook.signals.set(SignalEmitter::new(&conn, busconf.path.clone())?);
+        if options.idle.is_some() {
+            tokio::spawn(stop::watch_bus(memobook.clone(), conn.clone()));
+        }
+        _connection = Some(conn);
+        println!("serving on the {busconf}");
+    }
+
+    if let Err(e) = systemd::notify("READY=1") {
+        println!("Could not notify systemd: {e}");
+    }
+    let stop = stopper.wait(&memobook).await;
+    println!("Stopping memoserv: {stop}");
+    let _ = systemd::notify("STOPPING=1");
+    memobook.shutdown().await;
+
+    if let Some(rpcconf) = &options.rpc {
+        let _ = std::fs::remove_file(&rpcconf.socket);
+    }
+    if let Some(Listen::Unix(path)) = options.http.as_ref().map(|h| &h.listen) {
+        let _ = std::fs::remove_file(path);
+    }
     println!("Shutting down memoserv.");
 
     Ok(())
 
 }
+
+
+/// `memoserv units`: writes (or prints) the systemd unit and D-Bus activation file
+fn units(args: &[String]) {
+    let exe = match env::current_exe() {
+        Ok(e) => e,
+        Err(e) => {
+            println!("Could not find the memoserv program: {e}");
+            return;
+        }
+    };
+    let units = match Units::from_args(&exe, args) {
+        Ok(u) => u,
+        Err(e) => {
+            println!("{e}\n{UNITS_USAGE}");
+            return;
+        }
+    };
+    if units.print {
+        print!("# {}\n{}", units.name, units.unit_file());
+        if let Some(activation) = units.activation_file() {
+            print!("\n# dbus-1 {}\n{activation}", units.name);
+        }
+        if let Some(policy) = units.policy_file() {
+            print!("\n# dbus-1 system.d\n{policy}");
+        }
+        return;
+    }
+    let written = units.dirs().and_then(|dirs| {
+        units.write(&dirs).map_err(|e| format!("Could not write the files: {e}"))
+    });
+    match written {
+        Ok(files) => {
+            for f in files {
+                println!("wrote {}", f.display());
+            }
+            println!("Reload with systemctl{} daemon-reload", if units.system() { "" } else { " --user" });
+            if units.system() {
+                println!("and the bus policy with systemctl reload dbus");
+            }
+        },
+        Err(e) => println!("{e}")
+    }
+}
@@ -0,0 +1,311 @@
+//  message.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+// The client side of parse.rs: the string vectors the org.memobook.memoserv1 methods expect.
+// Counts are written here so that callers never have to.
+
+
+/// A search filter: field (file, mark, type), logic (and, or) and terms
+pub struct FilterSpec {
+    pub field: String,
+    pub logic: String,
+    pub terms: Vec<String>
+}
+
+
+fn flag(b: bool) -> String {
+    if b { "true".to_string() } else { "false".to_string() }
+}
+
+
+/// Head, the number of terms that follow, then the terms
+fn counted(head: &str, terms: Vec<String>) -> Vec<String> {
+    let mut msg: Vec<String> = vec![head.to_string(), terms.len().to_string()];
+    msg.extend(terms);
+    msg
+}
+
+
+/// A keyword and its list, left out when the list is empty
+fn section(msg: &mut Vec<String>, keyword: &str, list: &[String]) {
+    if !list.is_empty() {
+        msg.extend(counted(keyword, list.to_vec()));
+    }
+}
+
+
+/// For search and search_job; also the tail of saved create and update
+pub fn search_msg(filters: &[FilterSpec], equality: &str, grep: bool, grepcase: bool, greplinks: bool) -> Vec<String> {
+    let mut terms: Vec<String> = vec![];
+    for f in filters {
+        terms.push(f.field.to_string());
+        terms.extend(counted(&f.logic, f.terms.to_vec()));
+    }
+    let mut msg: Vec<String> = vec![flag(grep), flag(grepcase), flag(greplinks)];
+    msg.extend(counted(equality, terms));
+    msg
+}
+
+
+pub fn add_record_msg(files: &[String], marks: &[String], types: &[String]) -> Vec<String> {
+    let mut terms: Vec<String> = vec![];
+    section(&mut terms, "file", files);
+    section(&mut terms, "mark", marks);
+    section(&mut terms, "type", types);
+    counted("addrecord", terms)
+}
+
+
+/// Types are those of the file's records to update
+pub fn mark_update_msg(file: &str, types: &[String], rems: &[String], adds: &[String]) -> Vec<String> {
+    let mut terms: Vec<String> = vec![file.to_string()];
+    section(&mut terms, "aux", types);
+    section(&mut terms, "rem", rems);
+    section(&mut terms, "add", adds);
+    counted("markupdate", terms)
+}
+
+
+pub fn target_remove_msg(field: &str, value: &str) -> Vec<String> {
+    counted("targetremove", vec![field.to_string(), value.to_string()])
+}
+
+
+/// Modification messages, as built above, for one batch
+pub fn batch_msg(items: &[Vec<String>]) -> Vec<String> {
+    items.concat()
+}
+
+
+pub fn import_msg(source: &str, log: &str, links: bool) -> Vec<String> {
+    counted("import", vec!["source".to_string(), source.to_string(), "log".to_string(), log.to_string(),
+        "link".to_string(), flag(links)])
+}
+
+
+pub fn export_msg(target: &str) -> Vec<String> {
+    counted("export", vec![target.to_string()])
+}
+
+
+/// Keyword and value pairs: load, base, loc, mult, freq, clear, force
+pub fn backup_msg(options: &[(&str, String)]) -> Vec<String> {
+    counted("backup", options.iter().flat_map(|(k, v)| [k.to_string(), v.to_string()]).collect())
+}
+
+
+fn configuration_msg(terms: Vec<String>) -> Vec<String> {
+    counted("configuration", terms)
+}
+
+
+fn repo_terms(include: &[String], exclude: &[String]) -> Vec<String> {
+    let mut terms: Vec<String> = vec![];
+    section(&mut terms, "include", include);
+    section(&mut terms, "exclude", exclude);
+    terms
+}
+
+
+pub fn set_source_msg(source: &str) -> Vec<String> {
+    configuration_msg(vec!["setsource".to_string(), source.to_string()])
+}
+
+
+pub fn set_repo_msg(trunk: Option<&str>, include: &[String], exclude: &[String]) -> Vec<String> {
+    match trunk {
+        Some(t) => {
+            let repo = repo_terms(include, exclude);
+            let mut terms: Vec<String> = vec!["setrepotr".to_string(), t.to_string(), repo.len().to_string()];
+            terms.extend(repo);
+            configuration_msg(terms)
+        },
+        None => configuration_msg(counted("setrepo", repo_terms(include, exclude)))
+    }
+}
+
+
+/// Removals then additions, each as (include, exclude)
+pub fn modify_repo_msg(rems: (&[String], &[String]), adds: (&[String], &[String])) -> Vec<String> {
+    let mut terms: Vec<String> = vec![];
+    let rems = repo_terms(rems.0, rems.1);
+    if !rems.is_empty() {
+        terms.extend(counted("remove", rems));
+    }
+    let adds = repo_terms(adds.0, adds.1);
+    if !adds.is_empty() {
+        terms.extend(counted("add", adds));
+    }
+    configuration_msg(counted("modrepo", terms))
+}
+
+
+pub fn list_types_msg() -> Vec<String> {
+    configuration_msg(vec!["listtypes".to_string()])
+}
+
+
+/// Keyword is addtype, addsuffix or remsuffix
+pub fn type_suffixes_msg(keyword: &str, name: &str, suffixes: &[String]) -> Vec<String> {
+    let mut terms: Vec<String> = vec![keyword.to_string(), name.to_string()];
+    terms.extend(suffixes.iter().cloned());
+    configuration_msg(terms)
+}
+
+
+pub fn remove_type_msg(name: &str) -> Vec<String> {
+    configuration_msg(vec!["remtype".to_string(), name.to_string()])
+}
+
+
+pub fn rename_type_msg(old: &str, new: &str, rewrite: bool) -> Vec<String> {
+    configuration_msg(vec!["renametype".to_string(), old.to_string(), new.to_string(), flag(rewrite)])
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::parse::*;
+    use crate::manager::Manager;
+    use crate::configmodifier::ConfigModifier;
+    use memobook::filtercontainer::FilterContainer;
+    use memobook::modifiers::Modifier;
+    use memobook::queryer::Queryer;
+
+    fn strs(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn refs(msg: &[String]) -> Vec<&str> {
+        msg.iter().map(|s| s.as_str()).collect()
+    }
+
+    #[test]
+    fn test_search_msg() {
+        let filters = vec![
+            FilterSpec { field: "mark".to_string(), logic: "or".to_string(), terms: strs(&["rust", "dbus"]) },
+            FilterSpec { field: "type".to_string(), logic: "and".to_string(), terms: strs(&["Text"]) }
+        ];
+        let msg = search_msg(&filters, "file", true, false, true);
+        let query = parse_search_msg(refs(&msg)).unwrap();
+        assert_eq!((query.equality().as_str(), query.grep(), query.grepcase(), query.greplink()), ("file", true, false, true));
+        let parsed: Vec<(String, Vec<String>)> = query.iter_filters()
+            .map(|f| (f.filtertype().to_string(), f.iter().map(|t| t.to_string()).collect()))
+            .collect();
+        assert_eq!(parsed, vec![("mark".to_string(), strs(&["rust", "dbus"])), ("type".to_string(), strs(&["Text"]))]);
+        assert!(parse_search_msg(refs(&search_msg(&[], "mark", false, false, false))).is_ok());
+    }
+
+    #[test]
+    fn test_modify_msgs() {
+        let msg = add_record_msg(&strs(&["a.txt", "b.md"]), &strs(&["doom"]), &[]);
+        match parse_modification_msg(refs(&msg)).unwrap() {
+            Modifier::AddRecord(ar) => assert_eq!((ar.files.len(), ar.marks.len(), ar.ftypes.len()), (2, 1, 0)),
+            _ => panic!("wrong modification parsed")
+        }
+        let msg = mark_update_msg("a.txt", &strs(&["Text"]), &strs(&["old"]), &strs(&["new", "newer"]));
+        match parse_modification_msg(refs(&msg)).unwrap() {
+            Modifier::MarkUpdate(mu) => {
+                assert_eq!(mu.file, "a.txt");
+                assert_eq!((mu.ftypes, mu.rem, mu.add), (strs(&["Text"]), strs(&["old"]), strs(&["new", "newer"])));
+            },
+            _ => panic!("wrong modification parsed")
+        }
+        match parse_modification_msg(refs(&target_remove_msg("mark", "doom"))).unwrap() {
+            Modifier::TargetRemove(tr) => assert_eq!((tr.ttype.as_str(), tr.value.as_str()), ("mark", "doom")),
+            _ => panic!("wrong modification parsed")
+        }
+    }
+
+    #[test]
+    fn test_batch_msg() {
+        let msg = batch_msg(&[
+            add_record_msg(&strs(&["a.txt"]), &strs(&["doom"]), &[]),
+            mark_update_msg("a.txt", &strs(&["Text"]), &strs(&["doom"]), &strs(&["quake"])),
+            target_remove_msg("mark", "quake")
+        ]);
+        let mods = parse_batch_msg(refs(&msg)).unwrap();
+        assert_eq!(mods.len(), 3);
+        assert!(matches!(mods[1], Modifier::MarkUpdate(ref mu) if mu.add == strs(&["quake"])));
+        assert!(parse_batch_msg(vec![]).is_err());
+        assert!(parse_batch_msg(refs(&msg[..msg.len() - 1])).is_err());
+        let mut extra = msg.clone();
+        extra.push("addrecord".to_string());
+        assert!(parse_batch_msg(refs(&extra)).is_err());
+        let err = parse_batch_msg(vec!["targetremove", "2", "mark", "doom", "targetremove", "1", "mark"]).err().unwrap();
+        assert!(err.to_string().contains("batch item 2"));
+    }
+
+    #[test]
+    fn test_manage_msgs() {
+        match parse_manage_msg(refs(&import_msg("/old/export.json", "/tmp/import.log", true))).unwrap() {
+            Manager::Import(imp) => assert_eq!((imp.target.as_str(), imp.log.as_str(), imp.links), ("/old/export.json", "/tmp/import.log", true)),
+            _ => panic!("wrong manage request parsed")
+        }
+        match parse_manage_msg(refs(&export_msg("/tmp/export.json"))).unwrap() {
+            Manager::Export(exp) => assert_eq!(exp.log, "/tmp/export.json"),
+            _ => panic!("wrong manage request parsed")
+        }
+        let msg = backup_msg(&[("mult", "3".to_string()), ("force", "true".to_string())]);
+        match parse_manage_msg(refs(&msg)).unwrap() {
+            Manager::Backup(bup) => assert_eq!((bup.mult, bup.force, bup.remove), (Some(3), true, false)),
+            _ => panic!("wrong manage request parsed")
+        }
+    }
+
+    #[test]
+    fn test_configuration_msgs() {
+        assert!(matches!(parse_manage_msg(refs(&set_source_msg("/srv/archive.db"))).unwrap(),
+            Manager::Configure(ConfigModifier::SetSource(s)) if s == "/srv/archive.db"));
+        assert!(matches!(parse_manage_msg(refs(&list_types_msg())).unwrap(), Manager::Configure(ConfigModifier::ListTypes)));
+        assert!(matches!(parse_manage_msg(refs(&type_suffixes_msg("addtype", "Image", &strs(&["png", "jpg"])))).unwrap(),
+            Manager::Configure(ConfigModifier::AddType(n, s)) if n == "Image" && s.len() == 2));
+        assert!(matches!(parse_manage_msg(refs(&remove_type_msg("Image"))).unwrap(),
+            Manager::Configure(ConfigModifier::RemoveType(n)) if n == "Image"));
+        assert!(matches!(parse_manage_msg(refs(&rename_type_msg("Image", "Picture", true))).unwrap(),
+            Manager::Configure(ConfigModifier::RenameType(_, _, true))));
+        match parse_manage_msg(refs(&set_repo_msg(None, &strs(&["/home/me/notes"]), &strs(&["/home/me/notes/old"])))).unwrap() {
+            Manager::Configure(ConfigModifier::SetRepo(repo)) => {
+                assert_eq!((repo.get_include().len(), repo.get_exclude().len()), (1, 1));
+            },
+            _ => panic!("wrong configuration request parsed")
+        }
+        match parse_manage_msg(refs(&set_repo_msg(Some("/home/me"), &strs(&["notes", "code"]), &[]))).unwrap() {
+            Manager::Configure(ConfigModifier::SetRepo(repo)) => {
+                assert_eq!((repo.get_trunk(), repo.get_include().len()), ("/home/me", 2));
+            },
+            _ => panic!("wrong configuration request parsed")
+        }
+        let msg = modify_repo_msg((&strs(&["/a"]), &[]), (&strs(&["/b", "/c"]), &strs(&["/d"])));
+        match parse_manage_msg(refs(&msg)).unwrap() {
+            Manager::Configure(ConfigModifier::ModifyRepo((rems, adds))) => {
+                assert_eq!((rems.get_include().len(), adds.get_include().len(), adds.get_exclude().len()), (1, 2, 1));
+            },
+            _ => panic!("wrong configuration request parsed")
+        }
+    }
+
+}
@@ -0,0 +1,993 @@
+//  ops.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::Ordering;
+use memobook::backer::{Backer, TransBackStruct};
+use memobook::backerparserjson::BackerParserJSON;
+use memobook::{MemoBook, Queryable};
+use memobook::mberror::MBError;
+use memobook::queryer::Queryer;
+use memobook::logic::Logic;
+use memobook::mbfilter::MBFilter;
+use memobook::mbstats::MBStats;
+use memobook::query::Query;
+use memobook::modifiers::{Modifier, ModifyFieldReplace};
+use memobook::suggester::Suggestion;
+use crate::MemoBookServer;
+use crate::prepare::*;
+use crate::parse::build_filter;
+use crate::manager::Manager;
+use crate::configmodifier::ConfigModifier;
+use crate::searchsaver::SearchSaver;
+use crate::serverv2::MemoBookServer2;
+use crate::jobs::{JobFailure, JobStatus, JobWork};
+use memobook::crawler::CrawlOption;
+use memobook::jobprogress::JobProgress;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+
+// Operations shared by the string interface (memoserv1) and the typed one (memoserv2).
+// Each interface only translates its arguments in and its replies out.
+
+/// How often a running job reports its progress
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a shutdown waits for cancelled jobs to finish
+const JOB_GRACE: Duration = Duration::from_secs(5);
+
+/// Opens files for the desktop search providers
+pub const OPENER: &str = "xdg-open";
+
+
+/// An operation failure: what was being done, and what went wrong
+#[derive(Debug)]
+pub struct OpError {
+    pub context: &'static str,
+    pub error: String
+}
+
+
+impl OpError {
+
+    pub fn new(context: &'static str, error: impl fmt::Display) -> OpError {
+        OpError { context, error: error.to_string() }
+    }
+
+}
+
+
+impl fmt::Display for OpError {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.context.is_empty() {
+            write!(f, "{}", self.error)
+        } else {
+            write!(f, "{}: {}", self.context, self.error)
+        }
+    }
+
+}
+
+
+/// Maps a job's error to a failure in the given context, or to its cancellation
+fn job_failure(context: &'static str) -> impl Fn(MBError) -> JobFailure {
+    move |e| match e {
+        MBError::Cancelled(_) => JobFailure::Cancelled,
+        e => JobFailure::Failed(OpError::new(context, e).to_string())
+    }
+}
+
+
+/// A change clients should hear about, queued by an operation and emitted as signals
+/// once the method is done
+#[derive(Clone, PartialEq, Debug)]
+pub enum Change {
+    Records(Vec<String>),        // affected files, empty when not known
+    Configuration(&'static str), // section: source, repositories, types, backup, saved
+    Backup(&'static str),        // created, loaded
+    Exiting
+}
+
+
+/// What clients are told: a change, or the progress or end of a job. D-Bus clients get these
+/// as signals, socket clients as notifications.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Notice {
+    Change(Change),
+    JobProgress(u32, u64, u64, u64), // id, scanned, hashed, matched
+    JobFinished(u32, String, String) // id, state, report or error
+}
+
+
+pub enum SavedReply {
+    Names(Vec<String>),
+    Query(Query<MBFilter>),
+    Results(Vec<String>),
+    Done
+}
+
+
+/// How a batch of modifications went: whether it was kept, and a message for each
+/// modification, empty for those that succeeded
+pub struct BatchReport {
+    pub committed: bool,
+    pub results: Vec<String>
+}
+
+
+impl BatchReport {
+
+    pub fn to_json(&self) -> json::JsonValue {
+        json::object!{ committed: self.committed, results: self.results.clone() }
+    }
+
+}
+
+
+impl<B> Clone for MemoBookServer<B>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    fn clone(&self) -> Self {
+        MemoBookServer {
+            name: self.name.clone(),
+            stop: self.stop.clone(),
+            last_request: self.last_request.clone(),
+            exitflag: self.exitflag.clone(),
+            cfg: self.cfg.clone(),
+            mb: self.mb.clone(),
+            cache: self.cache.clone(),
+            changes: self.changes.clone(),
+            jobs: self.jobs.clone(),
+            runtime: self.runtime.clone(),
+            access: self.access.clone(),
+            signals: self.signals.clone(),
+            notices: self.notices.clone()
+        }
+    }
+}
+
+
+impl<B> MemoBookServer<B>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+
+    pub fn announce(&self, change: Change) {
+        let mut changes = self.changes.lock().unwrap();
+        if !changes.contains(&change) {
+            changes.push(change);
+        }
+    }
+
+
+    /// Emit every change queued so far
+    pub async fn emit_changes(&self) {
+        let changes: Vec<Change> = std::mem::take(&mut *self.changes.lock().unwrap());
+        for change in changes {
+            self.emit(Notice::Change(change)).await;
+        }
+    }
+
+
+    /// Signal a notice on both D-Bus interfaces, once the connection is up, and pass it to
+    /// socket clients
+    pub async fn emit(&self, notice: Notice) {
+        if let Some(emitter) = self.signals.get() {
+            let _ = match &notice {
+                Notice::Change(Change::Records(files)) => {
+                    let _ = MemoBookServer::<B>::records_changed(emitter, files).await;
+                    MemoBookServer2::<B>::records_changed(emitter, files).await
+                },
+                Notice::Change(Change::Configuration(section)) => {
+                    let _ = MemoBookServer::<B>::configuration_changed(emitter, section).await;
+                    MemoBookServer2::<B>::configuration_changed(emitter, section).await
+                },
+                Notice::Change(Change::Backup(action)) => {
+                    let _ = MemoBookServer::<B>::backup_changed(emitter, action).await;
+                    MemoBookServer2::<B>::backup_changed(emitter, action).await
+                },
+                Notice::Change(Change::Exiting) => {
+                    let _ = MemoBookServer::<B>::exited(emitter).await;
+                    MemoBookServer2::<B>::exited(emitter).await
+                },
+                Notice::JobProgress(id, scanned, hashed, matched) => {
+                    let _ = MemoBookServer::<B>::job_progress(emitter, *id, *scanned, *hashed, *matched).await;
+                    MemoBookServer2::<B>::job_progress(emitter, *id, *scanned, *hashed, *matched).await
+                },
+                Notice::JobFinished(id, state, message) => {
+                    let _ = MemoBookServer::<B>::job_finished(emitter, *id, state, message).await;
+                    MemoBookServer2::<B>::job_finished(emitter, *id, state, message).await
+                }
+            };
+        }
+        // no receivers just means no socket clients
+        let _ = self.notices.send(notice);
+    }
+
+
+    fn backup_check(&self, memocfg: &mut std::sync::MutexGuard<'_, memobook::configuration::Configuration<B>>) {
+        if memocfg.check_backup(true) {
+            self.announce(Change::Backup("created"));
+        }
+    }
+
+
+    fn lookup_files(memobk: &MemoBook, field: &str, values: Vec<String>) -> Vec<String> {
+        let query = Query::new(vec![MBFilter::new(field.to_string(), Logic::OR, values)], "file", false, false, false);
+        memobk.search(query).unwrap_or_default()
+    }
+
+
+    /// Files a modification will touch, found before it is applied; empty if it cannot be told
+    fn affected_files(memobk: &MemoBook, cmd: &Modifier) -> Vec<String> {
+        let lookup = |field: &str, values: Vec<String>| Self::lookup_files(memobk, field, values);
+        let mut files: Vec<String> = match cmd {
+            Modifier::AddRecord(ar) => ar.files.to_vec(),
+            Modifier::MarkUpdate(mu) => vec![mu.file.to_string()],
+            Modifier::TypeUpdate(tu) => vec![tu.file.to_string()],
+            Modifier::TargetRemove(tr) if tr.ttype == "file" => vec![tr.value.to_string()],
+            Modifier::TargetRemove(tr) => lookup(&tr.ttype, vec![tr.value.to_string()]),
+            Modifier::FieldReplace(fr) if fr.field == "file" => fr.repl.iter()
+                .flat_map(|(a, b)| [a.to_string(), b.to_string()])
+                .collect(),
+            Modifier::FieldReplace(fr) => lookup(&fr.field, fr.repl.iter().map(|(a, _)| a.to_string()).collect())
+        };
+        files.sort();
+        files.dedup();
+        files
+    }
+
+
+    /// The lookup that finds, once a modification is applied, the files it touched: those now
+    /// holding a replacement. Files a batch's earlier items gave the old value are found so.
+    fn affected_after(cmd: &Modifier) -> Option<(String, Vec<String>)> {
+        match cmd {
+            Modifier::FieldReplace(fr) if fr.field != "file" => Some((fr.field.to_string(), fr.repl.iter().map(|(_, b)| b.to_string()).collect())),
+            _ => None
+        }
+    }
+
+
+    /// Run work on a blocking thread, off the D-Bus executor, so that a long request holds up
+    /// only itself. The book is read-locked by searches and write-locked by changes, so
+    /// searches run side by side while a change has it to itself.
+    async fn offload<T: Send + 'static>(&self, work: impl FnOnce(Self) -> Result<T, OpError> + Send + 'static) -> Result<T, OpError> {
+        let core = self.clone();
+        match self.runtime.spawn_blocking(move || work(core)).await {
+            Ok(r) => r,
+            Err(e) => Err(OpError::new("Worker error", e))
+        }
+    }
+
+
+    /// A search whose grep crawl, if any, runs without holding the book
+    fn search_blocking(&self, query: Query<MBFilter>, progress: Option<&Arc<JobProgress>>) -> Result<Vec<String>, MBError> {
+        let mut hits: Vec<String> = vec![];
+        if query.grep() {
+            let mut crawler = self.mb.blocking_read().grep_for(&query)?;
+            if let Some(p) = progress {
+                crawler.set_options(CrawlOption::Progress(p.clone()));
+            }
+            hits = crawler.crawl()?.retrieve().unwrap_or_default();
+        }
+        self.mb.blocking_read().search_merged(query, hits)
+    }
+
+
+    pub async fn op_search(&self, query: Query<MBFilter>, context: &'static str) -> Result<Vec<String>, OpError> {
+        self.offload(move |core| core.search_blocking(query, None).map_err(|e| OpError::new(context, e))).await
+    }
+
+
+    /// Files carrying a mark for every term, a term standing for the marks that start with it,
+    /// in any case; for the desktop search providers. One search per term, since a mark filter
+    /// matches a single mark of a file.
+    pub async fn op_mark_prefix_search(&self, terms: &[String]) -> Result<Vec<String>, OpError> {
+        let marks = self.op_search(Query::new(vec![], "mark", false, false, false), "Error in table of contents").await?;
+        let mut found: Option<Vec<String>> = None;
+        for term in terms {
+            let term = term.to_lowercase();
+            let matched: Vec<String> = marks.iter().filter(|m| m.to_lowercase().starts_with(&term)).cloned().collect();
+            if matched.is_empty() {
+                return Ok(vec![]);
+            }
+            let filter = build_filter("mark", "or", matched).map_err(|e| OpError::new("Error in mark search", e))?;
+            let files = self.op_search(Query::new(vec![filter], "file", false, false, false), "Error in mark search").await?;
+            found = Some(match found {
+                Some(f) => f.into_iter().filter(|x| files.contains(x)).collect(),
+                None => files
+            });
+        }
+        Ok(found.unwrap_or_default())
+    }
+
+
+    /// The marks of a file, empty when it is not in the archive
+    pub async fn op_marks_of(&self, file: &str) -> Result<Vec<String>, OpError> {
+        let filter = build_filter("file", "or", vec![file.to_string()]).map_err(|e| OpError::new("Error in mark search", e))?;
+        self.op_search(Query::new(vec![filter], "mark", false, false, false), "Error in mark search").await
+    }
+
+
+    /// Open a file of the archive, or its folder, with the desktop's opener
+    pub async fn op_open(&self, file: String, folder: bool) -> Result<(), OpError> {
+        if self.op_marks_of(&file).await?.is_empty() {
+            return Err(OpError::new("Open request refused", format!("{file} is not in the archive")));
+        }
+        let target = match folder {
+            true => std::path::Path::new(&file).parent().map(|p| p.to_string_lossy().to_string()).unwrap_or(file),
+            false => file
+        };
+        self.runtime.spawn(async move {
+            if let Err(e) = tokio::process::Command::new(OPENER).arg(&target).status().await {
+                println!("Could not open {target}: {e}");
+            }
+        });
+        Ok(())
+    }
+
+
+    pub async fn op_search_scored(&self, query: Query<MBFilter>) -> Result<Vec<(String, usize)>, OpError> {
+        self.offload(move |core| {
+            core.mb.blocking_read().search_scored(query).map_err(|e| OpError::new("Error in search", e))
+        }).await
+    }
+
+
+    pub async fn op_related(&self, marks: Vec<String>, limit: usize) -> Result<Vec<(String, usize)>, OpError> {
+        self.offload(move |core| {
+            core.mb.blocking_read().related(&marks, limit).map_err(|e| OpError::new("Error in related marks", e))
+        }).await
+    }
+
+
+    pub async fn op_stats(&self, topn: usize) -> Result<MBStats, OpError> {
+        self.offload(move |core| {
+            core.mb.blocking_read().stats(topn).map_err(|e| OpError::new("Error in stats", e))
+        }).await
+    }
+
+
+    /// Suggestions read the files without holding the book, as grep searches do
+    pub async fn op_suggest(&self, file: String, limit: usize) -> Result<Vec<Suggestion>, OpError> {
+        self.offload(move |core| {
+            let work = core.mb.blocking_read().suggest_for(&file).map_err(|e| OpError::new("Error in suggestion", e))?;
+            work.run(limit).map_err(|e| OpError::new("Error in suggestion", e))
+        }).await
+    }
+
+
+    pub async fn op_modify(&self, clientcmd: Modifier) -> Result<(), OpError> {
+        self.offload(move |core| core.modify_helper(clientcmd)).await
+    }
+
+
+    fn modify_helper(&self, mut clientcmd: Modifier) -> Result<(), OpError> {
+        // lock memobook
+        let mut memobk = self.mb.blocking_write();
+        prepare_modification(&memobk, &mut clientcmd)
+            .map_err(|e| OpError::new("Error in modification auxiliary search", e))?;
+        let affected: Vec<String> = Self::affected_files(&memobk, &clientcmd);
+        // lock config
+        let mut memocfg = self.cfg.lock().unwrap();
+        self.backup_check(&mut memocfg);
+        memobk.modify(&clientcmd).map_err(|e| OpError::new("Modify request returned error", e))?;
+        memocfg.mb_alt(true);
+        self.cache.lock().unwrap().clear();
+        self.announce(Change::Records(affected));
+        Ok(())
+    }
+
+
+    /// Modifications applied in one transaction: all of them are kept, or none
+    pub async fn op_modify_batch(&self, clientcmds: Vec<Modifier>) -> Result<BatchReport, OpError> {
+        self.offload(move |core| {
+            // lock memobook
+            let mut memobk = core.mb.blocking_write();
+            // lookups before the batch miss what its own items change, so they are made after it too
+            let mut affected: Vec<String> = clientcmds.iter().flat_map(|cmd| Self::affected_files(&memobk, cmd)).collect();
+            let after: Vec<(String, Vec<String>)> = clientcmds.iter().filter_map(Self::affected_after).collect();
+            // lock config
+            let mut memocfg = core.cfg.lock().unwrap();
+            core.backup_check(&mut memocfg);
+            let results = memobk.modify_batch(clientcmds).map_err(|e| OpError::new("Batch request returned error", e))?;
+            let committed = results.iter().all(|r| r.is_ok());
+            if committed {
+                affected.extend(after.into_iter().flat_map(|(field, values)| Self::lookup_files(&memobk, &field, values)));
+                affected.sort();
+                affected.dedup();
+                memocfg.mb_alt(true);
+                core.cache.lock().unwrap().clear();
+                core.announce(Change::Records(affected));
+            }
+            let results = results.into_iter().map(|r| r.err().map(|e| e.to_string()).unwrap_or_default()).collect();
+            Ok(BatchReport { committed, results })
+        }).await
+    }
+
+
+    pub fn op_types(&self) -> HashMap<String, Vec<String>> {
+        let memocfg = self.cfg.lock().unwrap();
+        memocfg.mime().iter()
+            .map(|(t, m)| (t.to_string(), m.iter().map(|s| s.to_string()).collect()))
+            .collect()
+    }
+
+
+    /// Types of a file by its suffix in the mime table
+    pub fn op_types_of(&self, file: &str) -> Vec<String> {
+        let suffix = std::path::Path::new(file).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        let memocfg = self.cfg.lock().unwrap();
+        let mut types: Vec<String> = memocfg.mime().iter()
+            .filter(|(_, m)| m.contains(&suffix))
+            .map(|(t, _)| t.to_string())
+            .collect();
+        types.sort();
+        types
+    }
+
+
+    /// Configuration, import, export and backup management. The reply is the text the
+    /// operation reports, empty for most.
+    pub async fn op_manage(&self, clientcmd: Manager) -> Result<String, OpError> {
+        self.offload(move |core| {
+            let reply = core.manage_helper(clientcmd);
+            core.cache.lock().unwrap().clear();
+            reply
+        }).await
+    }
+
+
+    fn manage_helper(&self, clientcmd: Manager) -> Result<String, OpError> {
+        match clientcmd {
+            Manager::Configure(cfg) => {
+                match cfg {
+                    ConfigModifier::SetSource(ss) => {
+                        let mut memobk = self.mb.blocking_write();
+                        {
+                            let mut memocfg = self.cfg.lock().unwrap();
+                            self.backup_check(&mut memocfg);
+                            memocfg.set_source(&ss);
+                        }
+                        self.announce(Change::Configuration("source"));
+                        match memobk.connect(Some(ss)) {
+                            Ok(()) => {
+                                self.announce(Change::Records(vec![]));
+                                Ok("".to_string())
+                            },
+                            Err(e) => Err(OpError::new("Error managing source", e))
+                        }
+                    },
+                    ConfigModifier::SetRepo(sr) => {
+                        let mut memobk = self.mb.blocking_write();
+                        let mut memocfg = self.cfg.lock().unwrap();
+                        self.backup_check(&mut memocfg);
+                        memocfg.set_repo_by_repo(sr);   
+                        self.announce(Change::Configuration("repositories"));
+                        match memobk.target(&memocfg.mb().scan, memocfg.mime()) {
+                            Ok(()) => Ok("".to_string()),
+                            Err(e) => Err(OpError::new("Error managing repo", e))
+                        }
+                    },
+                    ConfigModifier::ModifyRepo(mrtuple) => { 
+                        let mut memobk = self.mb.blocking_write();
+                        let mut memocfg = self.cfg.lock().unwrap();
+                        self.backup_check(&mut memocfg);
+                        memocfg.modify_repo_by_repo(mrtuple);
+                        self.announce(Change::Configuration("repositories"));
+                        match memobk.target(&memocfg.mb().scan, memocfg.mime()) {
+                            Ok(()) => Ok("".to_string()),
+                            Err(e) => Err(OpError::new("Error managing repo", e))
+                        }
+                    },
+                    ConfigModifier::ListTypes => {
+                        let memocfg = self.cfg.lock().unwrap();
+                        Ok(memocfg.assemble_mime_info())
+                    },
+                    mimemod => {
+                        let mut memobk = self.mb.blocking_write();
+                        let mut memocfg = self.cfg.lock().unwrap();
+                        if let ConfigModifier::RenameType(_, _, true) = &mimemod {
+                            self.backup_check(&mut memocfg);
+                        }
+                        let changed = match &mimemod {
+                            ConfigModifier::AddType(name, suffixes) => memocfg.add_type(name, suffixes),
+                            ConfigModifier::RemoveType(name) => memocfg.remove_type(name),
+                            ConfigModifier::AddSuffixes(name, suffixes) => memocfg.add_suffixes(name, suffixes),
+                            ConfigModifier::RemoveSuffixes(name, suffixes) => memocfg.remove_suffixes(name, suffixes),
+                            ConfigModifier::RenameType(old, new, _) => memocfg.rename_type(old, new),
+                            _ => Ok(())
+                        };
+                        changed.map_err(|e| OpError::new("Error managing types", e))?;
+                        if let ConfigModifier::RenameType(old, new, true) = &mimemod {
+                            let rewrite = Modifier::FieldReplace(ModifyFieldReplace::new("type", (old, new)));
+                            let affected: Vec<String> = Self::affected_files(&memobk, &rewrite);
+                            if let Err(e) = memobk.modify(&rewrite) {
+                                // keep the table in step with the records
+                                let _ = memocfg.rename_type(new, old);
+                                return Err(OpError::new("Error renaming type in database", e));
+                            }
+                            memocfg.mb_alt(true);
+                            self.announce(Change::Records(affected));
+                        }
+                        self.announce(Change::Configuration("types"));
+                        memobk.target(&memocfg.mb().scan, memocfg.mime())
+                            .map_err(|e| OpError::new("Error managing types", e))?;
+                        Ok("".to_string())
+                    }
+                }
+            },
+            // IMPORT WILL ALMOST CERTAINLY ALTER THE DB, SO DO A BACKUP
+            Manager::Import(imp) => {
+                let mut memobk = self.mb.blocking_write();
+                {
+                    let mut memocfg = self.cfg.lock().unwrap();
+                    self.backup_check(&mut memocfg);
+                }
+                let report = memobk.import(imp).map_err(|e| OpError::new("Error importing files", e))?;
+                self.announce(Change::Records(vec![]));
+                Ok(report)
+            },
+            Manager::Export(exp) => {
+                let memobk = self.mb.blocking_read();
+                memobk.export(exp).map_err(|e| OpError::new("Error exporting", e))
+            },
+            Manager::Backup(bup) => {
+                let mut memobk = self.mb.blocking_write();
+                memobk.disconnect();
+                let connectable: Option<String>;
+                {
+                    let mut memocfg = self.cfg.lock().unwrap();
+                    connectable = match memocfg.process_modify_backup(&bup) {
+                        Ok(Some(s)) => Some(s),
+                        Ok(None) => None,
+                        Err(e) => return Err(OpError::new("Error in backup modification call", e))
+                    };
+                }
+                match memobk.connect(connectable) {
+                    Ok(_) => {
+                        self.announce_backup(&bup);
+                        Ok("".to_string())
+                    },
+                    Err(x) => Err(OpError::new("Backup data could not be loaded", format!("{:?}", x)))
+                }
+            }
+        }
+    }
+
+
+    fn announce_backup(&self, bup: &TransBackStruct) {
+        self.announce(Change::Configuration("backup"));
+        if bup.force {
+            self.announce(Change::Backup("created"));
+        }
+        if bup.load.as_ref().is_some_and(|l| !l.is_empty()) {
+            self.announce(Change::Backup("loaded"));
+            self.announce(Change::Records(vec![]));
+        }
+    }
+
+
+    pub async fn op_saved(&self, clientcmd: SearchSaver) -> Result<SavedReply, OpError> {
+        match clientcmd {
+            SearchSaver::List => {
+                let memocfg = self.cfg.lock().unwrap();
+                Ok(SavedReply::Names(memocfg.saved().names()))
+            },
+            SearchSaver::Show(name) => {
+                let memocfg = self.cfg.lock().unwrap();
+                match memocfg.saved().get(&name) {
+                    Some(q) => Ok(SavedReply::Query(q.clone())),
+                    None => Err(OpError::new("Saved search error", format!("no saved search named [{name}]")))
+                }
+            },
+            SearchSaver::Create(name, query) => {
+                let mut memocfg = self.cfg.lock().unwrap();
+                memocfg.save_search(&name, query, false).map_err(|e| OpError::new("Saved search error", e))?;
+                self.announce(Change::Configuration("saved"));
+                Ok(SavedReply::Done)
+            },
+            SearchSaver::Update(name, query) => {
+                let mut memocfg = self.cfg.lock().unwrap();
+                memocfg.save_search(&name, query, true).map_err(|e| OpError::new("Saved search error", e))?;
+                self.cache.lock().unwrap().remove(&name);
+                self.announce(Change::Configuration("saved"));
+                Ok(SavedReply::Done)
+            },
+            SearchSaver::Delete(name) => {
+                let mut memocfg = self.cfg.lock().unwrap();
+                memocfg.remove_search(&name).map_err(|e| OpError::new("Saved search error", e))?;
+                self.cache.lock().unwrap().remove(&name);
+                self.announce(Change::Configuration("saved"));
+                Ok(SavedReply::Done)
+            },
+            SearchSaver::Run(name) => {
+                if let Some(hit) = self.cache.lock().unwrap().get(&name) {
+                    return Ok(SavedReply::Results(hit.to_vec()));
+                }
+                let query = {
+                    let memocfg = self.cfg.lock().unwrap();
+                    match memocfg.saved().get(&name) {
+                        Some(q) => q.clone(),
+                        None => return Err(OpError::new("Saved search error", format!("no saved search named [{name}]")))
+                    }
+                };
+                // grep results follow the files on disk, which can change under the cache
+                let grep = query.grep();
+                let results = self.op_search(query, "Error in saved search").await?;
+                if !grep {
+                    self.cache.lock().unwrap().insert(name, results.to_vec());
+                }
+                Ok(SavedReply::Results(results))
+            }
+        }
+    }
+
+
+    /// Start a background job and return its id. The work runs on a blocking thread while
+    /// progress is signalled every PROGRESS_INTERVAL; its end is signalled with the job's state.
+    pub fn op_start_job(&self, work: JobWork) -> u32 {
+        let (id, progress) = self.jobs.lock().unwrap().start(work.kind());
+        let core = self.clone();
+        self.runtime.spawn(async move {
+            let mut worker = {
+                let core = core.clone();
+                let progress = progress.clone();
+                tokio::task::spawn_blocking(move || core.run_job(work, &progress))
+            };
+            let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+            let outcome = loop {
+                tokio::select! {
+                    done = &mut worker => break done,
+                    _ = ticker.tick() => {
+                        let (scanned, hashed, matched) = progress.snapshot();
+                        core.emit(Notice::JobProgress(id, scanned as u64, hashed as u64, matched as u64)).await;
+                    }
+                }
+            };
+            let outcome = match outcome {
+                Ok(r) => r,
+                Err(e) => Err(JobFailure::Failed(format!("Job error: {e}")))
+            };
+            let message: String = outcome.as_ref().map_or_else(|e| e.to_string(), |(r, _)| r.to_string());
+            let state = core.jobs.lock().unwrap().finish(id, outcome).to_string();
+            core.emit(Notice::JobFinished(id, state, message)).await;
+            core.emit_changes().await;
+        });
+        id
+    }
+
+
+    /// The work of a job. The book is locked only to read from or write to the database, not
+    /// while files are crawled and hashed, so other requests go on meanwhile.
+    fn run_job(&self, work: JobWork, progress: &Arc<JobProgress>) -> Result<(String, Vec<String>), JobFailure> {
+        match work {
+            JobWork::Import(imp) => {
+                let (scan, archives) = {
+                    let memocfg = self.cfg.lock().unwrap();
+                    (memocfg.mb().scan.clone(), memocfg.mb().archives)
+                };
+                let found = self.runtime.block_on(MemoBook::gather_import(scan, archives, imp, Some(progress.clone())))
+                    .map_err(job_failure("Error importing files"))?;
+                progress.check().map_err(job_failure(""))?;
+                let mut memobk = self.mb.blocking_write();
+                {
+                    let mut memocfg = self.cfg.lock().unwrap();
+                    self.backup_check(&mut memocfg);
+                }
+                let report = memobk.insert_import(found).map_err(job_failure("Error importing files"))?;
+                self.cache.lock().unwrap().clear();
+                self.announce(Change::Records(vec![]));
+                Ok((report, vec![]))
+            },
+            JobWork::Export(exp) => {
+                let logger = self.mb.blocking_read().prepare_export(&exp)
+                    .map_err(job_failure("Error exporting"))?;
+                logger.write(Some(progress)).map_err(job_failure("Error exporting"))?;
+                Ok((format!("export to {} complete", &exp.log), vec![]))
+            },
+            JobWork::Grep(query) => {
+                let results = self.search_blocking(query, Some(progress))
+                    .map_err(job_failure("Error in search"))?;
+                Ok((format!("{} found", results.len()), results))
+            }
+        }
+    }
+
+
+    pub fn op_cancel_job(&self, id: u32) -> Result<(), OpError> {
+        if self.jobs.lock().unwrap().cancel(id) {
+            Ok(())
+        } else {
+            Err(OpError::new("Job error", format!("no running job {id}")))
+        }
+    }
+
+
+    pub fn op_job_status(&self, id: u32) -> Result<JobStatus, OpError> {
+        self.jobs.lock().unwrap().status(id)
+            .ok_or_else(|| OpError::new("Job error", format!("no job {id}")))
+    }
+
+
+    /// Notes a request, putting off the idle timeout
+    pub fn touch(&self) {
+        *self.last_request.lock().unwrap() = Instant::now();
+    }
+
+
+    /// How long since the last request, or zero while a job is still running
+    pub fn idle_for(&self) -> Duration {
+        if self.jobs.lock().unwrap().running() > 0 {
+            self.touch();
+        }
+        self.last_request.lock().unwrap().elapsed()
+    }
+
+
+    /// Asks main to shut down; callers reply to their client first
+    pub fn request_exit(&self) {
+        self.stop.notify_one();
+    }
+
+
+    /// Requests the exit once the reply to the exit method has been sent
+    pub fn exit_after(&self, sent: event_listener::EventListener) {
+        let core = self.clone();
+        self.runtime.spawn(async move {
+            sent.await;
+            core.request_exit();
+        });
+    }
+
+
+    /// The one way out, whether asked by a client or by a signal: tell everyone, then close up
+    pub async fn shutdown(&self) {
+        if self.exitflag.load(Ordering::SeqCst) {
+            return;
+        }
+        // running jobs are stopped first, so that none still writes once the book is closed
+        if self.jobs.lock().unwrap().cancel_all() > 0 {
+            let started = Instant::now();
+            while self.jobs.lock().unwrap().running() > 0 && started.elapsed() < JOB_GRACE {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        }
+        self.announce(Change::Exiting);
+        self.emit_changes().await;
+        self.op_exit().await;
+    }
+
+
+    pub async fn op_exit(&self) {
+        // lock memobook
+        let mut memobk = self.mb.write().await;
+        memobk.disconnect();
+        { // lock config
+            let memocfg = self.cfg.lock().unwrap();
+            memocfg.finish();
+        } // release config
+        self.exitflag.store(true, Ordering::SeqCst);
+    }
+
+}
+
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+
+    use super::*;
+    use crate::access::AccessPolicy;
+    use memobook::configuration::{Configuration, MBInfo};
+    use memobook::magic::Sniffer;
+    use memobook::modifiers::{ModifyAddRecord, ModifyMarkUpdate, ModifyTargetRemove};
+    use memobook::repository::Repository;
+    use memobook::utckeeper::UtcKeeper;
+
+    #[test]
+    fn test_affected_files() {
+        let dbpath = std::env::temp_dir().join(format!("memoserv_test_affected_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&dbpath);
+        let info = MBInfo {
+            src: dbpath.to_str().unwrap().to_string(),
+            table: "bookmarks".to_string(),
+            scan: Repository::new(),
+            alt: false,
+            deftype: "".to_string(),
+            sniff: Sniffer::default(),
+            archives: false
+        };
+        let mut book = MemoBook::new(&info, &HashMap::new());
+        book.connect(None).unwrap();
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        for (file, marks) in [("a.txt", vec!["rust"]), ("b.txt", vec!["rust", "dbus"]), ("c.txt", vec!["sqlite"])] {
+            book.modify(&Modifier::AddRecord(ModifyAddRecord::new(&strings(&[file]), &strings(&marks), &strings(&["Text"])))).unwrap();
+        }
+        let affected = |cmd: Modifier| MemoBookServer::<UtcKeeper>::affected_files(&book, &cmd);
+        assert_eq!(affected(Modifier::AddRecord(ModifyAddRecord::new(&strings(&["d.txt", "d.txt"]), &[], &[]))), strings(&["d.txt"]));
+        assert_eq!(affected(Modifier::MarkUpdate(ModifyMarkUpdate::new("c.txt", &[], &[], &strings(&["dbus"])))), strings(&["c.txt"]));
+        assert_eq!(affected(Modifier::TargetRemove(ModifyTargetRemove::new("mark", "rust"))), strings(&["a.txt", "b.txt"]));
+        assert_eq!(affected(Modifier::FieldReplace(ModifyFieldReplace::new("file", ("c.txt", "e.txt")))), strings(&["c.txt", "e.txt"]));
+        assert_eq!(affected(Modifier::FieldReplace(ModifyFieldReplace::new("mark", ("dbus", "zbus")))), strings(&["b.txt"]));
+        book.disconnect();
+        let _ = std::fs::remove_file(&dbpath);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_modify_batch() {
+        let (core, dir) = test_server("batch", AccessPolicy::default());
+        let a = dir.join("a.txt").to_str().unwrap().to_string();
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        let mut notices = core.notices.subscribe();
+        let report = core.op_modify_batch(vec![
+            Modifier::AddRecord(ModifyAddRecord::new(std::slice::from_ref(&a), &strings(&["rust"]), &[])),
+            Modifier::MarkUpdate(ModifyMarkUpdate::new(&a, &[], &[], &strings(&["dbus"])))
+        ]).await.unwrap();
+        assert_eq!((report.committed, report.results), (true, strings(&["", ""])));
+        assert_eq!(core.op_marks_of(&a).await.unwrap(), strings(&["dbus", "rust"]));
+        core.emit_changes().await;
+        assert!(matches!(notices.try_recv(), Ok(Notice::Change(Change::Records(files))) if files == vec![a.clone()]));
+
+        let report = core.op_modify_batch(vec![
+            Modifier::TargetRemove(ModifyTargetRemove::new("mark", "rust")),
+            Modifier::AddRecord(ModifyAddRecord::new(&strings(&["/nowhere/c.pdf"]), &strings(&["rust"]), &[]))
+        ]).await.unwrap();
+        assert!(!report.committed);
+        assert_eq!(report.results[0], "");
+        assert!(report.results[1].contains("no type found"));
+        assert_eq!(core.op_marks_of(&a).await.unwrap(), strings(&["dbus", "rust"]));
+        core.emit_changes().await;
+        assert!(notices.try_recv().is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_modify_batch_affected() {
+        let (core, dir) = test_server("batch_affected", AccessPolicy::default());
+        let a = dir.join("a.txt").to_str().unwrap().to_string();
+        let b = dir.join("b.md").to_str().unwrap().to_string();
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        core.op_modify(Modifier::AddRecord(ModifyAddRecord::new(std::slice::from_ref(&b), &strings(&["keep"]), &[]))).await.unwrap();
+        core.emit_changes().await;
+        let mut notices = core.notices.subscribe();
+        // a is added and then removed by mark within the batch
+        let report = core.op_modify_batch(vec![
+            Modifier::AddRecord(ModifyAddRecord::new(std::slice::from_ref(&a), &strings(&["scratch"]), &[])),
+            Modifier::TargetRemove(ModifyTargetRemove::new("mark", "scratch"))
+        ]).await.unwrap();
+        assert!(report.committed);
+        assert!(core.op_marks_of(&a).await.unwrap().is_empty());
+        core.emit_changes().await;
+        assert!(matches!(notices.try_recv(), Ok(Notice::Change(Change::Records(files))) if files == vec![a.clone()]));
+        // b gains a mark in the batch, which a later item then replaces
+        let report = core.op_modify_batch(vec![
+            Modifier::MarkUpdate(ModifyMarkUpdate::new(&b, &[], &[], &strings(&["draft"]))),
+            Modifier::FieldReplace(ModifyFieldReplace::new("mark", ("draft", "final")))
+        ]).await.unwrap();
+        assert!(report.committed);
+        assert_eq!(core.op_marks_of(&b).await.unwrap(), strings(&["final", "keep"]));
+        core.emit_changes().await;
+        assert!(matches!(notices.try_recv(), Ok(Notice::Change(Change::Records(files))) if files == vec![b.clone()]));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_saved_grep_not_cached() {
+        let (core, dir) = test_server("saved_grep", AccessPolicy::default());
+        let a = dir.join("a.txt").to_str().unwrap().to_string();
+        let grep = Query::new(vec![build_filter("mark", "or", vec!["gamma".to_string()]).unwrap(),
+            build_filter("type", "or", vec!["Text".to_string()]).unwrap()], "file", true, false, false);
+        core.op_saved(SearchSaver::Create("gamma".to_string(), grep)).await.unwrap();
+        let plain = Query::new(vec![], "mark", false, false, false);
+        core.op_saved(SearchSaver::Create("marks".to_string(), plain)).await.unwrap();
+
+        assert!(matches!(core.op_saved(SearchSaver::Run("gamma".to_string())).await, Ok(SavedReply::Results(r)) if r.is_empty()));
+        core.op_saved(SearchSaver::Run("marks".to_string())).await.unwrap();
+        assert!(core.cache.lock().unwrap().contains_key("marks"));
+        assert!(!core.cache.lock().unwrap().contains_key("gamma"));
+        std::fs::write(&a, "alpha gamma").unwrap();
+        assert!(matches!(core.op_saved(SearchSaver::Run("gamma".to_string())).await, Ok(SavedReply::Results(r)) if r == vec![a.clone()]));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_rename_type() {
+        let (core, dir) = test_server("rename_type", AccessPolicy::default());
+        let a = dir.join("a.txt").to_str().unwrap().to_string();
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        core.op_modify(Modifier::AddRecord(ModifyAddRecord::new(std::slice::from_ref(&a), &strings(&["rust"]), &[]))).await.unwrap();
+        let rename = |old: &str, new: &str| {
+            let msg = crate::message::rename_type_msg(old, new, true);
+            crate::parse::parse_manage_msg(msg.iter().map(|x| x.as_str()).collect()).unwrap()
+        };
+        let types = |core: &MemoBookServer<UtcKeeper>| {
+            let mut t: Vec<String> = core.cfg.lock().unwrap().mime().keys().cloned().collect();
+            t.sort();
+            t
+        };
+        let typed = |t: &str| Query::new(vec![build_filter("type", "or", vec![t.to_string()]).unwrap()], "file", false, false, false);
+
+        // the quote breaks the rewrite, which must leave the table as it was
+        assert!(core.op_manage(rename("Text", "Plain'text")).await.is_err());
+        assert_eq!(types(&core), strings(&["Markdown", "Text"]));
+        assert_eq!(core.op_search(typed("Text"), "test").await.unwrap(), vec![a.clone()]);
+
+        core.cfg.lock().unwrap().mb_alt(false);
+        core.op_manage(rename("Text", "Plain")).await.unwrap();
+        assert_eq!(types(&core), strings(&["Markdown", "Plain"]));
+        assert_eq!(core.op_search(typed("Plain"), "test").await.unwrap(), vec![a.clone()]);
+        assert!(core.cfg.lock().unwrap().mb().alt);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_shutdown() {
+        let (core, dir) = test_server("shutdown", AccessPolicy::default());
+        let mut notices = core.notices.subscribe();
+        // repository changes are only written out by finish
+        let drafts = dir.join("drafts");
+        std::fs::create_dir_all(&drafts).unwrap();
+        let msg = crate::message::set_repo_msg(None, &[dir.to_str().unwrap().to_string()], &[drafts.to_str().unwrap().to_string()]);
+        let request = crate::parse::parse_manage_msg(msg.iter().map(|x| x.as_str()).collect()).unwrap();
+        core.op_manage(request).await.unwrap();
+        core.emit_changes().await;
+        while notices.try_recv().is_ok() {}
+        assert!(!std::fs::read_to_string(dir.join("conf.json")).unwrap().contains("drafts"));
+
+        core.shutdown().await;
+        assert!(core.exitflag.load(Ordering::SeqCst));
+        assert!(matches!(notices.try_recv(), Ok(Notice::Change(Change::Exiting))));
+        assert!(std::fs::read_to_string(dir.join("conf.json")).unwrap().contains("drafts"));
+        core.shutdown().await;
+        assert!(notices.try_recv().is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+
+    /// A server over a new archive in a directory of its own, holding a.txt and b.md. Needs a
+    /// multi-threaded tokio runtime, where its background work goes.
+    pub(crate) fn test_server(name: &str, access: AccessPolicy) -> (MemoBookServer<UtcKeeper>, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("memoserv_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "alpha").unwrap();
+        std::fs::write(dir.join("b.md"), "beta").unwrap();
+        let conf = json::object!{
+            database: { src: dir.join("archive.db").to_str().unwrap(), table: "bookmarks",
+                scan: { trunk: "", include: [dir.to_str().unwrap()], exclude: [] }, alt: false },
+            mime: [["Text", "txt"], ["Markdown", "md"]]
+        };
+        let confpath = dir.join("conf.json");
+        std::fs::write(&confpath, conf.dump()).unwrap();
+        let cfg: Configuration<UtcKeeper> = Configuration::read(confpath.to_str().unwrap(), None).unwrap();
+        let mut book = MemoBook::new(cfg.mb(), cfg.mime());
+        book.connect(None).unwrap();
+        (MemoBookServer::new(cfg, book, access, tokio::runtime::Handle::current()), dir)
+    }
+
+}
@@ -0,0 +1,209 @@
+//  options.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+use std::fs;
+use std::time::Duration;
+use crate::access::AccessPolicy;
+use crate::busconfig::{BusConfig, BusType};
+use crate::http::{HttpConfig, Listen};
+use crate::rpc::RpcConfig;
+
+
+// Startup settings, from the configuration file and then the command line. The service is
+// reached over D-Bus, the JSON-RPC socket and the http listener, in any mix; at least one must be on.
+// "idle" at the top of conf.json (or --idle) gives the minutes without requests before it exits.
+
+pub const USAGE: &str = "usage: memoserv [--session | --system] [--name BUSNAME] [--path OBJECTPATH] [--search-provider]
+                [--krunner] [--no-dbus] [--socket PATH] [--http ADDRESS] [--readonly] [--idle MINUTES] CONF.JSON
+       memoserv units [--print] [SERVICE OPTIONS] CONF.JSON";
+
+
+pub struct Options {
+    pub confaddr: String,
+    pub bus: Option<BusConfig>,     // none when D-Bus is off
+    pub rpc: Option<RpcConfig>,     // none when the socket is off
+    pub http: Option<HttpConfig>,   // none when the http listener is off
+    pub access: AccessPolicy,
+    pub idle: Option<Duration>      // none to stay up
+}
+
+
+impl Options {
+
+    /// The command line without the program name
+    pub fn from_args(args: &[String]) -> Result<Options, String> {
+        let mut confaddr: Option<&str> = None;
+        let mut bus: Option<BusType> = None;
+        let mut name: Option<&str> = None;
+        let mut path: Option<&str> = None;
+        let mut socket: Option<&str> = None;
+        let mut listen: Option<&str> = None;
+        let mut provider = false;
+        let mut krunner = false;
+        let mut nodbus = false;
+        let mut readonly = false;
+        let mut idle: Option<&str> = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--session" => { bus = Some(BusType::Session); },
+                "--system" => { bus = Some(BusType::System); },
+                "--name" => { name = Some(iter.next().ok_or("--name needs a bus name")?); },
+                "--path" => { path = Some(iter.next().ok_or("--path needs an object path")?); },
+                "--search-provider" => { provider = true; },
+                "--krunner" => { krunner = true; },
+                "--no-dbus" => { nodbus = true; },
+                "--socket" => { socket = Some(iter.next().ok_or("--socket needs a path")?); },
+                "--http" => { listen = Some(iter.next().ok_or("--http needs an address or socket path")?); },
+                "--readonly" => { readonly = true; },
+                "--idle" => { idle = Some(iter.next().ok_or("--idle needs a number of minutes")?); },
+                x if x.starts_with("--") => { return Err(format!("unknown option: {x}")); },
+                x => {
+                    if confaddr.is_some() {
+                        return Err("only one configuration file may be given".to_string());
+                    }
+                    confaddr = Some(x);
+                }
+            }
+        }
+        let confaddr = confaddr.ok_or("valid configuration file needed")?;
+        let fdata = fs::read_to_string(confaddr).map_err(|_| "Unable to read file".to_string())?;
+        let rawjson = json::parse(&fdata).map_err(|_| "Unable to parse input".to_string())?;
+
+        let mut bc = BusConfig::read(&rawjson)?;
+        if let Some(b) = bus {
+            bc.bus = b;
+        }
+        if let Some(n) = name {
+            bc.name = n.to_string();
+        }
+        if let Some(p) = path {
+            bc.path = p.to_string();
+        }
+        bc.search_provider |= provider;
+        bc.krunner |= krunner;
+        bc.validate()?;
+        let dbus = match &rawjson["dbus"]["enabled"] {
+            json::JsonValue::Null => true,
+            x => x.as_bool().ok_or("Parse error on dbus enabled switch")?
+        };
+
+        let mut rpc = RpcConfig::read(&rawjson)?;
+        if let Some(s) = socket {
+            match rpc.as_mut() {
+                Some(r) => { r.socket = s.to_string(); },
+                None => { rpc = Some(RpcConfig { socket: s.to_string(), shared: false }); }
+            }
+        }
+
+        let mut http = HttpConfig::read(&rawjson)?;
+        if let Some(l) = listen {
+            let l = Listen::parse(l)?;
+            match http.as_mut() {
+                Some(h) => { h.listen = l; },
+                None => { http = Some(HttpConfig::new(l)); }
+            }
+        }
+
+        let mut access = AccessPolicy::read(&rawjson)?;
+        access.readonly |= readonly;
+
+        let minutes = match (idle, &rawjson["idle"]) {
+            (Some(m), _) => m.parse::<u64>().map_err(|_| format!("--idle needs a number of minutes, not {m}"))?,
+            (None, json::JsonValue::Null) => 0,
+            (None, x) => x.as_u64().ok_or("Parse error on idle minutes")?
+        };
+        let seconds = minutes.checked_mul(60).ok_or(format!("idle timeout of {minutes} minutes is too long"))?;
+        let idle = if seconds > 0 { Some(Duration::from_secs(seconds)) } else { None };
+
+        let bus = if dbus && !nodbus { Some(bc) } else { None };
+        if bus.is_none() && rpc.is_none() && http.is_none() {
+            return Err("with D-Bus off, a socket or http listener is needed".to_string());
+        }
+        Ok(Options { confaddr: confaddr.to_string(), bus, rpc, http, access, idle })
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_args_override_file() {
+        let dir = std::env::temp_dir().join(format!("memoserv_options_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let conf = dir.join("conf.json");
+        fs::write(&conf, r#"{"dbus": {"bus": "system", "name": "org.memobook.house", "path": "/org/memobook/house"},
+            "rpc": {"socket": "/run/memoserv.sock", "shared": true}}"#).unwrap();
+        let confaddr = conf.to_str().unwrap().to_string();
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+        let opts = Options::from_args(&args(&[&confaddr])).unwrap();
+        assert_eq!(opts.confaddr, confaddr);
+        let bc = opts.bus.unwrap();
+        assert_eq!((bc.bus, bc.name.as_str()), (BusType::System, "org.memobook.house"));
+        assert_eq!(opts.rpc, Some(RpcConfig { socket: "/run/memoserv.sock".to_string(), shared: true }));
+        assert!(!opts.access.readonly);
+        assert!(opts.idle.is_none());
+
+        let opts = Options::from_args(&args(&["--session", "--name", "org.memobook.notes", "--readonly", &confaddr])).unwrap();
+        let bc = opts.bus.unwrap();
+        assert_eq!((bc.bus, bc.name.as_str(), bc.path.as_str()), (BusType::Session, "org.memobook.notes", "/org/memobook/house"));
+        assert!(!bc.search_provider);
+        assert!(Options::from_args(&args(&["--search-provider", &confaddr])).unwrap().bus.unwrap().search_provider);
+        assert!(Options::from_args(&args(&["--krunner", &confaddr])).unwrap().bus.unwrap().krunner);
+        assert!(opts.access.readonly);
+
+        let opts = Options::from_args(&args(&["--no-dbus", "--socket", "/tmp/m.sock", &confaddr])).unwrap();
+        assert!(opts.bus.is_none());
+        assert_eq!(opts.rpc.unwrap().socket, "/tmp/m.sock");
+        assert!(opts.http.is_none());
+
+        let opts = Options::from_args(&args(&["--no-dbus", "--http", "localhost:8377", &confaddr])).unwrap();
+        assert_eq!(opts.http.unwrap().listen, Listen::parse("127.0.0.1:8377").unwrap());
+        assert!(Options::from_args(&args(&["--http", "0.0.0.0:8377", &confaddr])).is_err());
+
+        assert!(Options::from_args(&args(&[])).is_err());
+        assert!(Options::from_args(&args(&["--name"])).is_err());
+        assert!(Options::from_args(&args(&["--bogus", &confaddr])).is_err());
+        assert!(Options::from_args(&args(&["--path", "bad", &confaddr])).is_err());
+        assert_eq!(Options::from_args(&args(&["--idle", "10", &confaddr])).unwrap().idle, Some(Duration::from_secs(600)));
+        assert!(Options::from_args(&args(&["--idle", "0", &confaddr])).unwrap().idle.is_none());
+        assert!(Options::from_args(&args(&["--idle", "soon", &confaddr])).is_err());
+        assert!(Options::from_args(&args(&["--idle", &u64::MAX.to_string(), &confaddr])).is_err());
+
+        fs::write(&conf, r#"{"idle": 5}"#).unwrap();
+        assert_eq!(Options::from_args(&args(&[&confaddr])).unwrap().idle, Some(Duration::from_secs(300)));
+        assert!(Options::from_args(&args(&["--idle", "0", &confaddr])).unwrap().idle.is_none());
+
+        fs::write(&conf, r#"{"dbus": {"enabled": false}}"#).unwrap();
+        assert!(Options::from_args(&args(&[&confaddr])).is_err());
+        assert!(Options::from_args(&args(&["--socket", "/tmp/m.sock", &confaddr])).unwrap().bus.is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+}
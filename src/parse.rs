@@ -39,6 +39,7 @@ use memobook::transportstruct::TransPortStruct;
 use memobook::backer::{BuNumber, TransBackStruct};
 use crate::manager::Manager;
 use crate::configmodifier::ConfigModifier;
+use crate::searchsaver::SearchSaver;
 
 
 pub fn parse_grep_triplet(input: &[&str]) -> std::result::Result<(bool, bool, bool), MBError> {
@@ -61,8 +62,28 @@ pub fn parse_grep_triplet(input: &[&str]) -> std::result::Result<(bool, bool, bo
 }
 
 
+/// Shared by the string and typed interfaces: a filter from its field, logic and terms
+pub fn build_filter(field: &str, logic: &str, terms: Vec<String>) -> std::result::Result<MBFilter, MBError> {
+    let ftype: String = match field {
+        "mark" => "mark".to_string(),
+        "file" => "file".to_string(),
+        "type" => "type".to_string(),
+        _ => return Err(MBError::DBusMessage("improper filter term: filter type".to_string()))
+    };
+    let flogic: Logic = match logic {
+        "and" => Logic::AND,
+        "or" => Logic::OR,
+        _ => return Err(MBError::DBusMessage("improper filter term: filter logic".to_string()))
+    };
+    Ok(MBFilter::new(ftype, flogic, terms))
+}
+
+
 pub fn parse_search_msg(msgvec: Vec<&str>) -> std::result::Result<Query<MBFilter>, MBError> {
     let msglen = msgvec.len();
+    if msglen < 5 {
+        return Err(MBError::DBusMessage("improperly formed message (# of terms)".to_string()));
+    }
     let grepoptions = parse_grep_triplet(&msgvec[0..3])?;
     let equality = msgvec[3];
     let count = match msgvec[4].to_string().parse::<usize>() {
@@ -75,26 +96,21 @@ pub fn parse_search_msg(msgvec: Vec<&str>) -> std::result::Result<Query<MBFilter
     let mut filtervec: Vec<MBFilter> = Vec::new();
     let mut index:usize = 5;
     while index < msglen {
-        let ftype: String = match msgvec[index] {
-            "mark" => "mark".to_string(),
-            "file" => "file".to_string(),
-            "type" => "type".to_string(),
-            _ => return Err(MBError::DBusMessage("improper filter term: filter type".to_string()))
-        };
-        let flogic: Logic = match msgvec[index+1] {
-            "and" => Logic::AND,
-            "or" => Logic::OR,
-            _ => return Err(MBError::DBusMessage("improper filter term: filter logic".to_string()))
-        };
+        if index + 3 > msglen {
+            return Err(MBError::DBusMessage("search format error or unused terms present".to_string()))
+        }
         let numvecterm: usize = match msgvec[index+2].to_string().parse::<usize>() {
             Ok(x) => x,
             Err(_) => return Err(MBError::DBusMessage("invalid value for # of filter terms".to_string()))
         };
+        if index + 3 + numvecterm > msglen {
+            return Err(MBError::DBusMessage("invalid value for # of filter terms".to_string()))
+        }
         let mut termsvec: Vec<String> = Vec::new();
         for subindex in 0..numvecterm {
             termsvec.push(msgvec[index+3+subindex].to_string());
         }
-        filtervec.push(MBFilter::new(ftype, flogic, termsvec));
+        filtervec.push(build_filter(msgvec[index], msgvec[index+1], termsvec)?);
         index += numvecterm + 3;
     }
     if index != msglen {
@@ -150,10 +166,16 @@ pub fn parse_add_record(input: &[&str]) -> std::result::Result<Modifier, MBError
             "file" => &mut files,
             _ => return Err(MBError::DBusMessage("improper add term: add term type".to_string()))
         };
+        if index + 2 > count {
+            return Err(MBError::DBusMessage("add format error or unused terms present".to_string()))
+        }
         let numvecterm: usize = match input[index+1].to_string().parse::<usize>() {
             Ok(x) => x,
             Err(_) => return Err(MBError::DBusMessage("invalid value for # of add terms".to_string()))
         };
+        if index + 2 + numvecterm > count {
+            return Err(MBError::DBusMessage("invalid value for # of add terms".to_string()))
+        }
         for subindex in 0..numvecterm {
             targetvec.push(input[index+2+subindex].to_string());
         }
@@ -162,16 +184,26 @@ pub fn parse_add_record(input: &[&str]) -> std::result::Result<Modifier, MBError
     if index != count {
         return Err(MBError::DBusMessage("add format error or unused terms present".to_string()))
     }
-    if types.is_empty() || marks.is_empty() || files.is_empty() {
+    build_add_record(&files, &marks, &types)
+}
+
+
+pub fn build_add_record(files: &[String], marks: &[String], types: &[String]) -> std::result::Result<Modifier, MBError> {
+    // types are optional: memobook infers each file's type from the mime table when none are given
+    if marks.is_empty() || files.is_empty() {
         return Err(MBError::DBusMessage("missing terms for add record".to_string()));
     }
-    Ok(Modifier::AddRecord(ModifyAddRecord::new(&files, &marks, &types)))
+    Ok(Modifier::AddRecord(ModifyAddRecord::new(files, marks, types)))
 }
 
 
+#[allow(clippy::manual_is_multiple_of)]
 pub fn parse_field_replace(input: &[&str]) -> std::result::Result<Modifier, MBError> {
     let count: usize = input.len();
     let mut index: usize;
+    if count < 2 {
+        return Err(MBError::DBusMessage("improperly formed message (# of terms)".to_string()));
+    }
     let argfield: &str = input[0];
     let mut argtuples: Vec<(&str, &str)> = Vec::new();
     let numvecterm: usize = match input[1].to_string().parse::<usize>() {
@@ -195,8 +227,16 @@ pub fn parse_field_replace(input: &[&str]) -> std::result::Result<Modifier, MBEr
         argtuples.push((input[index], input[index+1]));
         index += 2;
     }
-    let mut modfieldrepl: ModifyFieldReplace = ModifyFieldReplace::new(argfield, argtuples.pop().unwrap());
-    for tup in argtuples {
+    build_field_replace(argfield, argtuples)
+}
+
+
+pub fn build_field_replace(field: &str, mut pairs: Vec<(&str, &str)>) -> std::result::Result<Modifier, MBError> {
+    let Some(last) = pairs.pop() else {
+        return Err(MBError::DBusMessage("improperly formed message (# of tuple terms is zero)".to_string()));
+    };
+    let mut modfieldrepl: ModifyFieldReplace = ModifyFieldReplace::new(field, last);
+    for tup in pairs {
         modfieldrepl.add(tup);
     }
     Ok(Modifier::FieldReplace(modfieldrepl))
@@ -206,7 +246,9 @@ pub fn parse_field_replace(input: &[&str]) -> std::result::Result<Modifier, MBEr
 pub fn parse_mark_update(input: &[&str]) -> std::result::Result<Modifier, MBError> {
     let count: usize = input.len();
     let mut index: usize = 1;
-    let argfile: &str = input[0];
+    let Some(&argfile) = input.first() else {
+        return Err(MBError::DBusMessage("improperly formed message (no file given)".to_string()));
+    };
     let mut argtype: Vec<String> = Vec::new();
     let mut argrems: Vec<String> = Vec::new();
     let mut argadds: Vec<String> = Vec::new();
@@ -217,10 +259,16 @@ pub fn parse_mark_update(input: &[&str]) -> std::result::Result<Modifier, MBErro
             "aux" => &mut argtype,
             _ => return Err(MBError::DBusMessage("improper mark update term type".to_string()))
         };
+        if index + 2 > count {
+            return Err(MBError::DBusMessage("mark update format error or unused terms present".to_string()))
+        }
         let numvecterm: usize = match input[index+1].to_string().parse::<usize>() {
             Ok(x) => x,
             Err(_) => return Err(MBError::DBusMessage("invalid value for # of mark update terms".to_string()))
         };
+        if index + 2 + numvecterm > count {
+            return Err(MBError::DBusMessage("invalid value for # of mark update terms".to_string()))
+        }
         for subindex in 0..numvecterm {
             targetvec.push(input[index+2+subindex].to_string());
         }
@@ -229,17 +277,24 @@ pub fn parse_mark_update(input: &[&str]) -> std::result::Result<Modifier, MBErro
     if index != count {
         return Err(MBError::DBusMessage("mark update format error or unused terms present".to_string()))
     }
-    if argtype.is_empty() || (argrems.is_empty() && argadds.is_empty()) {
+    build_mark_update(argfile, &argtype, &argrems, &argadds)
+}
+
+
+pub fn build_mark_update(file: &str, types: &[String], rems: &[String], adds: &[String]) -> std::result::Result<Modifier, MBError> {
+    if types.is_empty() || (rems.is_empty() && adds.is_empty()) {
         return Err(MBError::DBusMessage("missing terms for mark update".to_string()));
     }
-    Ok(Modifier::MarkUpdate(ModifyMarkUpdate::new(argfile, &argtype, &argrems, &argadds)))
+    Ok(Modifier::MarkUpdate(ModifyMarkUpdate::new(file, types, rems, adds)))
 }
 
 
 pub fn parse_type_update(input: &[&str]) -> std::result::Result<Modifier, MBError> {
     let count: usize = input.len();
     let mut index: usize = 1;
-    let argfile: &str = input[0];
+    let Some(&argfile) = input.first() else {
+        return Err(MBError::DBusMessage("improperly formed message (no file given)".to_string()));
+    };
     let mut argmark: Vec<String> = Vec::new();
     let mut argrems: Vec<String> = Vec::new();
     let mut argadds: Vec<String> = Vec::new();
@@ -250,10 +305,16 @@ pub fn parse_type_update(input: &[&str]) -> std::result::Result<Modifier, MBErro
             "aux" => &mut argmark,
             _ => return Err(MBError::DBusMessage("improper type update term type".to_string()))
         };
+        if index + 2 > count {
+            return Err(MBError::DBusMessage("type update format error or unused terms present".to_string()))
+        }
         let numvecterm: usize = match input[index+1].to_string().parse::<usize>() {
             Ok(x) => x,
             Err(_) => return Err(MBError::DBusMessage("invalid value for # of type update terms".to_string()))
         };
+        if index + 2 + numvecterm > count {
+            return Err(MBError::DBusMessage("invalid value for # of type update terms".to_string()))
+        }
         for subindex in 0..numvecterm {
             targetvec.push(input[index+2+subindex].to_string());
         }
@@ -262,10 +323,15 @@ pub fn parse_type_update(input: &[&str]) -> std::result::Result<Modifier, MBErro
     if index != count {
         return Err(MBError::DBusMessage("type update format error or unused terms present".to_string()))
     }
-    if argmark.is_empty() || (argrems.is_empty() && argadds.is_empty()) {
+    build_type_update(argfile, &argmark, &argrems, &argadds)
+}
+
+
+pub fn build_type_update(file: &str, marks: &[String], rems: &[String], adds: &[String]) -> std::result::Result<Modifier, MBError> {
+    if marks.is_empty() || (rems.is_empty() && adds.is_empty()) {
         return Err(MBError::DBusMessage("missing terms for type update".to_string()));
     }
-    Ok(Modifier::TypeUpdate(ModifyTypeUpdate::new(argfile, &argmark, &argrems, &argadds)))
+    Ok(Modifier::TypeUpdate(ModifyTypeUpdate::new(file, marks, rems, adds)))
 }
 
 
@@ -278,7 +344,10 @@ pub fn parse_target_remove(input: &[&str]) -> std::result::Result<Modifier, MBEr
 
 
 pub fn parse_modification_msg(input: Vec<&str>) -> std::result::Result<Modifier, MBError> {
-    let modtype: &str = input[0]; 
+    if input.len() < 2 {
+        return Err(MBError::DBusMessage("improperly formed message (# of terms)".to_string()));
+    }
+    let modtype: &str = input[0];
     let count = match input[1].to_string().parse::<usize>() {
         Ok(x) => x,
         Err(_) => return Err(MBError::DBusMessage("improperly formed message (invalid # of terms)".to_string()))
@@ -297,6 +366,35 @@ pub fn parse_modification_msg(input: Vec<&str>) -> std::result::Result<Modifier,
 }
 
 
+/// A batch is modification messages back to back
+pub fn parse_batch_msg(input: Vec<&str>) -> std::result::Result<Vec<Modifier>, MBError> {
+    let mut mods: Vec<Modifier> = Vec::new();
+    let mut index: usize = 0;
+    while index < input.len() {
+        if index + 2 > input.len() {
+            return Err(MBError::DBusMessage("improperly formed batch (unused terms present)".to_string()));
+        }
+        let count = match input[index+1].to_string().parse::<usize>() {
+            Ok(x) => x,
+            Err(_) => return Err(MBError::DBusMessage(format!("improperly formed batch (invalid # of terms in item {})", mods.len() + 1)))
+        };
+        let end = index + 2 + count;
+        if end > input.len() {
+            return Err(MBError::DBusMessage(format!("improperly formed batch (# of reported terms in item {})", mods.len() + 1)));
+        }
+        match parse_modification_msg(input[index..end].to_vec()) {
+            Ok(m) => mods.push(m),
+            Err(e) => return Err(MBError::DBusMessage(format!("batch item {}: {e}", mods.len() + 1)))
+        }
+        index = end;
+    }
+    if mods.is_empty() {
+        return Err(MBError::DBusMessage("empty batch".to_string()));
+    }
+    Ok(mods)
+}
+
+
 pub fn parse_and_build_repo(trunk: Option<&str>, terms: &[&str]) -> std::result::Result<Repository, MBError> {
     let mut index: usize = 0;
     let length: usize = terms.len();
@@ -305,9 +403,9 @@ pub fn parse_and_build_repo(trunk: Option<&str>, terms: &[&str]) -> std::result:
     while index < length {
         match terms[index] {
             "include" => {
-                let subcount: usize = match terms[index+1].to_string().parse::<usize>() {
-                    Ok(x) => x,
-                    Err(_) => return Err(MBError::DBusMessage("configuration manage call: invalid # of includes".to_string()))
+                let subcount: usize = match terms.get(index+1).map(|t| t.parse::<usize>()) {
+                    Some(Ok(x)) if index + x + 2 <= length => x,
+                    _ => return Err(MBError::DBusMessage("configuration manage call: invalid # of includes".to_string()))
                 };
                 let mut subindex: usize = 0;
                 while subindex < subcount {
@@ -317,9 +415,9 @@ pub fn parse_and_build_repo(trunk: Option<&str>, terms: &[&str]) -> std::result:
                 index += subcount + 2;
             },
             "exclude" => {
-                let subcount: usize = match terms[index+1].to_string().parse::<usize>() {
-                    Ok(x) => x,
-                    Err(_) => return Err(MBError::DBusMessage("configuration manage call: invalid # of includes".to_string()))
+                let subcount: usize = match terms.get(index+1).map(|t| t.parse::<usize>()) {
+                    Some(Ok(x)) if index + x + 2 <= length => x,
+                    _ => return Err(MBError::DBusMessage("configuration manage call: invalid # of includes".to_string()))
                 };
                 let mut subindex: usize = 0;
                 while subindex < subcount {
@@ -334,13 +432,18 @@ pub fn parse_and_build_repo(trunk: Option<&str>, terms: &[&str]) -> std::result:
     if index != length {
         return Err(MBError::DBusMessage("configuration manage call: format error or unused terms present".to_string()))
     }
+    Ok(build_repo(trunk, includes, excludes))
+}
+
+
+pub fn build_repo(trunk: Option<&str>, includes: Vec<String>, excludes: Vec<String>) -> Repository {
     let mut repo: Repository = Repository::new();
     if let Some(trunkpath) = trunk {
         repo.set_trunk(trunkpath);
     }
     repo.add_include_v(includes);
     repo.add_exclude_v(excludes);
-    Ok(repo)
+    repo
 }
 
 
@@ -352,9 +455,9 @@ pub fn parse_repo_modify(terms: &[&str]) -> std::result::Result<(Repository,Repo
     while index < length {
         match terms[index] {
             "remove" => {
-                let sublength: usize = match terms[index+1].to_string().parse::<usize>() {
-                    Ok(x) => x,
-                    Err(_) => return Err(MBError::DBusMessage("configuration manage call: invalid # of removals".to_string()))
+                let sublength: usize = match terms.get(index+1).map(|t| t.parse::<usize>()) {
+                    Some(Ok(x)) if index + x + 2 <= length => x,
+                    _ => return Err(MBError::DBusMessage("configuration manage call: invalid # of removals".to_string()))
                 };
                 rems = match parse_and_build_repo(None, &terms[(index+2)..(index+sublength+2)]) {
                     Ok(a) => a,
@@ -363,9 +466,9 @@ pub fn parse_repo_modify(terms: &[&str]) -> std::result::Result<(Repository,Repo
                 index += sublength + 2;
             },
             "add" => {
-                let sublength: usize = match terms[index+1].to_string().parse::<usize>() {
-                    Ok(x) => x,
-                    Err(_) => return Err(MBError::DBusMessage("configuration manage call: invalid # of removals".to_string()))
+                let sublength: usize = match terms.get(index+1).map(|t| t.parse::<usize>()) {
+                    Some(Ok(x)) if index + x + 2 <= length => x,
+                    _ => return Err(MBError::DBusMessage("configuration manage call: invalid # of removals".to_string()))
                 };
                 adds = match parse_and_build_repo(None, &terms[(index+2)..(index+sublength+2)]) {
                     Ok(a) => a,
@@ -383,8 +486,33 @@ pub fn parse_repo_modify(terms: &[&str]) -> std::result::Result<(Repository,Repo
 }
 
 
+/// A type request with its suffixes normalised: leading dots are dropped and empty names or
+/// suffixes refused. Shared by both bus interfaces.
+pub(crate) fn build_type_suffixes<S: AsRef<str>>(form: fn(String, Vec<String>) -> ConfigModifier, name: &str, suffixes: &[S])
+    -> std::result::Result<ConfigModifier, MBError>
+{
+    let suffixes: Vec<String> = suffixes.iter().map(|x| x.as_ref().trim_start_matches('.').to_string()).collect();
+    if name.is_empty() || suffixes.is_empty() || suffixes.iter().any(|x| x.is_empty()) {
+        return Err(MBError::DBusMessage("configuration manage call: empty type or suffix".to_string()))
+    }
+    Ok(form(name.to_string(), suffixes))
+}
+
+
 pub fn parse_manage_config(input: &[&str]) -> std::result::Result<Manager, MBError> {
-    match input[0] {
+    let Some(&configtype) = input.first() else {
+        return Err(MBError::DBusMessage("empty configuration manage call".to_string()))
+    };
+    // the repository calls read their counts from fixed places
+    let least: usize = match configtype {
+        "setrepo" | "modrepo" => 2,
+        "setrepotr" => 3,
+        _ => 1
+    };
+    if input.len() < least {
+        return Err(MBError::DBusMessage("improperly formed configuration manage call (invalid # of terms)".to_string()))
+    }
+    match configtype {
         "setsource" => {
             if input.len() != 2 {
                 Err(MBError::DBusMessage("improperly formed configuration manage call (invalid # of terms)".to_string()))
@@ -435,11 +563,46 @@ pub fn parse_manage_config(input: &[&str]) -> std::result::Result<Manager, MBErr
             };
             Ok(Manager::Configure(ConfigModifier::ModifyRepo(repotuple)))
         },
+        "listtypes" => {
+            if input.len() != 1 {
+                return Err(MBError::DBusMessage("improperly formed configuration manage call (invalid # of terms)".to_string()))
+            }
+            Ok(Manager::Configure(ConfigModifier::ListTypes))
+        },
+        "addtype" | "addsuffix" | "remsuffix" => {
+            if input.len() < 3 {
+                return Err(MBError::DBusMessage("improperly formed configuration manage call (type and suffixes required)".to_string()))
+            }
+            let form: fn(String, Vec<String>) -> ConfigModifier = match input[0] {
+                "addtype" => ConfigModifier::AddType,
+                "addsuffix" => ConfigModifier::AddSuffixes,
+                _ => ConfigModifier::RemoveSuffixes
+            };
+            Ok(Manager::Configure(build_type_suffixes(form, input[1], &input[2..])?))
+        },
+        "remtype" => {
+            if input.len() != 2 {
+                return Err(MBError::DBusMessage("improperly formed configuration manage call (invalid # of terms)".to_string()))
+            }
+            Ok(Manager::Configure(ConfigModifier::RemoveType(input[1].to_string())))
+        },
+        "renametype" => {
+            if input.len() != 4 {
+                return Err(MBError::DBusMessage("improperly formed configuration manage call (invalid # of terms)".to_string()))
+            }
+            let rewrite = match input[3] {
+                "true" => true,
+                "false" => false,
+                _ => return Err(MBError::DBusMessage("configuration manage call: invalid database rewrite flag".to_string()))
+            };
+            Ok(Manager::Configure(ConfigModifier::RenameType(input[1].to_string(), input[2].to_string(), rewrite)))
+        },
         _ => Err(MBError::DBusMessage("invalid configuration manage call type".to_string()))
     }
 }
 
 
+#[allow(clippy::manual_is_multiple_of)]
 pub fn parse_manage_import(input: &[&str]) -> std::result::Result<Manager, MBError> {
     let mut importfn: &str = "";
     let mut logfn: &str = "";
@@ -481,10 +644,14 @@ pub fn parse_manage_import(input: &[&str]) -> std::result::Result<Manager, MBErr
 
 
 pub fn parse_manage_export(input: &[&str]) -> std::result::Result<Manager, MBError> {
+    if input.len() != 1 {
+        return Err(MBError::DBusMessage("improperly formed export call (# of terms)".to_string()))
+    }
     Ok(Manager::Export(TransPortStruct {target: "".to_string(), log: input[0].to_string(), links: false}))
 }
 
 
+#[allow(clippy::manual_is_multiple_of)]
 pub fn parse_manage_backup(input: &[&str]) -> std::result::Result<Manager, MBError> {
     let mut clear: bool = false;
     let mut force: bool = false;
@@ -565,6 +732,9 @@ pub fn parse_manage_backup(input: &[&str]) -> std::result::Result<Manager, MBErr
 
 
 pub fn parse_manage_msg(input: Vec<&str>) -> std::result::Result<Manager, MBError> {
+    if input.len() < 2 {
+        return Err(MBError::DBusMessage("improperly formed message (# of terms)".to_string()));
+    }
     let managetype: &str = input[0];
     let count = match input[1].to_string().parse::<usize>() {
         Ok(x) => x,
@@ -592,3 +762,169 @@ pub fn parse_toc_msg(msg: &str) -> std::result::Result<Query<MBFilter>, MBError>
     };
     Ok(Query::new(vec![], equality, false, false, false))
 }
+
+
+/// The typed interface's query: unlike the string message, the equality field is checked here
+pub fn build_query(filters: Vec<MBFilter>, equality: &str, grep: bool, grepcase: bool, greplinks: bool) -> std::result::Result<Query<MBFilter>, MBError> {
+    if !matches!(equality, "file" | "mark" | "type") {
+        return Err(MBError::DBusMessage(format!("invalid search equality [{equality}]")));
+    }
+    Ok(Query::new(filters, equality, grep, grepcase, greplinks))
+}
+
+
+
+pub fn parse_saved_msg(input: Vec<&str>) -> std::result::Result<SearchSaver, MBError> {
+    if input.is_empty() {
+        return Err(MBError::DBusMessage("empty saved search request".to_string()));
+    }
+    match (input[0], input.len()) {
+        ("list", 1) => Ok(SearchSaver::List),
+        ("delete", 2) => Ok(SearchSaver::Delete(input[1].to_string())),
+        ("show", 2) => Ok(SearchSaver::Show(input[1].to_string())),
+        ("run", 2) => Ok(SearchSaver::Run(input[1].to_string())),
+        ("create", x) | ("update", x) if x > 6 => {
+            let query: Query<MBFilter> = parse_search_msg(input[2..].to_vec())?;
+            if input[0] == "create" {
+                Ok(SearchSaver::Create(input[1].to_string(), query))
+            } else {
+                Ok(SearchSaver::Update(input[1].to_string(), query))
+            }
+        },
+        ("list", _) | ("delete", _) | ("show", _) | ("run", _) | ("create", _) | ("update", _) => {
+            Err(MBError::DBusMessage("improperly formed saved search request (# of terms)".to_string()))
+        },
+        (kw, _) => Err(MBError::DBusMessage(format!("invalid keyword [{kw}] in saved search request")))
+    }
+}
+
+
+pub fn parse_related_msg(input: Vec<&str>) -> std::result::Result<(Vec<String>, usize), MBError> {
+    if input.len() < 3 {
+        return Err(MBError::DBusMessage("improperly formed related marks request (# of terms)".to_string()));
+    }
+    let limit = match input[0].to_string().parse::<usize>() {
+        Ok(x) => x,
+        Err(_) => return Err(MBError::DBusMessage("invalid value for related marks limit".to_string()))
+    };
+    let count = match input[1].to_string().parse::<usize>() {
+        Ok(x) => x,
+        Err(_) => return Err(MBError::DBusMessage("improperly formed message (invalid # of marks)".to_string()))
+    };
+    if count != input.len() - 2 {
+        return Err(MBError::DBusMessage("improperly formed message (# of reported marks)".to_string()));
+    }
+    Ok((input[2..].iter().map(|m| m.to_string()).collect(), limit))
+}
+
+
+pub fn parse_stats_msg(msg: &str) -> std::result::Result<usize, MBError> {
+    if msg.is_empty() {
+        return Ok(10);
+    }
+    match msg.parse::<usize>() {
+        Ok(x) => Ok(x),
+        Err(_) => Err(MBError::DBusMessage("invalid value for # of top marks in stats request".to_string()))
+    }
+}
+
+
+pub fn parse_suggest_msg(input: Vec<&str>) -> std::result::Result<(String, usize), MBError> {
+    match input.len() {
+        1 => Ok((input[0].to_string(), 10)),
+        2 => match input[1].to_string().parse::<usize>() {
+            Ok(x) => Ok((input[0].to_string(), x)),
+            Err(_) => Err(MBError::DBusMessage("invalid value for # of suggestions".to_string()))
+        },
+        _ => Err(MBError::DBusMessage("improperly formed suggestion request (# of terms)".to_string()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use memobook::queryer::Queryer;
+
+    #[test]
+    fn test_parse_saved_create() {
+        let msg = vec!["create", "daily", "false", "false", "false", "file", "4", "mark", "or", "1", "notes"];
+        match parse_saved_msg(msg).unwrap() {
+            SearchSaver::Create(name, query) => {
+                assert_eq!(name, "daily");
+                assert_eq!(query.equality(), "file");
+                assert!(!query.grep());
+            },
+            _ => panic!("wrong saved search request parsed")
+        }
+    }
+
+    #[test]
+    fn test_parse_saved_bad_terms() {
+        assert!(parse_saved_msg(vec!["run"]).is_err());
+        assert!(parse_saved_msg(vec!["list", "extra"]).is_err());
+        assert!(parse_saved_msg(vec!["rename", "a", "b"]).is_err());
+        assert!(parse_saved_msg(vec!["create", "daily", "false", "false", "false", "file", "9"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_related() {
+        let (marks, limit) = parse_related_msg(vec!["10", "2", "rust", "dbus"]).unwrap();
+        assert_eq!(limit, 10);
+        assert_eq!(marks, vec!["rust".to_string(), "dbus".to_string()]);
+        assert!(parse_related_msg(vec!["10", "3", "rust", "dbus"]).is_err());
+        assert!(parse_related_msg(vec!["ten", "1", "rust"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_add_without_types() {
+        let msg = vec!["addrecord", "7", "file", "2", "a.txt", "b.png", "mark", "1", "doom"];
+        match parse_modification_msg(msg).unwrap() {
+            Modifier::AddRecord(ar) => {
+                assert_eq!(ar.files.len(), 2);
+                assert!(ar.ftypes.is_empty());
+            },
+            _ => panic!("wrong modification parsed")
+        }
+        assert!(parse_modification_msg(vec!["addrecord", "3", "file", "1", "a.txt"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_short_messages() {
+        assert!(parse_search_msg(vec![]).is_err());
+        assert!(parse_search_msg(vec!["false", "false", "false", "file", "3", "mark", "or", "2"]).is_err());
+        assert!(parse_search_msg(vec!["false", "false", "false", "file", "2", "mark", "or"]).is_err());
+        assert!(parse_modification_msg(vec!["addrecord"]).is_err());
+        assert!(parse_modification_msg(vec!["markupdate", "0"]).is_err());
+        assert!(parse_modification_msg(vec!["addrecord", "3", "file", "2", "a.txt"]).is_err());
+        assert!(parse_modification_msg(vec!["fieldreplace", "1", "mark"]).is_err());
+        assert!(parse_manage_msg(vec![]).is_err());
+        assert!(parse_manage_msg(vec!["configuration", "0"]).is_err());
+        assert!(parse_manage_msg(vec!["configuration", "1", "setrepo"]).is_err());
+        assert!(parse_manage_msg(vec!["configuration", "4", "setrepo", "2", "include", "3"]).is_err());
+        assert!(parse_manage_msg(vec!["configuration", "4", "modrepo", "2", "add", "5"]).is_err());
+        assert!(parse_manage_msg(vec!["export", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_manage_types() {
+        match parse_manage_msg(vec!["configuration", "4", "addtype", "Image", ".png", "jpg"]).unwrap() {
+            Manager::Configure(ConfigModifier::AddType(name, suffixes)) => {
+                assert_eq!(name, "Image");
+                assert_eq!(suffixes, vec!["png".to_string(), "jpg".to_string()]);
+            },
+            _ => panic!("wrong configuration request parsed")
+        }
+        match parse_manage_msg(vec!["configuration", "4", "renametype", "Image", "Picture", "true"]).unwrap() {
+            Manager::Configure(ConfigModifier::RenameType(old, new, rewrite)) => {
+                assert_eq!((old.as_str(), new.as_str(), rewrite), ("Image", "Picture", true));
+            },
+            _ => panic!("wrong configuration request parsed")
+        }
+        assert!(parse_manage_msg(vec!["configuration", "2", "addsuffix", "Image"]).is_err());
+        assert!(parse_manage_msg(vec!["configuration", "4", "renametype", "Image", "Picture", "yes"]).is_err());
+        assert!(parse_manage_msg(vec!["configuration", "2", "listtypes", "extra"]).is_err());
+    }
+
+}
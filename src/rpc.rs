@@ -0,0 +1,524 @@
+//  rpc.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use json::JsonValue;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::sync::broadcast::error::RecvError;
+use memobook::backer::Backer;
+use memobook::backerparserjson::BackerParserJSON;
+use memobook::mberror::MBError;
+use memobook::savedsearch::query_to_json;
+use crate::{MemoBookServer, manage_access, scored_json, related_json, suggestions_json};
+use crate::access::{Access, Role};
+use crate::jobs::JobWork;
+use crate::manager::Manager;
+use crate::ops::{Change, Notice, OpError, SavedReply};
+use crate::parse::*;
+
+
+// JSON-RPC 2.0 over a Unix socket, for machines without a session bus. Each line holds a request
+// or a batch, and each reply is a line. Methods are those of org.memobook.memoserv1, in snake
+// case, and params is the same array of strings the D-Bus method takes (one string for toc,
+// stats and the job calls); results come back as json rather than joined strings. Notices of
+// changes and jobs arrive as notifications, named after the D-Bus signals.
+// Callers get their role from the socket peer's uid.
+
+pub const PARSE_ERROR: i32 = -32700;
+pub const INVALID_REQUEST: i32 = -32600;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+pub const OPERATION_FAILED: i32 = -32000;
+pub const ACCESS_DENIED: i32 = -32001;
+
+
+/// The "rpc" section of conf.json: the socket path, and whether users other than the owner
+/// may connect (their role then comes from the access policy)
+#[derive(Clone, PartialEq, Debug)]
+pub struct RpcConfig {
+    pub socket: String,
+    pub shared: bool
+}
+
+
+impl RpcConfig {
+
+    pub fn read(rawjson: &JsonValue) -> Result<Option<RpcConfig>, String> {
+        let section = &rawjson["rpc"];
+        if section.is_null() {
+            return Ok(None);
+        }
+        let socket = section["socket"].as_str().ok_or("Parse error on rpc socket")?;
+        let shared = match &section["shared"] {
+            JsonValue::Null => false,
+            x => x.as_bool().ok_or("Parse error on rpc shared switch")?
+        };
+        Ok(Some(RpcConfig { socket: socket.to_string(), shared }))
+    }
+
+    pub fn bind(&self) -> io::Result<UnixListener> {
+        bind_socket(&self.socket, self.shared)
+    }
+
+}
+
+
+/// Bind a Unix socket, clearing one left behind by an instance that is gone. Only the owner
+/// may connect unless it is shared.
+pub fn bind_socket(path: &str, shared: bool) -> io::Result<UnixListener> {
+    if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{path} is in use")));
+        }
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    let mode = if shared { 0o666 } else { 0o600 };
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+
+#[derive(Debug, PartialEq)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String
+}
+
+
+impl RpcError {
+
+    pub fn new(code: i32, message: impl ToString) -> RpcError {
+        RpcError { code, message: message.to_string() }
+    }
+
+    fn invalid(e: MBError) -> RpcError {
+        RpcError::new(INVALID_PARAMS, e)
+    }
+
+    fn failed(e: OpError) -> RpcError {
+        RpcError::new(OPERATION_FAILED, e)
+    }
+
+    fn denied(e: OpError) -> RpcError {
+        RpcError::new(ACCESS_DENIED, e)
+    }
+
+}
+
+
+pub struct Request {
+    pub id: Option<JsonValue>,  // none for a notification, which gets no reply
+    pub method: String,
+    pub params: Vec<String>
+}
+
+
+/// Check a request object; a bad one is answered with its id, if that much could be read
+pub fn parse_request(value: &JsonValue) -> Result<Request, (JsonValue, RpcError)> {
+    let id = match &value["id"] {
+        JsonValue::Null if !value.has_key("id") => None,
+        x @ (JsonValue::Null | JsonValue::Number(_) | JsonValue::String(_) | JsonValue::Short(_)) => Some(x.clone()),
+        _ => return Err((JsonValue::Null, RpcError::new(INVALID_REQUEST, "invalid id")))
+    };
+    let reply_id = id.clone().unwrap_or(JsonValue::Null);
+    if !value.is_object() || value["jsonrpc"].as_str() != Some("2.0") {
+        return Err((reply_id, RpcError::new(INVALID_REQUEST, "not a JSON-RPC 2.0 request")));
+    }
+    let Some(method) = value["method"].as_str() else {
+        return Err((reply_id, RpcError::new(INVALID_REQUEST, "method missing")));
+    };
+    let params: Vec<String> = match &value["params"] {
+        JsonValue::Null => vec![],
+        JsonValue::Array(items) => match items.iter().map(|p| p.as_str().map(|s| s.to_string())).collect() {
+            Some(p) => p,
+            None => return Err((reply_id, RpcError::new(INVALID_PARAMS, "params must be strings")))
+        },
+        _ => return Err((reply_id, RpcError::new(INVALID_PARAMS, "params must be an array")))
+    };
+    Ok(Request { id, method: method.to_string(), params })
+}
+
+
+pub fn response(id: JsonValue, result: Result<JsonValue, RpcError>) -> JsonValue {
+    match result {
+        Ok(r) => json::object!{ jsonrpc: "2.0", result: r, id: id },
+        Err(e) => json::object!{ jsonrpc: "2.0", error: { code: e.code, message: e.message }, id: id }
+    }
+}
+
+
+pub fn notification(notice: &Notice) -> JsonValue {
+    let (method, params) = match notice {
+        Notice::Change(Change::Records(files)) => ("records_changed", json::object!{ files: files.clone() }),
+        Notice::Change(Change::Configuration(section)) => ("configuration_changed", json::object!{ section: *section }),
+        Notice::Change(Change::Backup(action)) => ("backup_changed", json::object!{ action: *action }),
+        Notice::Change(Change::Exiting) => ("exited", json::object!{}),
+        Notice::JobProgress(id, scanned, hashed, matched) => {
+            ("job_progress", json::object!{ id: *id, scanned: *scanned, hashed: *hashed, matched: *matched })
+        },
+        Notice::JobFinished(id, state, message) => {
+            ("job_finished", json::object!{ id: *id, state: state.as_str(), message: message.as_str() })
+        }
+    };
+    json::object!{ jsonrpc: "2.0", method: method, params: params }
+}
+
+
+/// Accept clients until the server exits
+pub async fn serve<B>(core: MemoBookServer<B>, listener: UnixListener)
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let core = core.clone();
+                tokio::spawn(async move { connection(core, stream).await });
+            },
+            Err(e) => println!("rpc socket error: {e}")
+        }
+    }
+}
+
+
+async fn connection<B>(core: MemoBookServer<B>, stream: UnixStream)
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    let uid = stream.peer_cred().ok().map(|c| c.uid());
+    let role = core.access.role_of(uid, &[]);
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut notices = core.notices.subscribe();
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else { break; };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (reply, exiting) = handle_line(&core, role, &line).await;
+                core.touch();
+                if let Some(r) = reply {
+                    if send(&mut writer, &r).await.is_err() {
+                        break;
+                    }
+                }
+                if exiting {
+                    // replied first, so that the client hears back before the server goes
+                    core.request_exit();
+                    break;
+                }
+            },
+            notice = notices.recv() => match notice {
+                Ok(n) => {
+                    if send(&mut writer, &notification(&n)).await.is_err() {
+                        break;
+                    }
+                },
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break
+            }
+        }
+    }
+}
+
+
+async fn send(writer: &mut OwnedWriteHalf, message: &JsonValue) -> io::Result<()> {
+    let mut line = message.dump();
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+
+/// The reply to a line, if any, and whether the server should now exit
+async fn handle_line<B>(core: &MemoBookServer<B>, role: Role, line: &str) -> (Option<JsonValue>, bool)
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    let value = match json::parse(line) {
+        Ok(v) => v,
+        Err(e) => return (Some(response(JsonValue::Null, Err(RpcError::new(PARSE_ERROR, e)))), false)
+    };
+    let JsonValue::Array(batch) = value else {
+        return handle_request(core, role, &value).await;
+    };
+    if batch.is_empty() {
+        return (Some(response(JsonValue::Null, Err(RpcError::new(INVALID_REQUEST, "empty batch")))), false);
+    }
+    let mut replies = JsonValue::new_array();
+    let mut exiting = false;
+    for item in batch.iter() {
+        let (reply, exit) = handle_request(core, role, item).await;
+        if let Some(r) = reply {
+            let _ = replies.push(r);
+        }
+        exiting |= exit;
+    }
+    (if replies.is_empty() { None } else { Some(replies) }, exiting)
+}
+
+
+async fn handle_request<B>(core: &MemoBookServer<B>, role: Role, value: &JsonValue) -> (Option<JsonValue>, bool)
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    let request = match parse_request(value) {
+        Ok(r) => r,
+        Err((id, e)) => return (Some(response(id, Err(e))), false)
+    };
+    let result = dispatch(core, role, &request.method, &request.params).await;
+    let exiting = request.method == "exit" && result.is_ok();
+    (request.id.map(|id| response(id, result)), exiting)
+}
+
+
+fn one_param(params: &[String]) -> Result<&str, RpcError> {
+    match params {
+        [p] => Ok(p.as_str()),
+        _ => Err(RpcError::new(INVALID_PARAMS, "one parameter expected"))
+    }
+}
+
+
+/// Messages for the string parsers, which check their own lengths
+fn terms(params: &[String]) -> Vec<&str> {
+    params.iter().map(|p| p.as_str()).collect()
+}
+
+
+fn job_id(params: &[String]) -> Result<u32, RpcError> {
+    let id = one_param(params)?;
+    id.trim().parse::<u32>().map_err(|_| RpcError::new(INVALID_PARAMS, format!("bad job id {id}")))
+}
+
+
+/// A configuration reply is json, or empty when there is nothing to say
+pub(crate) fn reply_json(reply: String) -> JsonValue {
+    if reply.is_empty() {
+        return JsonValue::Null;
+    }
+    json::parse(&reply).unwrap_or(JsonValue::String(reply))
+}
+
+
+async fn dispatch<B>(core: &MemoBookServer<B>, role: Role, method: &str, params: &[String]) -> Result<JsonValue, RpcError>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    let allow = |access: Access| core.access.check(access, role).map_err(RpcError::denied);
+    match method {
+        "search" => {
+            let query = parse_search_msg(terms(params)).map_err(RpcError::invalid)?;
+            core.op_search(query, "Error in search").await.map(JsonValue::from).map_err(RpcError::failed)
+        },
+        "search_scored" => {
+            let query = parse_search_msg(terms(params)).map_err(RpcError::invalid)?;
+            core.op_search_scored(query).await.map(scored_json).map_err(RpcError::failed)
+        },
+        "toc" => {
+            let query = parse_toc_msg(one_param(params)?).map_err(RpcError::invalid)?;
+            core.op_search(query, "Error in table of contents").await.map(JsonValue::from).map_err(RpcError::failed)
+        },
+        "related" => {
+            let (marks, limit) = parse_related_msg(terms(params)).map_err(RpcError::invalid)?;
+            core.op_related(marks, limit).await.map(related_json).map_err(RpcError::failed)
+        },
+        "stats" => {
+            let topn = parse_stats_msg(one_param(params)?).map_err(RpcError::invalid)?;
+            core.op_stats(topn).await.map(|st| st.to_json()).map_err(RpcError::failed)
+        },
+        "suggest" => {
+            let (file, limit) = parse_suggest_msg(terms(params)).map_err(RpcError::invalid)?;
+            core.op_suggest(file, limit).await.map(suggestions_json).map_err(RpcError::failed)
+        },
+        "backup" => Ok(reply_json(core.cfg.lock().unwrap().assemble_backup_info())),
+        "repositories" => Ok(reply_json(core.cfg.lock().unwrap().assemble_repo_info())),
+        "modify" => {
+            allow(Access::Write)?;
+            let cmd = parse_modification_msg(terms(params)).map_err(RpcError::invalid)?;
+            let reply = core.op_modify(cmd).await.map(|_| JsonValue::Null).map_err(RpcError::failed);
+            core.emit_changes().await;
+            reply
+        },
+        "modify_batch" => {
+            allow(Access::Write)?;
+            let cmds = parse_batch_msg(terms(params)).map_err(RpcError::invalid)?;
+            let reply = core.op_modify_batch(cmds).await.map(|r| r.to_json()).map_err(RpcError::failed);
+            core.emit_changes().await;
+            reply
+        },
+        "manage" => {
+            let request = parse_manage_msg(terms(params)).map_err(RpcError::invalid)?;
+            allow(manage_access(&request))?;
+            let reply = match request {
+                Manager::Import(imp) => Ok(JsonValue::from(core.op_start_job(JobWork::Import(imp)))),
+                Manager::Export(exp) => Ok(JsonValue::from(core.op_start_job(JobWork::Export(exp)))),
+                m => core.op_manage(m).await.map(reply_json).map_err(RpcError::failed)
+            };
+            core.emit_changes().await;
+            reply
+        },
+        "saved" => {
+            let cmd = parse_saved_msg(terms(params)).map_err(RpcError::invalid)?;
+            if cmd.writes() {
+                allow(Access::Write)?;
+            }
+            let reply = match core.op_saved(cmd).await.map_err(RpcError::failed)? {
+                SavedReply::Names(names) => JsonValue::from(names),
+                SavedReply::Query(q) => query_to_json(&q),
+                SavedReply::Results(results) => JsonValue::from(results),
+                SavedReply::Done => JsonValue::Null
+            };
+            core.emit_changes().await;
+            Ok(reply)
+        },
+        "search_job" => {
+            let query = parse_search_msg(terms(params)).map_err(RpcError::invalid)?;
+            Ok(JsonValue::from(core.op_start_job(JobWork::Grep(query))))
+        },
+        "cancel_job" => {
+            allow(Access::Jobs)?;
+            core.op_cancel_job(job_id(params)?).map(|_| JsonValue::Null).map_err(RpcError::failed)
+        },
+        "job_status" => core.op_job_status(job_id(params)?).map(|st| st.to_json()).map_err(RpcError::failed),
+        "exit" => {
+            allow(Access::Control)?;
+            Ok(JsonValue::Null)
+        },
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("no method {method}")))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::access::AccessPolicy;
+    use crate::message::{add_record_msg, search_msg, FilterSpec};
+    use crate::ops::tests::test_server;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+    use tokio::net::unix::OwnedReadHalf;
+
+    #[test]
+    fn test_parse_request() {
+        let parse = |s: &str| parse_request(&json::parse(s).unwrap());
+        let req = parse(r#"{"jsonrpc": "2.0", "method": "toc", "params": ["mark"], "id": 7}"#).ok().unwrap();
+        assert_eq!((req.id, req.method.as_str(), req.params), (Some(JsonValue::from(7)), "toc", vec!["mark".to_string()]));
+        assert!(parse(r#"{"jsonrpc": "2.0", "method": "exit"}"#).ok().unwrap().id.is_none());
+        let err = |s: &str| parse(s).err().unwrap();
+        assert_eq!(err(r#"{"method": "toc", "id": 1}"#), (JsonValue::from(1), RpcError::new(INVALID_REQUEST, "not a JSON-RPC 2.0 request")));
+        assert_eq!(err(r#"{"jsonrpc": "2.0", "id": "x"}"#).1.code, INVALID_REQUEST);
+        assert_eq!(err(r#"{"jsonrpc": "2.0", "method": "toc", "params": [1], "id": 1}"#).1.code, INVALID_PARAMS);
+        assert_eq!(err(r#"{"jsonrpc": "2.0", "method": "toc", "params": {"a": "b"}, "id": 1}"#).1.code, INVALID_PARAMS);
+        assert_eq!(err(r#"{"jsonrpc": "2.0", "method": "toc", "id": [1]}"#), (JsonValue::Null, RpcError::new(INVALID_REQUEST, "invalid id")));
+    }
+
+    #[test]
+    fn test_notification() {
+        let n = notification(&Notice::Change(Change::Records(vec!["a.txt".to_string()])));
+        assert_eq!(n.dump(), r#"{"jsonrpc":"2.0","method":"records_changed","params":{"files":["a.txt"]}}"#);
+        let n = notification(&Notice::JobFinished(3, "done".to_string(), "1 found".to_string()));
+        assert_eq!(n["params"]["id"], 3);
+        assert!(!n.has_key("id"));
+    }
+
+    async fn call(writer: &mut OwnedWriteHalf, lines: &mut Lines<BufReader<OwnedReadHalf>>, request: &str) -> JsonValue {
+        writer.write_all(format!("{request}\n").as_bytes()).await.unwrap();
+        loop {
+            let reply = json::parse(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            // skip notices
+            if reply.is_array() || reply.has_key("id") {
+                return reply;
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_socket_round_trip() {
+        let (core, dir) = test_server("rpc", AccessPolicy::default());
+        let config = RpcConfig { socket: dir.join("memoserv.sock").to_str().unwrap().to_string(), shared: false };
+        let listener = config.bind().unwrap();
+        assert!(config.bind().is_err());
+        tokio::spawn(serve(core.clone(), listener));
+
+        let (reader, mut writer) = UnixStream::connect(&config.socket).await.unwrap().into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let a = dir.join("a.txt").to_str().unwrap().to_string();
+        let add = json::object!{ jsonrpc: "2.0", method: "modify", id: 1,
+            params: add_record_msg(std::slice::from_ref(&a), &["rust".to_string(), "dbus".to_string()], &[]) };
+        assert_eq!(call(&mut writer, &mut lines, &add.dump()).await, json::object!{ jsonrpc: "2.0", result: null, id: 1 });
+        let filter = FilterSpec { field: "mark".to_string(), logic: "or".to_string(), terms: vec!["rust".to_string()] };
+        let search = json::object!{ jsonrpc: "2.0", method: "search", id: 2,
+            params: search_msg(&[filter], "file", false, false, false) };
+        assert_eq!(call(&mut writer, &mut lines, &search.dump()).await["result"], json::array![a.as_str()]);
+
+        let batch = r#"[{"jsonrpc": "2.0", "method": "toc", "params": ["mark"], "id": "t"},
+            {"jsonrpc": "2.0", "method": "stats", "params": ["1"]},
+            {"jsonrpc": "2.0", "method": "nothing", "id": 4},
+            {"jsonrpc": "2.0", "method": "toc", "params": ["colour"], "id": 5}]"#.replace('\n', "");
+        let reply = call(&mut writer, &mut lines, &batch).await;
+        assert_eq!(reply.len(), 3);
+        assert_eq!(reply[0]["result"], json::array!["dbus", "rust"]);
+        assert_eq!(reply[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(reply[2]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(call(&mut writer, &mut lines, "{not json").await["error"]["code"], PARSE_ERROR);
+        assert_eq!(call(&mut writer, &mut lines, r#"{"jsonrpc": "2.0", "method": "modify", "params": ["x"], "id": 6}"#).await["error"]["code"], INVALID_PARAMS);
+
+        let reply = call(&mut writer, &mut lines, r#"{"jsonrpc": "2.0", "method": "exit", "id": 9}"#).await;
+        assert_eq!(reply["id"], 9);
+        assert!(lines.next_line().await.unwrap().is_none_or(|l| !l.contains("\"id\"")));
+        tokio::time::timeout(std::time::Duration::from_secs(5), core.stop.notified()).await.unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_short_params() {
+        let (core, dir) = test_server("rpc_short", AccessPolicy::default());
+        let params = |p: &[&str]| p.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        for (method, p) in [("search", params(&["false"])), ("related", params(&[])), ("suggest", params(&[])),
+                            ("saved", params(&[])), ("modify", params(&["markupdate", "0"])),
+                            ("manage", params(&["export", "0"])), ("manage", params(&["configuration", "1", "setrepotr"]))] {
+            let err = dispatch(&core, Role::Admin, method, &p).await.err().unwrap();
+            assert_eq!(err.code, INVALID_PARAMS, "{method} {p:?}");
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_read_only_socket() {
+        let (core, dir) = test_server("rpc_ro", AccessPolicy::new(true, Role::Admin));
+        let config = RpcConfig { socket: dir.join("memoserv.sock").to_str().unwrap().to_string(), shared: false };
+        tokio::spawn(serve(core.clone(), config.bind().unwrap()));
+        let (reader, mut writer) = UnixStream::connect(&config.socket).await.unwrap().into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let reply = call(&mut writer, &mut lines, r#"{"jsonrpc": "2.0", "method": "modify", "id": 1, "params": ["targetremove", "2", "mark", "rust"]}"#).await;
+        assert_eq!(reply["error"]["code"], ACCESS_DENIED);
+        let reply = call(&mut writer, &mut lines, r#"{"jsonrpc": "2.0", "method": "toc", "id": 2, "params": ["file"]}"#).await;
+        assert_eq!(reply["result"], json::array![]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+}
@@ -0,0 +1,188 @@
+//  searchprovider.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+use std::collections::HashMap;
+use std::path::Path;
+use zbus::{fdo, interface};
+use zbus::zvariant::OwnedValue;
+use memobook::backer::Backer;
+use memobook::backerparserjson::BackerParserJSON;
+use crate::{MemoBookServer, failed, owned_value};
+
+
+// org.gnome.Shell.SearchProvider2, so that marked files turn up in the desktop's overview
+// search. Each term picks out the marks that start with it, ignoring case, and a file must
+// carry one of them for every term. Result ids are file paths; activating one opens the file
+// with xdg-open. Icons are guessed from the file's types. Served at the object path when
+// "search_provider" is set in the "dbus" section (or --search-provider); the shell finds it
+// from an ini file, see the README.
+
+
+
+pub struct SearchProvider<B: Backer+BackerParserJSON + std::marker::Send+'static> {
+    core: MemoBookServer<B>
+}
+
+
+/// A freedesktop icon name for a file's types. Type names are the user's own, so this is a guess.
+pub fn icon_for(types: &[String]) -> &'static str {
+    for t in types.iter().map(|t| t.to_lowercase()) {
+        let has = |words: &[&str]| words.iter().any(|w| t.contains(w));
+        if has(&["image", "picture", "photo"]) {
+            return "image-x-generic";
+        }
+        if has(&["pdf"]) {
+            return "application-pdf";
+        }
+        if has(&["audio", "music", "sound"]) {
+            return "audio-x-generic";
+        }
+        if has(&["video", "movie", "film"]) {
+            return "video-x-generic";
+        }
+        if has(&["code", "source", "script"]) {
+            return "text-x-script";
+        }
+    }
+    "text-x-generic"
+}
+
+
+impl<B> SearchProvider<B>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+
+    pub fn new(core: MemoBookServer<B>) -> SearchProvider<B> {
+        SearchProvider { core }
+    }
+
+}
+
+
+#[interface(name = "org.gnome.Shell.SearchProvider2")]
+impl<B> SearchProvider<B>
+where B: Backer+BackerParserJSON + std::marker::Send+std::marker::Sync+'static
+{
+
+    async fn get_initial_result_set(&self, terms: Vec<String>) -> fdo::Result<Vec<String>> {
+        self.core.op_mark_prefix_search(&terms).await.map_err(failed)
+    }
+
+    /// Narrowing a search: the new results, kept in the order the shell already has
+    async fn get_subsearch_result_set(&self, previous_results: Vec<String>, terms: Vec<String>) -> fdo::Result<Vec<String>> {
+        let found = self.core.op_mark_prefix_search(&terms).await.map_err(failed)?;
+        Ok(previous_results.into_iter().filter(|r| found.contains(r)).collect())
+    }
+
+    /// Name is the file name; the description gives its marks and folder
+    async fn get_result_metas(&self, identifiers: Vec<String>) -> fdo::Result<Vec<HashMap<String, OwnedValue>>> {
+        let mut metas = vec![];
+        for id in identifiers {
+            let marks = self.core.op_marks_of(&id).await.map_err(failed)?;
+            if marks.is_empty() {
+                continue;
+            }
+            let path = Path::new(&id);
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or(id.clone());
+            let folder = path.parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+            let mut meta = HashMap::new();
+            meta.insert("id".to_string(), owned_value(&id));
+            meta.insert("name".to_string(), owned_value(&name));
+            meta.insert("description".to_string(), owned_value(format!("{} in {folder}", marks.join(", "))));
+            meta.insert("gicon".to_string(), owned_value(icon_for(&self.core.op_types_of(&id))));
+            metas.push(meta);
+        }
+        Ok(metas)
+    }
+
+    /// Only files in the archive are opened
+    async fn activate_result(&self, identifier: String, _terms: Vec<String>, _timestamp: u32) -> fdo::Result<()> {
+        self.core.op_open(identifier, false).await.map_err(failed)
+    }
+
+    /// There is no application to show the search in
+    async fn launch_search(&self, _terms: Vec<String>, _timestamp: u32) {}
+
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use zbus::connection::Builder;
+    use zbus::{Connection, Guid};
+    use crate::access::AccessPolicy;
+    use crate::message::add_record_msg;
+    use crate::ops::tests::test_server;
+    use crate::parse::parse_modification_msg;
+
+    const PATH: &str = "/org/memobook/memoserv1";
+    const IFACE: &str = "org.gnome.Shell.SearchProvider2";
+
+    async fn call<A, R>(client: &Connection, method: &str, args: &A) -> zbus::Result<R>
+    where A: zbus::export::serde::Serialize + zbus::zvariant::DynamicType, R: for<'d> zbus::zvariant::DynamicDeserialize<'d>
+    {
+        client.call_method(None::<&str>, PATH, Some(IFACE), method, args).await?.body().deserialize::<R>()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_search_provider() {
+        let (core, dir) = test_server("provider", AccessPolicy::default());
+        let a = dir.join("a.txt").to_str().unwrap().to_string();
+        let b = dir.join("b.md").to_str().unwrap().to_string();
+        for (file, marks) in [(&a, vec!["Doomlike", "rust"]), (&b, vec!["doom", "notes"])] {
+            let msg = add_record_msg(std::slice::from_ref(file), &marks.iter().map(|m| m.to_string()).collect::<Vec<String>>(), &[]);
+            core.op_modify(parse_modification_msg(msg.iter().map(|s| s.as_str()).collect()).unwrap()).await.unwrap();
+        }
+
+        let (p0, p1) = UnixStream::pair().unwrap();
+        let server = Builder::async_io_unix_stream(p0).server(Guid::generate()).unwrap().p2p()
+            .serve_at(PATH, SearchProvider::new(core.clone())).unwrap()
+            .build();
+        let (server, client) = tokio::join!(server, Builder::async_io_unix_stream(p1).p2p().build());
+        let (_server, client) = (server.unwrap(), client.unwrap());
+
+        let terms = |t: &[&str]| t.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        let mut found: Vec<String> = call(&client, "GetInitialResultSet", &(terms(&["doo"]),)).await.unwrap();
+        found.sort();
+        assert_eq!(found, vec![a.clone(), b.clone()]);
+        let found: Vec<String> = call(&client, "GetSubsearchResultSet", &(vec![b.clone(), a.clone()], terms(&["DOOM", "ru"]))).await.unwrap();
+        assert_eq!(found, vec![a.clone()]);
+        let found: Vec<String> = call(&client, "GetInitialResultSet", &(terms(&["doom", "quake"]),)).await.unwrap();
+        assert!(found.is_empty());
+
+        let metas: Vec<HashMap<String, OwnedValue>> = call(&client, "GetResultMetas", &(vec![a.clone(), "/not/kept".to_string()],)).await.unwrap();
+        assert_eq!(metas.len(), 1);
+        let text = |key: &str| metas[0][key].downcast_ref::<String>().unwrap();
+        assert_eq!((text("id"), text("name")), (a.clone(), "a.txt".to_string()));
+        assert_eq!(text("description"), format!("Doomlike, rust in {}", dir.to_str().unwrap()));
+
+        assert_eq!(metas[0]["gicon"].downcast_ref::<String>().unwrap(), "text-x-generic");
+        let refused: zbus::Result<()> = call(&client, "ActivateResult", &("/etc/passwd", terms(&["doom"]), 0u32)).await;
+        assert!(refused.is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+}
@@ -0,0 +1,49 @@
+//  searchsaver.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+use memobook::query::Query;
+use memobook::mbfilter::MBFilter;
+
+
+// For saved search management in the server interface
+
+pub enum SearchSaver {
+    Create(String, Query<MBFilter>),
+    Update(String, Query<MBFilter>),
+    Delete(String),
+    Show(String),
+    Run(String),
+    List
+}
+
+
+impl SearchSaver {
+
+    /// Whether the request changes the saved searches
+    pub fn writes(&self) -> bool {
+        matches!(self, SearchSaver::Create(..) | SearchSaver::Update(..) | SearchSaver::Delete(_))
+    }
+
+}
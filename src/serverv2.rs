@@ -0,0 +1,634 @@
+//  serverv2.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+
+
+use std::collections::HashMap;
+use zbus::{fdo, interface, message::Header, object_server::{ResponseDispatchNotifier, SignalEmitter}, Connection};
+use zbus::zvariant::{OwnedValue, Value};
+use memobook::backer::{Backer, BuNumber, TransBackStruct};
+use memobook::backerparserjson::BackerParserJSON;
+use memobook::filtercontainer::FilterContainer;
+use memobook::logic::Logic;
+use memobook::mberror::MBError;
+use memobook::mbfilter::MBFilter;
+use memobook::modifiers::{Modifier, ModifyTargetRemove};
+use memobook::query::Query;
+use memobook::queryer::Queryer;
+use memobook::transportstruct::TransPortStruct;
+use crate::{MemoBookServer, failed, owned_value};
+use crate::parse::*;
+use crate::ops::SavedReply;
+use crate::manager::Manager;
+use crate::configmodifier::ConfigModifier;
+use crate::searchsaver::SearchSaver;
+use crate::jobs::JobWork;
+use crate::access::Access;
+
+
+// The typed interface, org.memobook.memoserv2: proper D-Bus signatures in and out, and
+// failures as error replies. It shares state and operations with memoserv1.
+
+/// A search filter on the wire: (field, logic, terms), e.g. ("mark", "and", ["rust", "dbus"])
+pub type WireFilter = (String, String, Vec<String>);
+
+/// Search options on the wire (a{sv}): equality (s), grep (b), grepcase (b), greplink (b)
+pub type WireOptions = HashMap<String, OwnedValue>;
+
+
+fn invalid(e: MBError) -> fdo::Error {
+    fdo::Error::InvalidArgs(e.to_string())
+}
+
+
+fn option_bool(options: &WireOptions, key: &str) -> fdo::Result<bool> {
+    match options.get(key) {
+        Some(v) => v.downcast_ref::<bool>()
+            .map_err(|_| fdo::Error::InvalidArgs(format!("option [{key}] must be a boolean"))),
+        None => Ok(false)
+    }
+}
+
+
+fn option_string(options: &WireOptions, key: &str) -> fdo::Result<Option<String>> {
+    match options.get(key) {
+        Some(v) => v.downcast_ref::<&str>()
+            .map(|s| Some(s.to_string()))
+            .map_err(|_| fdo::Error::InvalidArgs(format!("option [{key}] must be a string"))),
+        None => Ok(None)
+    }
+}
+
+
+fn option_number(options: &WireOptions, key: &str) -> fdo::Result<Option<BuNumber>> {
+    let Some(v) = options.get(key) else {
+        return Ok(None);
+    };
+    let number: u64 = if let Ok(x) = v.downcast_ref::<u32>() {
+        x as u64
+    } else if let Ok(x) = v.downcast_ref::<u16>() {
+        x as u64
+    } else if let Ok(x) = v.downcast_ref::<u64>() {
+        x
+    } else {
+        return Err(fdo::Error::InvalidArgs(format!("option [{key}] must be an unsigned integer")));
+    };
+    BuNumber::try_from(number).map(Some)
+        .map_err(|_| fdo::Error::InvalidArgs(format!("option [{key}] is out of range")))
+}
+
+
+fn check_options(options: &WireOptions, known: &[&str]) -> fdo::Result<()> {
+    match options.keys().find(|k| !known.contains(&k.as_str())) {
+        Some(k) => Err(fdo::Error::InvalidArgs(format!("unknown option [{k}]"))),
+        None => Ok(())
+    }
+}
+
+
+/// Filters and options to a query, through the same builders the string interface uses
+pub fn wire_to_query(filters: Vec<WireFilter>, options: &WireOptions) -> fdo::Result<Query<MBFilter>> {
+    check_options(options, &["equality", "grep", "grepcase", "greplink"])?;
+    let mut filtervec: Vec<MBFilter> = Vec::new();
+    for (field, logic, terms) in filters {
+        filtervec.push(build_filter(&field, &logic, terms).map_err(invalid)?);
+    }
+    let equality: String = option_string(options, "equality")?.unwrap_or("file".to_string());
+    build_query(filtervec,
+        &equality,
+        option_bool(options, "grep")?,
+        option_bool(options, "grepcase")?,
+        option_bool(options, "greplink")?).map_err(invalid)
+}
+
+
+pub fn query_to_wire(query: &Query<MBFilter>) -> (Vec<WireFilter>, WireOptions) {
+    let filters: Vec<WireFilter> = query.iter_filters()
+        .map(|f| (
+            f.filtertype().to_string(),
+            match f.logic() {
+                Logic::AND => "and".to_string(),
+                Logic::OR => "or".to_string()
+            },
+            f.iter().map(|t| t.to_string()).collect()))
+        .collect();
+    let mut options: WireOptions = HashMap::new();
+    options.insert("equality".to_string(), owned_value(query.equality()));
+    options.insert("grep".to_string(), owned_value(query.grep()));
+    options.insert("grepcase".to_string(), owned_value(query.grepcase()));
+    options.insert("greplink".to_string(), owned_value(query.greplink()));
+    (filters, options)
+}
+
+
+fn to_wire_counts(counts: Vec<(String, usize)>) -> Vec<(String, u64)> {
+    counts.into_iter().map(|(s, n)| (s, n as u64)).collect()
+}
+
+
+/// Type requests go through the same checks as version 1's
+fn type_suffixes(form: fn(String, Vec<String>) -> ConfigModifier, name: &str, suffixes: &[String]) -> fdo::Result<ConfigModifier> {
+    build_type_suffixes(form, name, suffixes).map_err(invalid)
+}
+
+
+
+pub struct MemoBookServer2<B>
+where B: Backer+BackerParserJSON + std::marker::Send + 'static
+{
+    core: MemoBookServer<B>
+}
+
+
+impl<B> MemoBookServer2<B>
+where B: Backer+BackerParserJSON + std::marker::Send + 'static
+{
+    pub fn new(core: MemoBookServer<B>) -> MemoBookServer2<B> {
+        MemoBookServer2 { core }
+    }
+}
+
+
+unsafe impl<B: Backer+BackerParserJSON + std::marker::Send> Send for MemoBookServer2<B> {}
+
+
+
+#[interface(name = "org.memobook.memoserv2")]
+impl<B> MemoBookServer2<B>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+
+    /// Every distinct file, mark or type
+    async fn toc(&self, field: &str) -> fdo::Result<Vec<String>> {
+        let query = parse_toc_msg(field).map_err(invalid)?;
+        self.core.op_search(query, "Error in table of contents").await.map_err(failed)
+    }
+
+
+    async fn search(&self, filters: Vec<WireFilter>, options: WireOptions) -> fdo::Result<Vec<String>> {
+        let query = wire_to_query(filters, &options)?;
+        self.core.op_search(query, "Error in search").await.map_err(failed)
+    }
+
+
+    /// (file, score) pairs, best first
+    async fn search_scored(&self, filters: Vec<WireFilter>, options: WireOptions) -> fdo::Result<Vec<(String, u64)>> {
+        let query = wire_to_query(filters, &options)?;
+        self.core.op_search_scored(query).await.map(to_wire_counts).map_err(failed)
+    }
+
+
+    /// (mark, count) pairs of marks sharing files with the given ones
+    async fn related(&self, marks: Vec<String>, limit: u32) -> fdo::Result<Vec<(String, u64)>> {
+        if marks.is_empty() {
+            return Err(fdo::Error::InvalidArgs("no marks given".to_string()));
+        }
+        self.core.op_related(marks, limit as usize).await.map(to_wire_counts).map_err(failed)
+    }
+
+
+    /// Keys: records, files, marks, types, dbsize (t); top_marks, roots (a(st)); untagged_types (as)
+    async fn stats(&self, top: u32) -> fdo::Result<HashMap<String, OwnedValue>> {
+        let st = self.core.op_stats(top as usize).await.map_err(failed)?;
+        let mut reply: HashMap<String, OwnedValue> = HashMap::new();
+        let mut put = |key: &str, value: Value<'_>| {
+            if let Ok(v) = OwnedValue::try_from(value) {
+                reply.insert(key.to_string(), v);
+            }
+        };
+        put("records", Value::from(st.records as u64));
+        put("files", Value::from(st.files as u64));
+        put("marks", Value::from(st.marks as u64));
+        put("types", Value::from(st.types as u64));
+        put("dbsize", Value::from(st.dbsize));
+        put("top_marks", Value::from(to_wire_counts(st.top_marks)));
+        put("untagged_types", Value::from(st.untagged_types));
+        put("roots", Value::from(to_wire_counts(st.roots)));
+        Ok(reply)
+    }
+
+
+    /// (mark, score, already a mark) triples
+    async fn suggest(&self, file: &str, limit: u32) -> fdo::Result<Vec<(String, f64, bool)>> {
+        self.core.op_suggest(file.to_string(), limit as usize).await
+            .map(|sug| sug.into_iter().map(|s| (s.mark, s.score, s.existing)).collect())
+            .map_err(failed)
+    }
+
+
+    /// Types may be empty, in which case they are inferred per file
+    async fn add_records(&self, files: Vec<String>, marks: Vec<String>, types: Vec<String>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Write, &header, conn).await?;
+        let cmd = build_add_record(&files, &marks, &types).map_err(invalid)?;
+        let reply = self.core.op_modify(cmd).await.map_err(failed);
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    /// Replace values of a field (file, mark or type) throughout, given (old, new) pairs
+    async fn replace_field(&self, field: &str, pairs: Vec<(String, String)>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Write, &header, conn).await?;
+        let cmd = build_field_replace(field, pairs.iter().map(|(a, b)| (a.as_str(), b.as_str())).collect())
+            .map_err(invalid)?;
+        let reply = self.core.op_modify(cmd).await.map_err(failed);
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn update_marks(&self, file: &str, types: Vec<String>, remove: Vec<String>, add: Vec<String>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Write, &header, conn).await?;
+        let cmd = build_mark_update(file, &types, &remove, &add).map_err(invalid)?;
+        let reply = self.core.op_modify(cmd).await.map_err(failed);
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn update_types(&self, file: &str, marks: Vec<String>, remove: Vec<String>, add: Vec<String>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Write, &header, conn).await?;
+        let cmd = build_type_update(file, &marks, &remove, &add).map_err(invalid)?;
+        let reply = self.core.op_modify(cmd).await.map_err(failed);
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    /// Remove every record whose field (file, mark or type) equals the value
+    async fn remove_records(&self, field: &str, value: &str,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Write, &header, conn).await?;
+        let reply = self.core.op_modify(Modifier::TargetRemove(ModifyTargetRemove::new(field, value))).await.map_err(failed);
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    /// Modifications, each as the memoserv1 modify message, applied in one transaction: all are
+    /// kept, or none. Replies whether the batch was committed, and a message per modification,
+    /// empty for success.
+    async fn modify_batch(&self, items: Vec<Vec<String>>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<(bool, Vec<String>)> {
+        self.authorize(Access::Write, &header, conn).await?;
+        if items.is_empty() {
+            return Err(fdo::Error::InvalidArgs("empty batch".to_string()));
+        }
+        let mut cmds: Vec<Modifier> = Vec::new();
+        for (n, item) in items.iter().enumerate() {
+            if item.len() < 2 {
+                return Err(fdo::Error::InvalidArgs(format!("batch item {} is too short", n + 1)));
+            }
+            let cmd = parse_modification_msg(item.iter().map(|s| s.as_str()).collect())
+                .map_err(|e| fdo::Error::InvalidArgs(format!("batch item {}: {e}", n + 1)))?;
+            cmds.push(cmd);
+        }
+        let reply = self.core.op_modify_batch(cmds).await.map(|r| (r.committed, r.results)).map_err(failed);
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    /// Type name to suffixes
+    async fn types(&self) -> HashMap<String, Vec<String>> {
+        self.core.op_types()
+    }
+
+
+    async fn add_type(&self, name: String, suffixes: Vec<String>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Configure, &header, conn).await?;
+        let reply = self.configure(type_suffixes(ConfigModifier::AddType, &name, &suffixes)?).await;
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn remove_type(&self, name: String,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Configure, &header, conn).await?;
+        let reply = self.configure(ConfigModifier::RemoveType(name)).await;
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn add_suffixes(&self, name: String, suffixes: Vec<String>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Configure, &header, conn).await?;
+        let reply = self.configure(type_suffixes(ConfigModifier::AddSuffixes, &name, &suffixes)?).await;
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn remove_suffixes(&self, name: String, suffixes: Vec<String>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Configure, &header, conn).await?;
+        let reply = self.configure(type_suffixes(ConfigModifier::RemoveSuffixes, &name, &suffixes)?).await;
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn rename_type(&self, old: String, new: String, rewrite_records: bool,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Configure, &header, conn).await?;
+        let reply = self.configure(ConfigModifier::RenameType(old, new, rewrite_records)).await;
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn set_source(&self, source: String,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Configure, &header, conn).await?;
+        let reply = self.configure(ConfigModifier::SetSource(source)).await;
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    /// (include, exclude) directories
+    async fn repositories(&self) -> (Vec<String>, Vec<String>) {
+        let memocfg = self.core.cfg.lock().unwrap();
+        let scan = &memocfg.mb().scan;
+        let paths = |v: &Vec<std::path::PathBuf>| v.iter().map(|p| p.to_string_lossy().to_string()).collect();
+        (paths(scan.get_include()), paths(scan.get_exclude()))
+    }
+
+
+    /// An empty trunk means none
+    async fn set_repositories(&self, trunk: &str, include: Vec<String>, exclude: Vec<String>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Configure, &header, conn).await?;
+        let trunk = if trunk.is_empty() { None } else { Some(trunk) };
+        let reply = self.configure(ConfigModifier::SetRepo(build_repo(trunk, include, exclude))).await;
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn modify_repositories(&self,
+        remove_include: Vec<String>,
+        remove_exclude: Vec<String>,
+        add_include: Vec<String>,
+        add_exclude: Vec<String>,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()>
+    {
+        self.authorize(Access::Configure, &header, conn).await?;
+        let rems = build_repo(None, remove_include, remove_exclude);
+        let adds = build_repo(None, add_include, add_exclude);
+        let reply = self.configure(ConfigModifier::ModifyRepo((rems, adds))).await;
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    /// Starts a background job; returns its id
+    async fn import(&self, source: String, log: String, follow_links: bool,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<u32> {
+        self.authorize(Access::Configure, &header, conn).await?;
+        Ok(self.core.op_start_job(JobWork::Import(TransPortStruct { target: source, log, links: follow_links })))
+    }
+
+
+    /// Starts a background job; returns its id
+    async fn export(&self, target: String,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<u32> {
+        self.authorize(Access::Control, &header, conn).await?;
+        Ok(self.core.op_start_job(JobWork::Export(TransPortStruct { target: "".to_string(), log: target, links: false })))
+    }
+
+
+    /// A search run as a background job; returns its id, results come with JobStatus
+    async fn search_job(&self, filters: Vec<WireFilter>, options: WireOptions) -> fdo::Result<u32> {
+        let query = wire_to_query(filters, &options)?;
+        Ok(self.core.op_start_job(JobWork::Grep(query)))
+    }
+
+
+    async fn cancel_job(&self, id: u32, #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Jobs, &header, conn).await?;
+        self.core.op_cancel_job(id).map_err(failed)
+    }
+
+
+    /// (kind, state, scanned, hashed, matched, report or error, results)
+    async fn job_status(&self, id: u32) -> fdo::Result<(String, String, u64, u64, u64, String, Vec<String>)> {
+        let status = self.core.op_job_status(id).map_err(failed)?;
+        Ok((status.kind.to_string(), status.state.to_string(),
+            status.scanned as u64, status.hashed as u64, status.matched as u64,
+            status.message, status.results))
+    }
+
+
+    /// The backup settings and copies, as json
+    async fn backup_info(&self) -> String {
+        let memocfg = self.core.cfg.lock().unwrap();
+        memocfg.assemble_backup_info()
+    }
+
+
+    /// Options: load, base, loc (s); mult, freq (u); clear, force (b)
+    async fn modify_backup(&self, options: WireOptions,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Configure, &header, conn).await?;
+        check_options(&options, &["load", "base", "loc", "mult", "freq", "clear", "force"])?;
+        let bup = TransBackStruct {
+            remove: option_bool(&options, "clear")?,
+            force: option_bool(&options, "force")?,
+            mult: option_number(&options, "mult")?,
+            freq: option_number(&options, "freq")?,
+            base: option_string(&options, "base")?,
+            loc: option_string(&options, "loc")?,
+            load: option_string(&options, "load")?
+        };
+        let reply = self.core.op_manage(Manager::Backup(bup)).await.map(|_| ()).map_err(failed);
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn saved_searches(&self) -> fdo::Result<Vec<String>> {
+        match self.core.op_saved(SearchSaver::List).await.map_err(failed)? {
+            SavedReply::Names(names) => Ok(names),
+            _ => Err(fdo::Error::Failed("unexpected saved search reply".to_string()))
+        }
+    }
+
+
+    async fn saved_search(&self, name: String) -> fdo::Result<(Vec<WireFilter>, WireOptions)> {
+        match self.core.op_saved(SearchSaver::Show(name)).await.map_err(failed)? {
+            SavedReply::Query(q) => Ok(query_to_wire(&q)),
+            _ => Err(fdo::Error::Failed("unexpected saved search reply".to_string()))
+        }
+    }
+
+
+    /// With replace false, the name must be new; with replace true, it must exist
+    async fn save_search(&self, name: String, filters: Vec<WireFilter>, options: WireOptions, replace: bool,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Write, &header, conn).await?;
+        let query = wire_to_query(filters, &options)?;
+        let cmd = if replace { SearchSaver::Update(name, query) } else { SearchSaver::Create(name, query) };
+        let reply = self.core.op_saved(cmd).await.map(|_| ()).map_err(failed);
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn delete_search(&self, name: String,
+        #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        self.authorize(Access::Write, &header, conn).await?;
+        let reply = self.core.op_saved(SearchSaver::Delete(name)).await.map(|_| ()).map_err(failed);
+        self.core.emit_changes().await;
+        reply
+    }
+
+
+    async fn run_saved_search(&self, name: String) -> fdo::Result<Vec<String>> {
+        match self.core.op_saved(SearchSaver::Run(name)).await.map_err(failed)? {
+            SavedReply::Results(results) => Ok(results),
+            _ => Err(fdo::Error::Failed("unexpected saved search reply".to_string()))
+        }
+    }
+
+
+    async fn exit(&self, #[zbus(header)] header: Header<'_>, #[zbus(connection)] conn: &Connection) -> fdo::Result<ResponseDispatchNotifier<()>> {
+        self.authorize(Access::Control, &header, conn).await?;
+        let (reply, sent) = ResponseDispatchNotifier::new(());
+        self.core.exit_after(sent);
+        Ok(reply)
+    }
+
+
+    /// Files whose records were added, changed or removed; empty when the whole set may have changed
+    #[zbus(signal)]
+    pub(crate) async fn records_changed(emitter: &SignalEmitter<'_>, files: &[String]) -> zbus::Result<()>;
+
+
+    /// Section is one of source, repositories, types, backup or saved
+    #[zbus(signal)]
+    pub(crate) async fn configuration_changed(emitter: &SignalEmitter<'_>, section: &str) -> zbus::Result<()>;
+
+
+    /// Action is created or loaded
+    #[zbus(signal)]
+    pub(crate) async fn backup_changed(emitter: &SignalEmitter<'_>, action: &str) -> zbus::Result<()>;
+
+
+    /// Files scanned, hashed and matched so far by a running job
+    #[zbus(signal)]
+    pub(crate) async fn job_progress(emitter: &SignalEmitter<'_>, id: u32, scanned: u64, hashed: u64, matched: u64) -> zbus::Result<()>;
+
+
+    /// State is done, failed or cancelled; message is the report or error
+    #[zbus(signal)]
+    pub(crate) async fn job_finished(emitter: &SignalEmitter<'_>, id: u32, state: &str, message: &str) -> zbus::Result<()>;
+
+
+    #[zbus(signal)]
+    pub(crate) async fn exited(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+}
+
+
+impl<B> MemoBookServer2<B>
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+
+    async fn authorize(&self, access: Access, header: &Header<'_>, conn: &Connection) -> fdo::Result<()> {
+        self.core.access.authorize(access, header, conn).await
+            .map_err(|e| fdo::Error::AccessDenied(e.to_string()))
+    }
+
+
+    async fn configure(&self, cfg: ConfigModifier) -> fdo::Result<()> {
+        self.core.op_manage(Manager::Configure(cfg)).await.map(|_| ()).map_err(failed)
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn options(pairs: &[(&str, Value<'_>)]) -> WireOptions {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.try_to_owned().unwrap())).collect()
+    }
+
+    #[test]
+    fn test_wire_query_round_trip() {
+        let filters: Vec<WireFilter> = vec![("mark".to_string(), "and".to_string(), vec!["rust".to_string(), "dbus".to_string()])];
+        let query = wire_to_query(filters.clone(), &options(&[("equality", Value::from("type")), ("grep", Value::from(true))])).unwrap();
+        assert_eq!(query.equality(), "type");
+        assert!(query.grep());
+        assert!(!query.grepcase());
+        let (wirefilters, wireoptions) = query_to_wire(&query);
+        assert_eq!(wirefilters, filters);
+        assert!(wireoptions.get("grep").unwrap().downcast_ref::<bool>().unwrap());
+        assert_eq!(wireoptions.get("equality").unwrap().downcast_ref::<&str>().unwrap(), "type");
+    }
+
+    #[test]
+    fn test_wire_query_errors() {
+        let filter = |field: &str, logic: &str| vec![(field.to_string(), logic.to_string(), vec!["x".to_string()])];
+        assert!(wire_to_query(filter("mark", "xor"), &HashMap::new()).is_err());
+        assert!(wire_to_query(filter("color", "or"), &HashMap::new()).is_err());
+        assert!(wire_to_query(filter("mark", "or"), &options(&[("equality", Value::from("color"))])).is_err());
+        assert!(wire_to_query(filter("mark", "or"), &options(&[("grep", Value::from("yes"))])).is_err());
+        assert!(wire_to_query(filter("mark", "or"), &options(&[("sort", Value::from(true))])).is_err());
+        assert!(wire_to_query(filter("mark", "or"), &HashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn test_type_suffixes() {
+        match type_suffixes(ConfigModifier::AddType, "Image", &[".PNG".to_string(), "jpg".to_string()]).unwrap() {
+            ConfigModifier::AddType(name, suffixes) => {
+                assert_eq!(name, "Image");
+                assert_eq!(suffixes, vec!["PNG".to_string(), "jpg".to_string()]);
+            },
+            _ => panic!("wrong configuration request built")
+        }
+        assert!(matches!(type_suffixes(ConfigModifier::AddSuffixes, "Image", &["".to_string()]), Err(fdo::Error::InvalidArgs(_))));
+        assert!(matches!(type_suffixes(ConfigModifier::RemoveSuffixes, "Image", &[".".to_string()]), Err(fdo::Error::InvalidArgs(_))));
+        assert!(matches!(type_suffixes(ConfigModifier::AddType, "", &["png".to_string()]), Err(fdo::Error::InvalidArgs(_))));
+    }
+
+    #[test]
+    fn test_option_number() {
+        let opts = options(&[("mult", Value::from(4u32)), ("freq", Value::from(100000u32))]);
+        assert_eq!(option_number(&opts, "mult").unwrap(), Some(4));
+        assert!(option_number(&opts, "freq").is_err());
+        assert_eq!(option_number(&opts, "base").unwrap(), None);
+    }
+
+}
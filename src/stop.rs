@@ -0,0 +1,241 @@
+//  stop.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use zbus::{Connection, MessageStream};
+use zbus::export::futures_core::Stream;
+use zbus::message::Type;
+use memobook::backer::Backer;
+use memobook::backerparserjson::BackerParserJSON;
+use crate::MemoBookServer;
+
+
+// What main waits on once everything is served. The exit methods only request the stop, after
+// their reply has gone out; SIGTERM (as sent by systemd) and Ctrl-C stop it directly. Either way
+// main then runs MemoBookServer::shutdown, so that running jobs are cancelled, clients hear
+// "exited" and the configuration is written out before the process ends. With an idle timeout it also stops once no request
+// has come in for that long and no job is running; D-Bus activation starts it again on demand.
+
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    Requested,
+    Interrupted,
+    Terminated,
+    Idle
+}
+
+
+impl fmt::Display for Stop {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Requested => write!(f, "exit requested"),
+            Stop::Interrupted => write!(f, "interrupted"),
+            Stop::Terminated => write!(f, "terminated"),
+            Stop::Idle => write!(f, "idle")
+        }
+    }
+
+}
+
+
+/// The stopping signals, caught from the moment this is made
+pub struct Stopper {
+    #[cfg(test)]
+    trigger: UnboundedSender<Stop>,
+    stops: UnboundedReceiver<Stop>,
+    idle: Option<Duration>
+}
+
+
+impl Stopper {
+
+    pub fn new(idle: Option<Duration>) -> io::Result<Stopper> {
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        let (forward, stops): (UnboundedSender<Stop>, UnboundedReceiver<Stop>) = unbounded_channel();
+        #[cfg(test)]
+        let trigger = forward.clone();
+        tokio::spawn(async move {
+            loop {
+                let stop = tokio::select! {
+                    Some(_) = interrupt.recv() => Stop::Interrupted,
+                    Some(_) = terminate.recv() => Stop::Terminated,
+                    else => break
+                };
+                if forward.send(stop).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Stopper {
+            #[cfg(test)]
+            trigger,
+            stops,
+            idle
+        })
+    }
+
+
+    /// Stops the wait as the signal would
+    #[cfg(test)]
+    pub(crate) fn trigger(&self, stop: Stop) {
+        let _ = self.trigger.send(stop);
+    }
+
+
+    /// Waits for an exit request, a signal or the idle timeout, whichever comes first
+    pub async fn wait<B>(&mut self, core: &MemoBookServer<B>) -> Stop
+    where B: Backer+BackerParserJSON + std::marker::Send+'static
+    {
+        loop {
+            // a request since the last look puts the timeout off again
+            let left = self.idle.map(|idle| idle.saturating_sub(core.idle_for()));
+            tokio::select! {
+                biased;
+                _ = core.stop.notified() => return Stop::Requested,
+                Some(stop) = self.stops.recv() => return stop,
+                _ = sleep_for(left) => {
+                    if left.is_some_and(|l| l.is_zero()) {
+                        return Stop::Idle;
+                    }
+                }
+            }
+        }
+    }
+
+}
+
+
+async fn sleep_for(left: Option<Duration>) {
+    match left {
+        Some(l) => tokio::time::sleep(l).await,
+        None => std::future::pending().await
+    }
+}
+
+
+/// Counts each method call arriving on the connection as a request
+pub async fn watch_bus<B>(core: MemoBookServer<B>, conn: Connection)
+where B: Backer+BackerParserJSON + std::marker::Send+'static
+{
+    let mut stream = MessageStream::from(&conn);
+    while let Some(msg) = std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+        if msg.is_ok_and(|m| m.message_type() == Type::MethodCall) {
+            core.touch();
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use crate::access::AccessPolicy;
+    use crate::jobs::{JobFailure, JobState};
+    use crate::ops::tests::test_server;
+
+    // Every stopper hears a signal sent to the process, so the tests making them take turns
+    static SIGNALS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_requested() {
+        let _turn = SIGNALS.lock().await;
+        let (core, dir) = test_server("stop_requested", AccessPolicy::default());
+        let mut stopper = Stopper::new(None).unwrap();
+        // a request made before anyone waits is kept
+        core.request_exit();
+        let stop = tokio::time::timeout(Duration::from_secs(5), stopper.wait(&core)).await.unwrap();
+        assert_eq!(stop, Stop::Requested);
+        assert!(!core.exitflag.load(Ordering::SeqCst));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_idle() {
+        let _turn = SIGNALS.lock().await;
+        let (core, dir) = test_server("stop_idle", AccessPolicy::default());
+        let mut stopper = Stopper::new(Some(Duration::from_millis(300))).unwrap();
+        let started = std::time::Instant::now();
+        let toucher = core.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            toucher.touch();
+        });
+        let stop = tokio::time::timeout(Duration::from_secs(5), stopper.wait(&core)).await.unwrap();
+        assert_eq!(stop, Stop::Idle);
+        assert!(started.elapsed() >= Duration::from_millis(500));
+
+        // a running job keeps it up
+        let (id, _) = core.jobs.lock().unwrap().start("grep");
+        assert!(tokio::time::timeout(Duration::from_millis(600), stopper.wait(&core)).await.is_err());
+        core.jobs.lock().unwrap().finish(id, Ok(("".to_string(), vec![])));
+        let stop = tokio::time::timeout(Duration::from_secs(5), stopper.wait(&core)).await.unwrap();
+        assert_eq!(stop, Stop::Idle);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_terminated() {
+        let _turn = SIGNALS.lock().await;
+        let (core, dir) = test_server("stop_terminated", AccessPolicy::default());
+        let mut stopper = Stopper::new(Some(Duration::from_secs(60))).unwrap();
+        assert_eq!(unsafe { libc::kill(libc::getpid(), libc::SIGTERM) }, 0);
+        let stop = tokio::time::timeout(Duration::from_secs(5), stopper.wait(&core)).await.unwrap();
+        assert_eq!(stop, Stop::Terminated);
+
+        // the shutdown cancels a running job and waits for it
+        let (id, progress) = core.jobs.lock().unwrap().start("grep");
+        let worker = core.clone();
+        tokio::spawn(async move {
+            while progress.check().is_ok() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            worker.jobs.lock().unwrap().finish(id, Err(JobFailure::Cancelled));
+        });
+        core.shutdown().await;
+        assert_eq!(core.jobs.lock().unwrap().status(id).unwrap().state, JobState::Cancelled);
+        assert!(core.exitflag.load(Ordering::SeqCst));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_triggered() {
+        let _turn = SIGNALS.lock().await;
+        let (core, dir) = test_server("stop_triggered", AccessPolicy::default());
+        let mut stopper = Stopper::new(None).unwrap();
+        stopper.trigger(Stop::Interrupted);
+        let stop = tokio::time::timeout(Duration::from_secs(5), stopper.wait(&core)).await.unwrap();
+        assert_eq!(stop, Stop::Interrupted);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+}
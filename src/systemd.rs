@@ -0,0 +1,317 @@
+//  systemd.rs
+//
+//  Author: Miguel Abele (eightbitastronomy@protonmail.com)
+//  Copyrighted by Miguel Abele (eightbitastronomy), 2025.
+//
+//  License information:
+//
+//  This file is a part of MemoServ.
+//
+//  MemoServ is free software; you can redistribute it and/or
+//  modify it under the terms of the GNU General Public License
+//  as published by the Free Software Foundation; either version 3
+//  of the License, or (at your option) any later version.
+//
+//  MemoServ is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU General Public License for more details.
+//
+//  You should have received a copy of the GNU General Public License
+//  along with this program; if not, write to the Free Software
+//  Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301, USA.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::{Path, PathBuf};
+use crate::busconfig::{BusConfig, BusType};
+use crate::options::Options;
+
+
+// Running under systemd. In a Type=notify unit the service reports READY=1 once it is served and
+// STOPPING=1 on its way out, over the datagram socket named in NOTIFY_SOCKET. `memoserv units`
+// writes that unit, and for D-Bus the activation file that lets the bus start it on the first
+// call, e.g. after an idle timeout: user files for the session bus, system ones for the system bus.
+// A system bus service runs as the user given with --user, and gets a policy letting that user
+// own the name and anyone call it; the access section still decides what each caller may do.
+
+pub const UNITS_USAGE: &str = "usage: memoserv units [--print] [--user NAME] [SERVICE OPTIONS] CONF.JSON\n  --user is needed with --system";
+
+
+/// Tells systemd how the service stands; nothing is sent outside a notify unit
+pub fn notify(state: &str) -> io::Result<()> {
+    match env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => notify_to(&socket, state),
+        None => Ok(())
+    }
+}
+
+
+fn notify_to(socket: &OsStr, state: &str) -> io::Result<()> {
+    let sender = UnixDatagram::unbound()?;
+    let addr = match socket.as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(socket)?
+    };
+    sender.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+
+/// An argument as systemd and the bus daemon split their Exec lines
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        return arg.to_string();
+    }
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+
+/// The unit and activation files for one instance of the service
+#[derive(Debug)]
+pub struct Units {
+    pub name: String,           // of the unit, after the bus name
+    pub exec: String,           // the command line both files run
+    pub bus: Option<BusConfig>, // none when D-Bus is off, so nothing to activate
+    pub user: Option<String>,   // to run a system service as
+    pub print: bool             // show the files rather than writing them
+}
+
+
+/// Where the files go: the unit's directory, the activation file's, and the bus policy's
+#[derive(Debug, PartialEq)]
+pub struct UnitDirs {
+    pub unit: PathBuf,
+    pub bus: PathBuf,
+    pub policy: Option<PathBuf> // system bus only
+}
+
+
+impl Units {
+
+    /// The arguments after `units`: --print and --user, then those the service itself is to run with
+    pub fn from_args(exe: &Path, args: &[String]) -> Result<Units, String> {
+        let print = args.iter().any(|a| a == "--print");
+        let mut args: Vec<String> = args.iter().filter(|a| *a != "--print").cloned().collect();
+        let user = match args.iter().position(|a| a == "--user") {
+            Some(i) => {
+                if i + 1 >= args.len() {
+                    return Err("--user needs a user name".to_string());
+                }
+                let user = args.remove(i + 1);
+                args.remove(i);
+                if user.is_empty() || !user.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)) {
+                    return Err(format!("bad user name {user}"));
+                }
+                Some(user)
+            },
+            None => None
+        };
+        let options = Options::from_args(&args)?;
+        match (options.bus.as_ref().map(|bc| bc.bus == BusType::System), &user) {
+            (Some(true), None) => return Err("a system bus service needs --user NAME to run as".to_string()),
+            (Some(false) | None, Some(_)) => return Err("--user is only for a system bus service".to_string()),
+            _ => {}
+        }
+        let confaddr = fs::canonicalize(&options.confaddr)
+            .map_err(|e| format!("Unable to find {}: {e}", options.confaddr))?;
+        let confaddr = confaddr.to_str().ok_or("configuration path is not utf-8")?;
+        let exe = exe.to_str().ok_or("program path is not utf-8")?;
+        let mut exec: Vec<String> = vec![quote(exe)];
+        exec.extend(args.iter().map(|a| if *a == options.confaddr { quote(confaddr) } else { quote(a) }));
+        let name = match &options.bus {
+            Some(bc) => format!("{}.service", bc.name),
+            None => "memoserv.service".to_string()
+        };
+        Ok(Units { name, exec: exec.join(" "), bus: options.bus, user, print })
+    }
+
+
+    /// Installed for the whole machine rather than the user
+    pub fn system(&self) -> bool {
+        self.bus.as_ref().is_some_and(|bc| bc.bus == BusType::System)
+    }
+
+
+    /// A User= line for a system service
+    fn user_line(&self) -> String {
+        self.user.as_ref().map(|u| format!("User={u}\n")).unwrap_or_default()
+    }
+
+
+    pub fn unit_file(&self) -> String {
+        let (description, wanted) = match self.system() {
+            true => ("MemoServ bookmark service", "multi-user.target"),
+            false => ("MemoServ bookmark service for the user", "default.target")
+        };
+        format!("[Unit]\nDescription={description}\n\n[Service]\nType=notify\n{}ExecStart={}\nRestart=on-failure\n\n[Install]\nWantedBy={wanted}\n",
+            self.user_line(), self.exec)
+    }
+
+
+    /// None without D-Bus
+    pub fn activation_file(&self) -> Option<String> {
+        let bc = self.bus.as_ref()?;
+        Some(format!("[D-BUS Service]\nName={}\nExec={}\n{}SystemdService={}\n", bc.name, self.exec, self.user_line(), self.name))
+    }
+
+
+    /// The system bus denies owning and calling a name unless a policy allows it; none on the
+    /// session bus
+    pub fn policy_file(&self) -> Option<String> {
+        let (bc, user) = (self.bus.as_ref()?, self.user.as_ref()?);
+        Some(format!(concat!(
+            "<!DOCTYPE busconfig PUBLIC \"-//freedesktop//DTD D-BUS Bus Configuration 1.0//EN\"\n",
+            " \"http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd\">\n",
+            "<busconfig>\n",
+            "  <policy user=\"{user}\">\n",
+            "    <allow own=\"{name}\"/>\n",
+            "  </policy>\n",
+            "  <policy context=\"default\">\n",
+            "    <allow send_destination=\"{name}\"/>\n",
+            "  </policy>\n",
+            "</busconfig>\n"), user = user, name = bc.name))
+    }
+
+
+    /// Where systemd and the bus daemon look
+    pub fn dirs(&self) -> Result<UnitDirs, String> {
+        if self.system() {
+            return Ok(UnitDirs {
+                unit: PathBuf::from("/etc/systemd/system"),
+                bus: PathBuf::from("/usr/share/dbus-1/system-services"),
+                policy: Some(PathBuf::from("/usr/share/dbus-1/system.d"))
+            });
+        }
+        let home = || env::var_os("HOME").map(PathBuf::from).ok_or("HOME is not set".to_string());
+        let config = match env::var_os("XDG_CONFIG_HOME") {
+            Some(c) => PathBuf::from(c),
+            None => home()?.join(".config")
+        };
+        let data = match env::var_os("XDG_DATA_HOME") {
+            Some(d) => PathBuf::from(d),
+            None => home()?.join(".local/share")
+        };
+        Ok(UnitDirs { unit: config.join("systemd/user"), bus: data.join("dbus-1/services"), policy: None })
+    }
+
+
+    /// Writes the files, creating the directories as needed; returns what was written
+    pub fn write(&self, dirs: &UnitDirs) -> io::Result<Vec<PathBuf>> {
+        let mut written: Vec<PathBuf> = vec![];
+        fs::create_dir_all(&dirs.unit)?;
+        let unit = dirs.unit.join(&self.name);
+        fs::write(&unit, self.unit_file())?;
+        written.push(unit);
+        if let Some(activation) = self.activation_file() {
+            fs::create_dir_all(&dirs.bus)?;
+            let file = dirs.bus.join(&self.name);
+            fs::write(&file, activation)?;
+            written.push(file);
+        }
+        if let (Some(policy), Some(policydir), Some(bc)) = (self.policy_file(), &dirs.policy, &self.bus) {
+            fs::create_dir_all(policydir)?;
+            let file = policydir.join(format!("{}.conf", bc.name));
+            fs::write(&file, policy)?;
+            written.push(file);
+        }
+        Ok(written)
+    }
+
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_notify() {
+        let dir = std::env::temp_dir().join(format!("memoserv_notify_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let listener = UnixDatagram::bind(&path).unwrap();
+        notify_to(path.as_os_str(), "READY=1").unwrap();
+        let mut buf = [0u8; 64];
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+
+        let name = format!("memoserv_notify_{}", std::process::id());
+        let listener = UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(name.as_bytes()).unwrap()).unwrap();
+        notify_to(OsStr::new(&format!("@{name}")), "STOPPING=1").unwrap();
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"STOPPING=1");
+        assert!(notify_to(dir.join("none.sock").as_os_str(), "READY=1").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("/usr/bin/memoserv"), "/usr/bin/memoserv");
+        assert_eq!(quote("/home/me/My Notes/conf.json"), "\"/home/me/My Notes/conf.json\"");
+        assert_eq!(quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote(""), "\"\"");
+    }
+
+    #[test]
+    fn test_units() {
+        let dir = std::env::temp_dir().join(format!("memoserv_units_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let conf = dir.join("conf.json");
+        fs::write(&conf, r#"{"dbus": {"name": "org.memobook.house"}}"#).unwrap();
+        let confaddr = fs::canonicalize(&conf).unwrap().to_str().unwrap().to_string();
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        let exe = Path::new("/usr/bin/memoserv");
+
+        let units = Units::from_args(exe, &args(&["--idle", "10", "--print", &confaddr])).unwrap();
+        assert!(units.print);
+        assert_eq!(units.name, "org.memobook.house.service");
+        assert_eq!(units.exec, format!("/usr/bin/memoserv --idle 10 {confaddr}"));
+        assert!(units.unit_file().contains("Type=notify\n"));
+        assert!(units.unit_file().contains(&format!("ExecStart={}\n", units.exec)));
+        assert_eq!(units.activation_file().unwrap(), format!("[D-BUS Service]\nName=org.memobook.house\nExec={}\nSystemdService=org.memobook.house.service\n",
+            units.exec));
+
+        let dirs = UnitDirs { unit: dir.join("user"), bus: dir.join("services"), policy: Some(dir.join("system.d")) };
+        assert!(units.policy_file().is_none());
+        let written = units.write(&dirs).unwrap();
+        assert_eq!(written, vec![dir.join("user/org.memobook.house.service"), dir.join("services/org.memobook.house.service")]);
+        assert_eq!(fs::read_to_string(&written[0]).unwrap(), units.unit_file());
+
+        let units = Units::from_args(exe, &args(&["--no-dbus", "--socket", "/tmp/m.sock", &confaddr])).unwrap();
+        assert!(!units.print);
+        assert_eq!(units.name, "memoserv.service");
+        assert!(units.activation_file().is_none());
+        assert_eq!(units.write(&dirs).unwrap().len(), 1);
+
+        // a system service needs a user to run as, never taken from the environment
+        assert!(Units::from_args(exe, &args(&["--system", &confaddr])).is_err());
+        assert!(Units::from_args(exe, &args(&["--user", "memo", &confaddr])).is_err());
+        assert!(Units::from_args(exe, &args(&["--system", "--user", "me two", &confaddr])).is_err());
+        assert!(Units::from_args(exe, &args(&["--system", "--user"])).is_err());
+        let units = Units::from_args(exe, &args(&["--system", "--user", "memo", &confaddr])).unwrap();
+        assert_eq!(units.exec, format!("/usr/bin/memoserv --system {confaddr}"));
+        assert_eq!(units.dirs().unwrap().policy, Some(PathBuf::from("/usr/share/dbus-1/system.d")));
+        assert!(units.unit_file().contains("WantedBy=multi-user.target"));
+        assert!(units.unit_file().contains("\nUser=memo\nExecStart="));
+        assert!(units.activation_file().unwrap().contains("\nUser=memo\n"));
+        let policy = units.policy_file().unwrap();
+        assert!(policy.contains("<policy user=\"memo\">\n    <allow own=\"org.memobook.house\"/>"));
+        assert!(policy.contains("<allow send_destination=\"org.memobook.house\"/>"));
+        let written = units.write(&dirs).unwrap();
+        assert_eq!(written[2], dir.join("system.d/org.memobook.house.conf"));
+        assert_eq!(fs::read_to_string(&written[2]).unwrap(), policy);
+
+        assert!(Units::from_args(exe, &args(&["--print"])).is_err());
+        assert!(Units::from_args(exe, &args(&[dir.join("missing.json").to_str().unwrap()])).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+}